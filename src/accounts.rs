@@ -0,0 +1,202 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module keeps the named admin accounts and their roles. The key is the built-in superuser
+// account, the rest of the accounts come from the config or are added by a superuser.
+
+extern crate serde_json;
+
+use std::fmt;
+use std::net;
+use crate::secrets;
+
+// The account of the key, it can't be removed nor demoted
+pub const BUILTIN_ADMIN: &str = "admin";
+pub const MAX_ACCOUNT_NAME_LEN: usize = 32;
+
+// Every role can do what the roles below it can do
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum AdminRole {
+    // Reads the clients, the reports, the bans and the running configuration
+    ReadOnly,
+    // Drops clients, manages the votes, the immunities, the reports and the bans
+    Operator,
+    // Changes the settings, the secrets, the sessions and the accounts
+    Superuser
+}
+
+impl AdminRole {
+    pub fn new_from_str(role: &str) -> Option<AdminRole> {
+        match role.to_lowercase().as_str() {
+            "read-only" | "read_only" | "readonly" => Some(AdminRole::ReadOnly),
+            "operator" => Some(AdminRole::Operator),
+            "superuser" => Some(AdminRole::Superuser),
+            _ => None
+        }
+    }
+
+    pub fn permits(&self, required: AdminRole) -> bool {
+        *self >= required
+    }
+}
+
+impl fmt::Display for AdminRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminRole::ReadOnly => write!(f, "read-only"),
+            AdminRole::Operator => write!(f, "operator"),
+            AdminRole::Superuser => write!(f, "superuser")
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Account {
+    pub name: String,
+    pub role: AdminRole,
+    secret: secrets::Secret
+}
+
+impl Account {
+    pub fn new(name: &str, role: AdminRole, secret: secrets::Secret) -> Account {
+        Account { name: String::from(name), role, secret }
+    }
+
+    // Parses the --admin-account values, name:role:secret, the secret can be hashed
+    pub fn new_from_str(account: &str) -> Option<Account> {
+        let mut parts = account.splitn(3, ':');
+        let name = parts.next()?;
+        let role = AdminRole::new_from_str(parts.next()?)?;
+        let secret = parts.next()?;
+        if !is_valid_account_name(name) || name == BUILTIN_ADMIN || !secrets::is_valid_secret(secret) {
+            return None;
+        }
+        Some(Account::new(name, role, secrets::Secret::new_from_str(secret)?))
+    }
+
+    pub fn secret(&self) -> &secrets::Secret {
+        &self.secret
+    }
+
+    // The secret is never included
+    pub fn to_json_string(&self) -> String {
+        format!("{{\"name\":\"{}\",\"role\":\"{}\"}}", self.name, self.role)
+    }
+
+    // Only the snapshot has the secret, and only its hash
+    pub fn to_snapshot_json_string(&self) -> String {
+        format!("{{\"name\":\"{}\",\"role\":\"{}\",\"secret\":\"{}\"}}", self.name, self.role, self.secret.to_hash_string())
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<Account> {
+        let name = val.get("name")?.as_str()?;
+        let role = AdminRole::new_from_str(val.get("role")?.as_str()?)?;
+        if !is_valid_account_name(name) || name == BUILTIN_ADMIN {
+            return None;
+        }
+        Some(Account::new(name, role, secrets::Secret::from_hash_str(val.get("secret")?.as_str()?)?))
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.role)
+    }
+}
+
+pub struct AccountStore {
+    accounts: Vec<Account>
+}
+
+impl AccountStore {
+    pub fn new() -> AccountStore {
+        AccountStore { accounts: Vec::new() }
+    }
+
+    // Returns false if there is already an account with that name
+    pub fn add(&mut self, account: Account) -> bool {
+        if account.name == BUILTIN_ADMIN || self.get(&account.name).is_some() {
+            return false;
+        }
+        self.accounts.push(account);
+        true
+    }
+
+    // The accounts of the config replace the ones restored from the snapshot
+    pub fn replace(&mut self, account: Account) -> bool {
+        if account.name == BUILTIN_ADMIN {
+            return false;
+        }
+        self.accounts.retain(|a| a.name != account.name);
+        self.accounts.push(account);
+        true
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Account> {
+        let index = self.accounts.iter().position(|a| a.name == name)?;
+        Some(self.accounts.remove(index))
+    }
+
+    pub fn set_role(&mut self, name: &str, role: AdminRole) -> bool {
+        if let Some(account) = self.accounts.iter_mut().find(|a| a.name == name) {
+            account.role = role;
+            return true;
+        }
+        false
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Account> {
+        self.accounts.iter().find(|a| a.name == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn to_json_string(&self) -> String {
+        format!("[{}]", self.accounts.iter().map(|a| a.to_json_string()).collect::<Vec<String>>().join(","))
+    }
+
+    pub fn to_snapshot_json_string(&self) -> String {
+        format!("[{}]", self.accounts.iter().map(|a| a.to_snapshot_json_string()).collect::<Vec<String>>().join(","))
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<AccountStore> {
+        let mut account_store = AccountStore::new();
+        for account in val.as_array()? {
+            if !account_store.add(Account::from_json_value(account)?) {
+                return None;
+            }
+        }
+        Some(account_store)
+    }
+}
+
+impl Default for AccountStore {
+    fn default() -> Self {
+        AccountStore::new()
+    }
+}
+
+// The admin that makes a request, it's what the logs show instead of just the socket address
+pub struct ActingAdmin {
+    pub name: String,
+    pub role: AdminRole,
+    pub peer_addr: net::SocketAddr,
+    // The session of the token that was sent instead of the secret
+    pub session: Option<u64>
+}
+
+impl fmt::Display for ActingAdmin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.peer_addr)
+    }
+}
+
+// The names go in the logs and in the replies, so they are kept short and simple
+pub fn is_valid_account_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_ACCOUNT_NAME_LEN && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
@@ -0,0 +1,142 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module writes the admin actions to an append-only audit log. Every entry carries the
+// HMAC-SHA256 of the previous one, keyed with a secret kept out of the log, so changing or removing
+// an entry breaks the chain from there on and only who has the key can make a new chain.
+
+extern crate ring;
+extern crate serde_json;
+
+use std::fs;
+use std::io::{
+    self,
+    Write
+};
+use std::net;
+use ring::hmac;
+use crate::accounts;
+use crate::auth;
+use crate::secrets;
+
+// The previous hash of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+// Every entry is a line that ends with the hash of the rest of the line
+const HASH_FIELD: &str = ",\"hash\":\"";
+// The audit keys shorter than this are too easy to guess
+const MIN_KEY_LEN: usize = 16;
+
+pub struct AuditLog {
+    path: String,
+    file: fs::File,
+    key: hmac::Key,
+    next_seq: u64,
+    last_hash: String
+}
+
+impl AuditLog {
+    // The chain goes on from the last entry of the file, the whole file is verified first so a
+    // tampered log is never extended
+    pub fn open(path: &str, key: hmac::Key) -> io::Result<AuditLog> {
+        let (next_seq, last_hash) = match fs::read_to_string(path) {
+            Ok(contents) => {
+                let (entries, last_hash) = verify_str(&contents, &key).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("the log was tampered, {}", e)))?;
+                (entries + 1, last_hash)
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (1, String::from(GENESIS_HASH)),
+            Err(e) => return Err(e)
+        };
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { path: String::from(path), file, key, next_seq, last_hash })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn entries(&self) -> u64 {
+        self.next_seq - 1
+    }
+
+    // The action is the request without its secrets and the result is the reply as it was sent
+    pub fn record(&mut self, time: u64, admin: &accounts::ActingAdmin, action: &str, result: &str) -> io::Result<()> {
+        let session = match admin.session {
+            Some(session) => session.to_string(),
+            None => String::from("null")
+        };
+        let who = format!("\"admin\":\"{}\",\"role\":\"{}\",\"session\":{},\"peer_addr\":\"{}\"", admin.name, admin.role, session, admin.peer_addr);
+        self.append(time, &who, action, result)
+    }
+
+    // The admin requests refused before the admin proved who it is, the account is the one the
+    // request named
+    pub fn record_refused(&mut self, time: u64, account: &str, peer_addr: &net::SocketAddr, action: &str, result: &str) -> io::Result<()> {
+        let who = format!("\"admin\":\"{}\",\"role\":null,\"session\":null,\"peer_addr\":\"{}\"", account, peer_addr);
+        self.append(time, &who, action, result)
+    }
+
+    fn append(&mut self, time: u64, who: &str, action: &str, result: &str) -> io::Result<()> {
+        let mut result = match serde_json::from_str::<serde_json::Value>(result) {
+            Ok(result) => result,
+            Err(_) => serde_json::Value::String(String::from(result))
+        };
+        // Whoever reads the log must not be able to use the sessions nor the invite codes
+        if let Some(result) = result.as_object_mut() {
+            result.remove("token");
+            result.remove("code");
+        }
+        let body = format!("{{\"seq\":{},\"time\":{},{},\"action\":{},\"result\":{},\"prev\":\"{}\"",
+            self.next_seq, time, who, serde_json::Value::String(String::from(action)), result, self.last_hash);
+        let hash = entry_hash(&self.key, &body);
+        self.file.write_all(format!("{}{}{}\"}}\n", body, HASH_FIELD, hash).as_bytes())?;
+        self.file.flush()?;
+        self.next_seq += 1;
+        self.last_hash = hash;
+        Ok(())
+    }
+}
+
+fn entry_hash(key: &hmac::Key, body: &str) -> String {
+    secrets::to_hex(hmac::sign(key, body.as_bytes()).as_ref())
+}
+
+// The key is the content of the file, without the surrounding whitespace. It must not be kept next
+// to the log, whoever can rewrite the log and read the key can make a new chain
+pub fn read_key(path: &str) -> Result<hmac::Key, String> {
+    let key = fs::read_to_string(path).map_err(|e| format!("I couldn't read the audit key {}: {}", path, e))?;
+    let key = key.trim();
+    if key.len() < MIN_KEY_LEN {
+        return Err(format!("The audit key {} must have at least {} characters", path, MIN_KEY_LEN));
+    }
+    Ok(hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes()))
+}
+
+pub fn verify(path: &str, key: &hmac::Key) -> Result<u64, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("I couldn't read {}: {}", path, e))?;
+    verify_str(&contents, key).map(|(entries, _last_hash)| entries)
+}
+
+// Returns how many entries there are and the hash of the last one. The entries removed from the
+// end of the log can't be detected by the chain, compare the number of entries with a copy
+pub fn verify_str(contents: &str, key: &hmac::Key) -> Result<(u64, String), String> {
+    let mut last_hash = String::from(GENESIS_HASH);
+    let mut entries: u64 = 0;
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let split = line.rfind(HASH_FIELD).ok_or_else(|| format!("the line {} is not an audit entry", line_number))?;
+        let (body, hash) = (&line[..split], &line[split + HASH_FIELD.len()..]);
+        let hash = hash.strip_suffix("\"}").ok_or_else(|| format!("the line {} is not an audit entry", line_number))?;
+        if !auth::constant_time_eq(&entry_hash(key, body), hash) {
+            return Err(format!("the entry of the line {} doesn't match its hash", line_number));
+        }
+        let entry = serde_json::from_str::<serde_json::Value>(line).map_err(|_| format!("the line {} is not an audit entry", line_number))?;
+        if entry.get("prev").and_then(|p| p.as_str()) != Some(last_hash.as_str()) {
+            return Err(format!("the entry of the line {} doesn't follow the previous entry", line_number));
+        }
+        if entry.get("seq").and_then(|s| s.as_u64()) != Some(entries + 1) {
+            return Err(format!("the entry of the line {} is out of sequence", line_number));
+        }
+        entries += 1;
+        last_hash = String::from(hash);
+    }
+    Ok((entries, last_hash))
+}
@@ -0,0 +1,209 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module checks the secrets of the requests, locks out the IPs that keep guessing them and
+// issues the nonces of the challenge-response authentication.
+
+extern crate ring;
+
+use std::collections;
+use std::fmt;
+use std::net;
+use std::time;
+use ring::hmac;
+use ring::rand;
+use ring::rand::SecureRandom;
+use crate::secrets;
+
+// The lockouts double with every failure but never last more than this
+const MAX_LOCKOUT: time::Duration = time::Duration::from_secs(3600);
+// When there are more entries than this, the ones that are not locked out are forgotten
+const MAX_ENTRIES: usize = 4096;
+// A nonce must be answered within this time, and only once
+pub const NONCE_TTL: time::Duration = time::Duration::from_secs(30);
+const NONCE_LEN: usize = 16;
+// When there are more nonces than this, the oldest ones are forgotten
+const MAX_NONCES: usize = 4096;
+
+// Compares every byte even after the first difference, so the time it takes doesn't tell
+// how much of the secret was guessed
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    constant_time_eq_bytes(a.as_bytes(), b.as_bytes())
+}
+
+pub fn constant_time_eq_bytes(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = a.len() ^ b.len();
+    for i in 0..a.len().max(b.len()) {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        diff |= usize::from(x ^ y);
+    }
+    diff == 0
+}
+
+// The admin key and the client password are guessed separately, knowing the client
+// password must not clear the failures with the admin key
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SecretKind {
+    AdminKey,
+    ClientPassword
+}
+
+impl fmt::Display for SecretKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretKind::AdminKey => write!(f, "key"),
+            SecretKind::ClientPassword => write!(f, "password")
+        }
+    }
+}
+
+struct FailedAuth {
+    failures: u32,
+    last_failure: time::Instant,
+    locked_until: Option<time::Instant>
+}
+
+pub struct AuthGuard {
+    // 0 disables the lockouts
+    max_failures: u32,
+    base_lockout: time::Duration,
+    failed: collections::HashMap<(net::IpAddr, SecretKind), FailedAuth>
+}
+
+impl AuthGuard {
+    pub fn new(max_failures: u32, base_lockout: time::Duration) -> AuthGuard {
+        AuthGuard { max_failures, base_lockout, failed: collections::HashMap::new() }
+    }
+
+    pub fn max_failures(&self) -> u32 {
+        self.max_failures
+    }
+
+    pub fn base_lockout(&self) -> time::Duration {
+        self.base_lockout
+    }
+
+    // Returns how many seconds the IP is still locked out
+    pub fn locked_out(&self, ip: &net::IpAddr, kind: SecretKind, now: time::Instant) -> Option<u64> {
+        let locked_until = self.failed.get(&(*ip, kind))?.locked_until?;
+        if locked_until > now {
+            return Some(seconds_until(locked_until, now));
+        }
+        None
+    }
+
+    // Returns how many seconds the IP is locked out because of this failure
+    pub fn record_failure(&mut self, ip: &net::IpAddr, kind: SecretKind, now: time::Instant) -> Option<u64> {
+        if self.max_failures == 0 {
+            return None;
+        }
+        if self.failed.len() > MAX_ENTRIES {
+            self.forget_old_failures(now);
+        }
+        let failed = self.failed.entry((*ip, kind)).or_insert(FailedAuth { failures: 0, last_failure: now, locked_until: None });
+        // The failures are forgotten once the IP stops failing for the longest lockout
+        if now.saturating_duration_since(failed.last_failure) > MAX_LOCKOUT {
+            failed.failures = 0;
+        }
+        failed.failures = failed.failures.saturating_add(1);
+        failed.last_failure = now;
+        if failed.failures < self.max_failures {
+            return None;
+        }
+
+        // The first lockout lasts base_lockout, every failure after it doubles it
+        let exponent = (failed.failures - self.max_failures).min(16);
+        let lockout = self.base_lockout.checked_mul(1 << exponent).unwrap_or(MAX_LOCKOUT).min(MAX_LOCKOUT);
+        let locked_until = now + lockout;
+        failed.locked_until = Some(locked_until);
+        Some(seconds_until(locked_until, now))
+    }
+
+    pub fn record_success(&mut self, ip: &net::IpAddr, kind: SecretKind) {
+        self.failed.remove(&(*ip, kind));
+    }
+
+    fn forget_old_failures(&mut self, now: time::Instant) {
+        self.failed.retain(|_key, failed| {
+            failed.locked_until.map(|until| until > now).unwrap_or(false) || now.saturating_duration_since(failed.last_failure) <= MAX_LOCKOUT
+        });
+    }
+
+    pub fn lockouts_to_json_string(&self, now: time::Instant) -> String {
+        let lockouts = self.failed.iter().filter_map(|((ip, kind), failed)| {
+            let locked_until = failed.locked_until?;
+            if locked_until <= now {
+                return None;
+            }
+            Some(format!("{{\"ip_addr\":\"{}\",\"secret\":\"{}\",\"failures\":{},\"retry_after\":{}}}", ip, kind, failed.failures, seconds_until(locked_until, now)))
+        }).collect::<Vec<String>>().join(",");
+        format!("[{}]", lockouts)
+    }
+}
+
+fn seconds_until(until: time::Instant, now: time::Instant) -> u64 {
+    let remaining = until.saturating_duration_since(now);
+    let seconds = remaining.as_secs();
+    if remaining.subsec_nanos() > 0 {
+        return seconds + 1;
+    }
+    seconds
+}
+
+struct IssuedNonce {
+    ip: net::IpAddr,
+    kind: SecretKind,
+    issued_at: time::Instant
+}
+
+// The nonces are bound to the IP that asked for them and to the secret they are answered with
+pub struct ChallengeStore {
+    nonces: collections::HashMap<String, IssuedNonce>,
+    rng: rand::SystemRandom,
+    // Makes up the salts of the accounts that don't exist
+    unknown_salt_key: Option<hmac::Key>
+}
+
+impl ChallengeStore {
+    pub fn new() -> ChallengeStore {
+        let rng = rand::SystemRandom::new();
+        let unknown_salt_key = hmac::Key::generate(hmac::HMAC_SHA256, &rng).ok();
+        ChallengeStore { nonces: collections::HashMap::new(), rng, unknown_salt_key }
+    }
+
+    // An account that doesn't exist always gets the same salt, made up from its name, so the
+    // challenges don't tell which accounts exist nor give out the salt of another secret
+    pub fn unknown_account_salt(&self, account: &str) -> Option<String> {
+        let tag = hmac::sign(self.unknown_salt_key.as_ref()?, account.as_bytes());
+        Some(secrets::to_hex(&tag.as_ref()[..secrets::SALT_LEN]))
+    }
+
+    pub fn issue(&mut self, ip: &net::IpAddr, kind: SecretKind, now: time::Instant) -> Option<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).ok()?;
+        let nonce = secrets::to_hex(&nonce);
+        if self.nonces.len() >= MAX_NONCES {
+            self.nonces.retain(|_nonce, issued| now.saturating_duration_since(issued.issued_at) < NONCE_TTL);
+            if self.nonces.len() >= MAX_NONCES {
+                let oldest = self.nonces.iter().min_by_key(|(_nonce, issued)| issued.issued_at).map(|(nonce, _issued)| nonce.clone())?;
+                self.nonces.remove(&oldest);
+            }
+        }
+        self.nonces.insert(nonce.clone(), IssuedNonce { ip: *ip, kind, issued_at: now });
+        Some(nonce)
+    }
+
+    // The nonce is used up even if it doesn't belong to the IP or it expired
+    pub fn take(&mut self, nonce: &str, ip: &net::IpAddr, kind: SecretKind, now: time::Instant) -> bool {
+        match self.nonces.remove(nonce) {
+            Some(issued) => issued.ip == *ip && issued.kind == kind && now.saturating_duration_since(issued.issued_at) < NONCE_TTL,
+            None => false
+        }
+    }
+}
+
+impl Default for ChallengeStore {
+    fn default() -> Self {
+        ChallengeStore::new()
+    }
+}
@@ -0,0 +1,166 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module keeps the IPs, networks and MAC addresses that the admins banned, the banned
+// peers can't make requests nor sign up until the ban expires or an admin lifts it.
+
+extern crate serde_json;
+
+use std::fmt;
+use std::net;
+use crate::clients;
+use crate::ipparser;
+
+// The reason of a ban is cut to this amount of characters
+pub const MAX_BAN_REASON_LEN: usize = 280;
+
+#[derive(Clone, PartialEq)]
+pub enum BanTarget {
+    // A single IP is a network with the longest prefix
+    Network(ipparser::Cidr),
+    Mac(ipparser::MacAddress)
+}
+
+impl BanTarget {
+    pub fn matches(&self, mac: Option<&ipparser::MacAddress>, ip: &net::IpAddr) -> bool {
+        match self {
+            BanTarget::Network(cidr) => cidr.contains(ip),
+            BanTarget::Mac(banned_mac) => mac == Some(banned_mac)
+        }
+    }
+
+    pub fn to_json_string(&self) -> String {
+        match self {
+            BanTarget::Network(cidr) => format!("\"network\":\"{}\"", cidr),
+            BanTarget::Mac(mac) => format!("\"mac\":\"{}\"", mac)
+        }
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<BanTarget> {
+        if let Some(network) = val.get("network") {
+            return Some(BanTarget::Network(ipparser::Cidr::new_from_str(network.as_str()?)?));
+        }
+        Some(BanTarget::Mac(ipparser::MacAddress::new_from_str(val.get("mac")?.as_str()?)?))
+    }
+}
+
+impl fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanTarget::Network(cidr) => write!(f, "{}", cidr),
+            BanTarget::Mac(mac) => write!(f, "{}", mac)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Ban {
+    pub id: u64,
+    pub target: BanTarget,
+    // Seconds since the UNIX epoch, None means that the ban is permanent
+    pub until: Option<u64>,
+    pub reason: String
+}
+
+impl Ban {
+    pub fn is_active(&self, now: u64) -> bool {
+        match self.until {
+            Some(until) => now < until,
+            None => true
+        }
+    }
+
+    pub fn to_json_string(&self) -> String {
+        // The reason is free text, serde_json escapes it
+        let reason = serde_json::Value::String(self.reason.clone());
+        let until = match self.until {
+            Some(until) => until.to_string(),
+            None => String::from("null")
+        };
+        format!("{{\"id\":{},{},\"until\":{},\"reason\":{}}}", self.id, self.target.to_json_string(), until, reason)
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<Ban> {
+        let id = val.get("id")?.as_u64()?;
+        let target = BanTarget::from_json_value(val)?;
+        let until = match val.get("until")? {
+            serde_json::Value::Null => None,
+            until => Some(until.as_u64()?)
+        };
+        let reason = String::from(val.get("reason")?.as_str()?);
+        Some(Ban { id, target, until, reason })
+    }
+}
+
+pub struct BanList {
+    next_id: u64,
+    bans: Vec<Ban>
+}
+
+impl BanList {
+    pub fn new() -> BanList {
+        BanList { next_id: 1, bans: Vec::new() }
+    }
+
+    // Banning the same target again replaces the old ban
+    pub fn ban(&mut self, target: BanTarget, until: Option<u64>, reason: &str) -> u64 {
+        self.bans.retain(|b| b.target != target);
+        let id = self.next_id;
+        self.next_id += 1;
+        let reason: String = reason.chars().take(MAX_BAN_REASON_LEN).collect();
+        self.bans.push(Ban { id, target, until, reason });
+        id
+    }
+
+    pub fn lift(&mut self, id: u64) -> Option<Ban> {
+        let index = self.bans.iter().position(|b| b.id == id)?;
+        Some(self.bans.remove(index))
+    }
+
+    pub fn banned_ip(&self, ip: &net::IpAddr) -> Option<&Ban> {
+        let now = clients::unix_now();
+        self.bans.iter().find(|b| b.is_active(now) && b.target.matches(None, ip))
+    }
+
+    pub fn banned_client(&self, mac: &ipparser::MacAddress, ip: &net::IpAddr) -> Option<&Ban> {
+        let now = clients::unix_now();
+        self.bans.iter().find(|b| b.is_active(now) && b.target.matches(Some(mac), ip))
+    }
+
+    // Returns how many bans expired
+    pub fn expire(&mut self) -> usize {
+        let now = clients::unix_now();
+        let bans_before = self.bans.len();
+        self.bans.retain(|b| b.is_active(now));
+        bans_before - self.bans.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bans.is_empty()
+    }
+
+    pub fn to_json_string(&self) -> String {
+        format!("[{}]", self.bans.iter().map(|b| b.to_json_string()).collect::<Vec<String>>().join(","))
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<BanList> {
+        let mut ban_list = BanList::new();
+        for ban in val.as_array()? {
+            let ban = Ban::from_json_value(ban)?;
+            if ban.id >= ban_list.next_id {
+                ban_list.next_id = ban.id + 1;
+            }
+            ban_list.bans.push(ban);
+        }
+        Some(ban_list)
+    }
+}
+
+impl Default for BanList {
+    fn default() -> Self {
+        BanList::new()
+    }
+}
@@ -5,25 +5,174 @@
 
 extern crate serde_json;
 extern crate regex;
+extern crate ring;
 
 use std::collections;
 use std::fmt;
 use std::cmp;
+use std::net;
 use crate::ipparser;
+use crate::auth;
 use std::convert::TryFrom;
+use std::time;
+use ring::digest;
+use ring::rand;
+use ring::rand::SecureRandom;
+
+// Seconds since the UNIX epoch, the drop votes are stamped with it
+pub fn unix_now() -> u64 {
+    time::SystemTime::now().duration_since(time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Who voted to drop out a client
+#[derive(PartialEq, Eq, Clone)]
+pub struct DropVote {
+    pub voter_ip: net::IpAddr,
+    // Only known when the voter is signed up
+    pub voter_mac: Option<ipparser::MacAddress>,
+    pub cast_at: u64
+}
+
+impl DropVote {
+    pub fn is_from(&self, voter: &DropVote) -> bool {
+        self.voter_ip == voter.voter_ip || (self.voter_mac.is_some() && self.voter_mac == voter.voter_mac)
+    }
+
+    pub fn to_json_string(&self) -> String {
+        match &self.voter_mac {
+            Some(mac) => format!("{{\"ip_addr\":\"{}\",\"mac\":\"{}\",\"cast_at\":{}}}", self.voter_ip, mac, self.cast_at),
+            None => format!("{{\"ip_addr\":\"{}\",\"mac\":null,\"cast_at\":{}}}", self.voter_ip, self.cast_at)
+        }
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<DropVote> {
+        let voter_ip = ipparser::parse_ip(val.get("ip_addr")?.as_str()?).ok()?;
+        let voter_mac = match val.get("mac") {
+            Some(serde_json::Value::String(mac)) => Some(ipparser::MacAddress::new_from_str(mac)?),
+            Some(serde_json::Value::Null) | None => None,
+            Some(_) => return None
+        };
+        // Votes saved without a timestamp start counting from now
+        let cast_at = match val.get("cast_at") {
+            Some(cast_at) => cast_at.as_u64()?,
+            None => unix_now()
+        };
+        Some(DropVote { voter_ip, voter_mac, cast_at })
+    }
+
+    pub fn has_expired(&self, now: u64, vote_expiry: u64) -> bool {
+        vote_expiry > 0 && self.cast_at.saturating_add(vote_expiry) <= now
+    }
+}
+
+// Immune clients can't be dropped out by the votes of the others, only by an admin
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Immunity {
+    NotImmune,
+    Until(u64),
+    Permanent
+}
+
+impl Immunity {
+    pub fn is_active(&self, now: u64) -> bool {
+        match self {
+            Immunity::NotImmune => false,
+            Immunity::Until(until) => now < *until,
+            Immunity::Permanent => true
+        }
+    }
+
+    pub fn to_json_string(&self) -> String {
+        match self {
+            Immunity::NotImmune => String::from("null"),
+            Immunity::Until(until) => until.to_string(),
+            Immunity::Permanent => String::from("\"permanent\"")
+        }
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<Immunity> {
+        match val {
+            serde_json::Value::Null => Some(Immunity::NotImmune),
+            serde_json::Value::String(s) if s == "permanent" => Some(Immunity::Permanent),
+            serde_json::Value::Number(n) => Some(Immunity::Until(n.as_u64()?)),
+            _ => None
+        }
+    }
+}
+
+const OWNERSHIP_TOKEN_LEN: usize = 32;
+
+// The sign up gives the client a secret token, while its lease lasts only that token can change
+// the record of the MAC, so nobody else on the LAN can take it over. With a lease of 0 there is
+// no lease, like before the ownership tokens
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Ownership {
+    // Only the SHA-256 of the token is kept, in hex
+    pub token_digest: String,
+    // Seconds since the UNIX epoch
+    pub lease_until: u64
+}
+
+impl Ownership {
+    // Returns the ownership and the token that proves it
+    pub fn issue(now: u64, lease: u64) -> Option<(Ownership, String)> {
+        let mut token = [0u8; OWNERSHIP_TOKEN_LEN];
+        rand::SystemRandom::new().fill(&mut token).ok()?;
+        let token = to_hex(&token);
+        Some((Ownership { token_digest: Ownership::digest(&token), lease_until: now.saturating_add(lease) }, token))
+    }
+
+    pub fn is_leased(&self, now: u64) -> bool {
+        now < self.lease_until
+    }
+
+    pub fn is_owned_by(&self, token: &str) -> bool {
+        auth::constant_time_eq(&Ownership::digest(token), &self.token_digest)
+    }
+
+    pub fn renew(&mut self, now: u64, lease: u64) {
+        self.lease_until = now.saturating_add(lease);
+    }
+
+    fn digest(token: &str) -> String {
+        to_hex(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+    }
+
+    pub fn to_json_string(&self) -> String {
+        format!("{{\"token_digest\":\"{}\",\"lease_until\":{}}}", self.token_digest, self.lease_until)
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<Ownership> {
+        let token_digest = String::from(val.get("token_digest")?.as_str()?);
+        let lease_until = val.get("lease_until")?.as_u64()?;
+        Some(Ownership { token_digest, lease_until })
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
 #[derive(Eq, Clone)]
 pub struct Client {
-    pub ipv4_addr: u32,
+    pub ip_addr: net::IpAddr,
+    // Only link-local IPv6 clients have a scope id, it's 0 for everyone else
+    pub scope_id: u32,
     pub port: u16,
     pub username: String,
     pub get_only_by_mac: bool,
-    pub drop_votes: u8
+    pub drop_votes: u16,
+    pub drop_voters: Vec<DropVote>,
+    pub immunity: Immunity,
+    // The clients signed up before the ownership tokens have no owner
+    pub owner: Option<Ownership>,
+    // The id of the invite that admitted the client, None if it signed up with the password
+    pub invite: Option<u64>
 }
 
 impl cmp::Ord for Client {
     fn cmp(&self, other: &Self) -> cmp::Ordering {
-        self.ipv4_addr.cmp(&other.ipv4_addr)
+        self.ip_addr.cmp(&other.ip_addr)
     }
 }
 
@@ -35,16 +184,16 @@ impl cmp::PartialOrd for Client {
 
 impl cmp::PartialEq for Client {
     fn eq(&self, other: &Self) -> bool {
-        self.ipv4_addr == other.ipv4_addr
+        self.ip_addr == other.ip_addr
     }
 }
 
 impl fmt::Display for Client {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.get_only_by_mac {
-            write!(f, "{} {} MAC-ONLY PORT: {} DROP-VOTES: {}", self.username, ipparser::u32_to_ipv4(self.ipv4_addr), self.port, self.drop_votes)
+            write!(f, "{} {} MAC-ONLY PORT: {} DROP-VOTES: {}", self.username, ipparser::scoped_ip_to_string(&self.ip_addr, self.scope_id), self.port, self.drop_votes)
         } else {
-            write!(f, "{} {} PORT: {} DROP-VOTES: {}", self.username, ipparser::u32_to_ipv4(self.ipv4_addr), self.port, self.drop_votes)
+            write!(f, "{} {} PORT: {} DROP-VOTES: {}", self.username, ipparser::scoped_ip_to_string(&self.ip_addr, self.scope_id), self.port, self.drop_votes)
         }
     }
 }
@@ -52,32 +201,39 @@ impl fmt::Display for Client {
 impl fmt::Debug for Client {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.get_only_by_mac {
-            write!(f, "{} {} MAC-ONLY PORT: {} DROP-VOTES: {}", self.username, ipparser::u32_to_ipv4(self.ipv4_addr), self.port, self.drop_votes)
+            write!(f, "{} {} MAC-ONLY PORT: {} DROP-VOTES: {}", self.username, ipparser::scoped_ip_to_string(&self.ip_addr, self.scope_id), self.port, self.drop_votes)
         } else {
-            write!(f, "{} {} PORT: {} DROP-VOTES: {}", self.username, ipparser::u32_to_ipv4(self.ipv4_addr), self.port, self.drop_votes)
+            write!(f, "{} {} PORT: {} DROP-VOTES: {}", self.username, ipparser::scoped_ip_to_string(&self.ip_addr, self.scope_id), self.port, self.drop_votes)
         }
     }
 }
 
 impl Client {
 
-    pub fn new(ipv4_addr: u32, port: u16, username: &str, get_only_by_mac: bool, drop_votes: u8) -> Option<Client> {
+    pub fn new(ip_addr: net::IpAddr, port: u16, username: &str, get_only_by_mac: bool, drop_votes: u16) -> Option<Client> {
         if Client::is_valid_username(username) {
-            return Some(Client { ipv4_addr, port, username: String::from(username), get_only_by_mac, drop_votes });
+            return Some(Client { ip_addr, scope_id: 0, port, username: String::from(username), get_only_by_mac, drop_votes, drop_voters: Vec::new(), immunity: Immunity::NotImmune, owner: None, invite: None });
         }
         None
     }
 
-    pub fn get_ipv4_addr(&self) -> u32 {
-        self.ipv4_addr
+    pub fn get_ip_addr(&self) -> net::IpAddr {
+        self.ip_addr
+    }
+
+    pub fn socket_addr(&self) -> net::SocketAddr {
+        match self.ip_addr {
+            net::IpAddr::V4(ipv4) => net::SocketAddr::V4(net::SocketAddrV4::new(ipv4, self.port)),
+            net::IpAddr::V6(ipv6) => net::SocketAddr::V6(net::SocketAddrV6::new(ipv6, self.port, 0, self.scope_id))
+        }
     }
 
-    pub fn set_drop_votes(&mut self, dv: u8) {
+    pub fn set_drop_votes(&mut self, dv: u16) {
         self.drop_votes = dv;        
     }
 
-    pub fn add_drop_votes(&mut self, dv: u8) -> u8 {
-        self.drop_votes += dv;
+    pub fn add_drop_votes(&mut self, dv: u16) -> u16 {
+        self.drop_votes = self.drop_votes.saturating_add(dv);
         self.drop_votes
     }
 
@@ -97,6 +253,40 @@ impl Client {
         self.username.to_lowercase().contains(&pattern.to_lowercase())
     }
 
+    fn ip_addr_from_json_value(val: &serde_json::Value) -> Option<(net::IpAddr, u32)> {
+        // Can be an string ("192.168.1.70") or a number (8974537)
+        if let Some(addr) = val.get("ipv4_addr") {
+            if let Some(addr) = addr.as_str() {
+                if let Some(addr) = ipparser::str_to_ipv4addr(addr) {
+                    return Some((net::IpAddr::V4(addr), 0));
+                }
+            } else if let Some(addr) = addr.as_u64() {
+                if let Ok(addr) = u32::try_from(addr) {
+                    return Some((net::IpAddr::V4(ipparser::u32_to_ipv4(addr)), 0));
+                }
+            }
+            return None;
+        }
+
+        // Can be an string ("fe80::1%2" or "2001:db8::1")
+        if let Some(addr) = val.get("ipv6_addr") {
+            if let Some(addr) = addr.as_str() {
+                if let Some((net::IpAddr::V6(addr), scope_id)) = ipparser::parse_scoped_ip(addr) {
+                    return Some((net::IpAddr::V6(addr), scope_id));
+                }
+            }
+        }
+        None
+    }
+
+    // The JSON field that holds the address of the client, IPv4 clients keep the old `ipv4_addr` field
+    fn ip_addr_json_field(&self) -> String {
+        match self.ip_addr {
+            net::IpAddr::V4(ipv4) => format!("\"ipv4_addr\": \"{}\"", ipv4),
+            net::IpAddr::V6(_) => format!("\"ipv6_addr\": \"{}\"", ipparser::scoped_ip_to_string(&self.ip_addr, self.scope_id))
+        }
+    }
+
     pub fn from_json_value_with_no_drop_votes(val: &serde_json::Value) -> Option<Client> {
         // Can be an string
         let username: String;
@@ -106,19 +296,7 @@ impl Client {
             } else { return None; }
         } else { return None; }
         
-        // Can be an string ("192.168.1.70") or a number (8974537)
-        let ipv4_addr: u32;        
-        if let Some(addr) = val.get("ipv4_addr") {
-            if let Some(addr) = addr.as_str() {
-                if let Some(addr) = ipparser::ipv4_to_u32(addr) {
-                    ipv4_addr = addr;
-                } else { return None; }
-            } else if let Some(addr) = addr.as_u64() {
-                if let Ok(addr) = u32::try_from(addr) {
-                    ipv4_addr = addr;
-                } else { return None; }
-            } else { return None; }
-        } else { return None; }
+        let (ip_addr, scope_id) = Client::ip_addr_from_json_value(val)?;
 
         // Can be a number
         let port: u16;
@@ -138,7 +316,7 @@ impl Client {
             } else { return None; }
         } else { return None; }
 
-        Some(Client { ipv4_addr, port, username, get_only_by_mac, drop_votes: 0 })
+        Some(Client { ip_addr, scope_id, port, username, get_only_by_mac, drop_votes: 0, drop_voters: Vec::new(), immunity: Immunity::NotImmune, owner: None, invite: None })
     }
 
     pub fn from_json_value(val: &serde_json::Value) -> Option<Client> {
@@ -150,19 +328,7 @@ impl Client {
             } else { return None; }
         } else { return None; }
         
-        // Can be an string ("192.168.1.70") or a number (8974537)
-        let ipv4_addr: u32;        
-        if let Some(addr) = val.get("ipv4_addr") {
-            if let Some(addr) = addr.as_str() {
-                if let Some(addr) = ipparser::ipv4_to_u32(addr) {
-                    ipv4_addr = addr;
-                } else { return None; }
-            } else if let Some(addr) = addr.as_u64() {
-                if let Ok(addr) = u32::try_from(addr) {
-                    ipv4_addr = addr;
-                } else { return None; }
-            } else { return None; }
-        } else { return None; }
+        let (ip_addr, scope_id) = Client::ip_addr_from_json_value(val)?;
 
         // Can be a number
         let port: u16;
@@ -183,79 +349,149 @@ impl Client {
         } else { return None; }
 
         // Can be a number
-        let drop_votes: u8;
+        let drop_votes: u16;
         if let Some(dv) = val.get("drop_votes") {
             if let Some(dv) = dv.as_u64() {
-                if let Ok(dv) = u8::try_from(dv) {
+                if let Ok(dv) = u16::try_from(dv) {
                     drop_votes = dv;
                 } else { return None; }
             } else { return None; }
         } else { return None; }
 
-        Some(Client { ipv4_addr, port, username, get_only_by_mac, drop_votes })
+        // Can be an array, snapshots written before the voters were recorded don't have it
+        let mut drop_voters: Vec<DropVote> = Vec::new();
+        if let Some(voters) = val.get("drop_voters") {
+            if let Some(voters) = voters.as_array() {
+                for voter in voters {
+                    if let Some(voter) = DropVote::from_json_value(voter) {
+                        drop_voters.push(voter);
+                    } else { return None; }
+                }
+            } else { return None; }
+        }
+
+        // Can be null, "permanent" or a timestamp, older snapshots don't have it
+        let immunity = match val.get("immunity") {
+            Some(immunity) => Immunity::from_json_value(immunity)?,
+            None => Immunity::NotImmune
+        };
+
+        // Can be null or an object, older snapshots don't have it
+        let owner = match val.get("owner") {
+            Some(serde_json::Value::Null) | None => None,
+            Some(owner) => Some(Ownership::from_json_value(owner)?)
+        };
+
+        // Can be null or a number, older snapshots don't have it
+        let invite = match val.get("invite") {
+            Some(serde_json::Value::Null) | None => None,
+            Some(invite) => Some(invite.as_u64()?)
+        };
+
+        Some(Client { ip_addr, scope_id, port, username, get_only_by_mac, drop_votes, drop_voters, immunity, owner, invite })
     }
 
     pub fn to_json_string(&self) -> String {
-        let ipv4_addr = ipparser::u32_to_ipv4(self.ipv4_addr);
+        let ip_addr = self.ip_addr_json_field();
         format!(r#"
             {{
-                "ipv4_addr": "{}",
+                {},
                 "port": {},
                 "username": "{}",
                 "get_only_by_mac": {},
                 "drop_votes": {}
             }}
-        "#, ipv4_addr, self.port, self.username, self.get_only_by_mac, self.drop_votes)
+        "#, ip_addr, self.port, self.username, self.get_only_by_mac, self.drop_votes)
     }
 
     pub fn to_json_string_without_drop_votes(&self) -> String {
-        let ipv4_addr = ipparser::u32_to_ipv4(self.ipv4_addr);
+        let ip_addr = self.ip_addr_json_field();
         format!(r#"
             {{
-                "ipv4_addr": "{}", 
+                {},
                 "port": {},
                 "username": "{}",
                 "get_only_by_mac": {}
             }}
-        "#, ipv4_addr, self.port, self.username, self.get_only_by_mac)
+        "#, ip_addr, self.port, self.username, self.get_only_by_mac)
     }
 
     pub fn to_json_string_without_drop_votes_get_only_by_mac(&self) -> String {
-        let ipv4_addr = ipparser::u32_to_ipv4(self.ipv4_addr);
+        let ip_addr = self.ip_addr_json_field();
         format!(r#"
             {{
-                "ipv4_addr": "{}", 
+                {},
                 "port": {},
                 "username": "{}"
             }}
-        "#, ipv4_addr, self.port, self.username)        
+        "#, ip_addr, self.port, self.username)        
     }
 
     pub fn to_json_string_with_mac_without_drop_votes(&self, mac: &ipparser::MacAddress) -> String {
-        let ipv4_addr = ipparser::u32_to_ipv4(self.ipv4_addr);
+        let ip_addr = self.ip_addr_json_field();
         format!(r#"
             {{
                 "mac": "{}",
-                "ipv4_addr": "{}", 
+                {},
                 "port": {},
                 "username": "{}",
                 "get_only_by_mac": {}
             }}
-        "#, mac, ipv4_addr, self.port, self.username, self.get_only_by_mac)        
+        "#, mac, ip_addr, self.port, self.username, self.get_only_by_mac)        
     }
 
     pub fn to_json_string_with_mac(&self, mac: &ipparser::MacAddress) -> String {
-        let ipv4_addr = ipparser::u32_to_ipv4(self.ipv4_addr);
+        let ip_addr = self.ip_addr_json_field();
+        let drop_voters = self.drop_voters.iter().map(|v| v.to_json_string()).collect::<Vec<String>>().join(",");
+        let owner = match &self.owner {
+            Some(owner) => owner.to_json_string(),
+            None => String::from("null")
+        };
+        let invite = match self.invite {
+            Some(invite) => invite.to_string(),
+            None => String::from("null")
+        };
         format!(r#"
             {{
                 "mac": "{}",
-                "ipv4_addr": "{}",
+                {},
                 "port": {},
                 "username": "{}",
                 "get_only_by_mac": {},
-                "drop_votes": {}
+                "drop_votes": {},
+                "drop_voters": [{}],
+                "immunity": {},
+                "owner": {},
+                "invite": {}
             }}
-        "#, mac, ipv4_addr, self.port, self.username, self.get_only_by_mac, self.drop_votes)        
+        "#, mac, ip_addr, self.port, self.username, self.get_only_by_mac, self.drop_votes, drop_voters, self.immunity.to_json_string(), owner, invite)        
+    }
+
+    pub fn reset_drop_votes(&mut self) {
+        self.drop_votes = 0;
+        self.drop_voters.clear();
+    }
+
+    pub fn is_immune(&self) -> bool {
+        self.immunity.is_active(unix_now())
+    }
+
+    // Returns how many votes expired, the votes cast before they were recorded never expire
+    pub fn expire_drop_votes(&mut self, now: u64, vote_expiry: u64) -> usize {
+        let votes_before = self.drop_voters.len();
+        self.drop_voters.retain(|v| !v.has_expired(now, vote_expiry));
+        let expired = votes_before - self.drop_voters.len();
+        self.drop_votes = self.drop_votes.saturating_sub(u16::try_from(expired).unwrap_or(u16::MAX));
+        expired
+    }
+
+    // Returns how many votes of the voter were removed
+    pub fn remove_drop_votes_from(&mut self, voter: &DropVote) -> usize {
+        let votes_before = self.drop_voters.len();
+        self.drop_voters.retain(|v| !v.is_from(voter));
+        let removed = votes_before - self.drop_voters.len();
+        self.drop_votes = self.drop_votes.saturating_sub(u16::try_from(removed).unwrap_or(u16::MAX));
+        removed
     }
 }
 
@@ -282,6 +518,14 @@ impl fmt::Display for ClientsMap {
     }  
 }
 
+pub enum DropVoteResult {
+    Dropped,
+    Voted,
+    AlreadyVoted,
+    ClientIsImmune,
+    ClientDoesNotExist
+}
+
 pub enum InsertionType {
     Update,
     Replace { client_mac_replaced: ipparser::MacAddress},
@@ -295,13 +539,22 @@ impl ClientsMap {
 
     pub fn insert(&mut self, mac: &ipparser::MacAddress, client: &Client) -> InsertionType {
         if let Some(existing_client) = self.clients.get(&mac) { // MAC exists
-            if *existing_client == *client { // IPv4 also exists
+            // The immunity was granted to the MAC, signing up again keeps it, the same as the
+            // invite that admitted it and the drop votes, only `expire_drop_votes` ages them out
+            let mut client = client.clone();
+            client.immunity = existing_client.immunity;
+            client.drop_votes = existing_client.drop_votes;
+            client.drop_voters = existing_client.drop_voters.clone();
+            if client.invite.is_none() {
+                client.invite = existing_client.invite;
+            }
+            if *existing_client == client { // IPv4 also exists
                 // Do nothing... I think I should do an update here, maybe the client changed his name
-                self.clients.insert(mac.clone(), client.clone());
+                self.clients.insert(mac.clone(), client);
                 return InsertionType::Update;
             } else { // IPv4 does not exist
                 // Do an update
-                self.clients.insert(mac.clone(), client.clone());
+                self.clients.insert(mac.clone(), client);
                 return InsertionType::Update;
             }
         } else { // MAC does not exist            
@@ -314,7 +567,7 @@ impl ClientsMap {
                 self.clients.insert(mac.clone(), client.clone());
                 return InsertionType::Insert;
             }            
-            self.clients.remove(&repl_mac);
+            self.remove(&repl_mac);
             self.clients.insert(mac.clone(), client.clone());
             return InsertionType::Replace { client_mac_replaced: repl_mac };
         }
@@ -324,6 +577,66 @@ impl ClientsMap {
         self.clients.len()
     }
 
+    // Every client that leaves the map must leave through here, so its votes against
+    // the others are forgotten too
+    fn remove(&mut self, mac: &ipparser::MacAddress) -> Option<Client> {
+        let client = self.clients.remove(mac)?;
+        let voter = DropVote { voter_ip: client.ip_addr, voter_mac: Some(mac.clone()), cast_at: 0 };
+        for other_client in self.clients.values_mut() {
+            other_client.remove_drop_votes_from(&voter);
+        }
+        Some(client)
+    }
+
+    // Forgets the votes older than vote_expiry seconds, 0 means that they never expire
+    pub fn expire_drop_votes(&mut self, vote_expiry: u64) -> usize {
+        if vote_expiry == 0 {
+            return 0;
+        }
+        let now = unix_now();
+        self.clients.values_mut().map(|client| client.expire_drop_votes(now, vote_expiry)).sum()
+    }
+
+    pub fn get_mac_by_ip(&self, ip: &net::IpAddr) -> Option<ipparser::MacAddress> {
+        self.clients.iter().find(|(_mac, client)| client.get_ip_addr() == *ip).map(|(mac, _client)| mac.clone())
+    }
+
+    // Expects an array like the one produced by `ClientsMap::to_json_string`
+    pub fn from_json_value(val: &serde_json::Value) -> Option<ClientsMap> {
+        let mut clients_map = ClientsMap::new();
+        if let Some(clients) = val.as_array() {
+            for client_val in clients {
+                let mac;
+                if let Some(m) = client_val.get("mac") {
+                    if let Some(m) = m.as_str() {
+                        if let Some(m) = ipparser::MacAddress::new_from_str(m) {
+                            mac = m;
+                        } else { return None; }
+                    } else { return None; }
+                } else { return None; }
+
+                if let Some(client) = Client::from_json_value(client_val) {
+                    clients_map.insert(&mac, &client);
+                } else { return None; }
+            }
+            return Some(clients_map);
+        }
+        None
+    }
+
+    pub fn to_json_string(&self) -> String {
+        let mut clients_json_array = String::from("[");
+        if !self.clients.is_empty() {
+            for (mac, client) in self.clients.iter() {
+                clients_json_array.push_str(&client.to_json_string_with_mac(mac));
+                clients_json_array.push(',');
+            }
+            clients_json_array.pop();
+        }
+        clients_json_array.push(']');
+        clients_json_array
+    }
+
     pub fn get_by_mac(&self, mac: &ipparser::MacAddress) -> Option<Client> {
         match self.clients.get(mac) {
             Some(client) => Some(client.clone()),
@@ -331,8 +644,44 @@ impl ClientsMap {
         }
     }
 
-    pub fn exists_by_ipv4(&self, ipv4: u32) -> bool {
-        if let Some((_mac_key, _client_value)) = self.clients.iter().find(|(_mac, client)| client.get_ipv4_addr() == ipv4) {
+    pub fn reset_drop_votes_by_ip(&mut self, ip: &net::IpAddr) -> bool {
+        if let Some(client) = self.clients.values_mut().find(|client| client.get_ip_addr() == *ip) {
+            client.reset_drop_votes();
+            return true;
+        }
+        false
+    }
+
+    // Returns how many clients had votes
+    pub fn reset_all_drop_votes(&mut self) -> usize {
+        let mut reset = 0;
+        for client in self.clients.values_mut() {
+            if client.drop_votes > 0 || !client.drop_voters.is_empty() {
+                client.reset_drop_votes();
+                reset += 1;
+            }
+        }
+        reset
+    }
+
+    pub fn set_invite(&mut self, mac: &ipparser::MacAddress, invite: u64) -> bool {
+        if let Some(client) = self.clients.get_mut(mac) {
+            client.invite = Some(invite);
+            return true;
+        }
+        false
+    }
+
+    pub fn set_immunity_by_ip(&mut self, ip: &net::IpAddr, immunity: Immunity) -> Option<(ipparser::MacAddress, Client)> {
+        if let Some((mac, client)) = self.clients.iter_mut().find(|(_mac, client)| client.get_ip_addr() == *ip) {
+            client.immunity = immunity;
+            return Some((mac.clone(), client.clone()));
+        }
+        None
+    }
+
+    pub fn exists_by_ip(&self, ip: &net::IpAddr) -> bool {
+        if let Some((_mac_key, _client_value)) = self.clients.iter().find(|(_mac, client)| client.get_ip_addr() == *ip) {
             return true;
         }
         false
@@ -419,50 +768,74 @@ impl ClientsMap {
         }
     }
 
-    pub fn drop_vote_by_mac(&mut self, mac: &ipparser::MacAddress, drop_votes: u8, max_drop_votes: u8) -> bool {
+    // A voter can vote only once against the same client, it's recognized by its IP or,
+    // if it's signed up, by its MAC
+    pub fn drop_vote_by_mac(&mut self, mac: &ipparser::MacAddress, voter_ip: &net::IpAddr, max_drop_votes: u16) -> DropVoteResult {
+        let voter = DropVote { voter_ip: *voter_ip, voter_mac: self.get_mac_by_ip(voter_ip), cast_at: unix_now() };
         let actual_drop_votes;
         if let Some(client) = self.clients.get_mut(mac) {
-            actual_drop_votes = client.add_drop_votes(drop_votes);
-        } else { return false; }
-        
+            if client.is_immune() {
+                return DropVoteResult::ClientIsImmune;
+            }
+            if client.drop_voters.iter().any(|v| v.is_from(&voter)) {
+                return DropVoteResult::AlreadyVoted;
+            }
+            client.drop_voters.push(voter);
+            actual_drop_votes = client.add_drop_votes(1);
+        } else { return DropVoteResult::ClientDoesNotExist; }
+
         if actual_drop_votes >= max_drop_votes {
-            self.clients.remove(mac);
-            return true;
+            self.remove(mac);
+            return DropVoteResult::Dropped;
         }
-        false
+        DropVoteResult::Voted
     }
 
-    pub fn drop_vote_by_ipv4(&mut self, ipv4: u32, drop_votes: u8, max_drop_votes: u8) -> bool {
-        let mac;
-        if let Some((mac_key, _client_value)) = self.clients.iter().find(|(_mac, client)| client.get_ipv4_addr() == ipv4) {
-            mac = mac_key.clone();
-        } else {  return false; }
-        return self.drop_vote_by_mac(&mac, drop_votes, max_drop_votes);
+    pub fn drop_vote_by_ip(&mut self, ip: &net::IpAddr, voter_ip: &net::IpAddr, max_drop_votes: u16) -> DropVoteResult {
+        match self.get_mac_by_ip(ip) {
+            Some(mac) => self.drop_vote_by_mac(&mac, voter_ip, max_drop_votes),
+            None => DropVoteResult::ClientDoesNotExist
+        }
+    }
+
+    pub fn drop_matching<F: Fn(&ipparser::MacAddress, &Client) -> bool>(&mut self, matches: F) -> Vec<(ipparser::MacAddress, Client)> {
+        let macs: Vec<ipparser::MacAddress> = self.clients.iter().filter(|(mac, client)| matches(mac, client)).map(|(mac, _client)| mac.clone()).collect();
+        let mut dropped: Vec<(ipparser::MacAddress, Client)> = Vec::new();
+        for mac in macs {
+            if let Some(client) = self.remove(&mac) {
+                dropped.push((mac, client));
+            }
+        }
+        dropped
+    }
+
+    pub fn drop_by_mac(&mut self, mac: &ipparser::MacAddress) -> Option<Client> {
+        self.remove(mac)
     }
 
-    pub fn drop_by_ipv4(&mut self, ipv4: u32) -> bool {
+    pub fn drop_by_ip(&mut self, ip: &net::IpAddr) -> bool {
         let mac;
-        if let Some((mac_k, _client)) = self.clients.iter().find(|(_mac, client)| client.get_ipv4_addr() == ipv4) {
+        if let Some((mac_k, _client)) = self.clients.iter().find(|(_mac, client)| client.get_ip_addr() == *ip) {
             mac = mac_k.clone();
         } else { return false; }
         
-        if let Some(_client) = self.clients.remove(&mac) {
+        if let Some(_client) = self.remove(&mac) {
             return true;
         } else {
-            log::error!("clients::ClientsMap::drop_by_ipv4: client {} was not removed", mac);
+            log::error!("clients::ClientsMap::drop_by_ip: client {} was not removed", mac);
             return false;
         }
     }
 
-    pub fn drop_amount(&mut self, max_drop_votes: u8) -> Vec<(ipparser::MacAddress, Client)> {
+    pub fn drop_amount(&mut self, max_drop_votes: u16) -> Vec<(ipparser::MacAddress, Client)> {
         let mut clients: Vec<(ipparser::MacAddress, Client)> = Vec::new();
         for (mac, client) in self.clients.iter() {
-            if client.drop_votes >= max_drop_votes {
+            if client.drop_votes >= max_drop_votes && !client.is_immune() {
                 clients.push((mac.clone(), client.clone()));       
             }
         }
         for (mac, _client) in clients.iter() {
-            if let Some(_c) = self.clients.remove(&mac) {
+            if let Some(_c) = self.remove(mac) {
             } else {
                 log::error!("clients::ClientsMap::drop_amount: client {} was not removed", mac);
             }
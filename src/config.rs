@@ -6,45 +6,230 @@ extern crate clap;
 extern crate fern;
 extern crate chrono;
 extern crate log;
+extern crate if_addrs;
 
 use std::net;
+use std::io;
 use std::fmt;
+use std::cmp;
+use std::convert::TryFrom;
 use crate::ipparser;
+use crate::secrets;
+use crate::tls;
+use crate::accounts;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ListenerRole {
+    Client,
+    Admin,
+    Both
+}
+
+impl ListenerRole {
+    pub fn serves_clients(self) -> bool {
+        self != ListenerRole::Admin
+    }
+
+    pub fn serves_admins(self) -> bool {
+        self != ListenerRole::Client
+    }
+}
+
+impl fmt::Display for ListenerRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenerRole::Client => write!(f, "client"),
+            ListenerRole::Admin => write!(f, "admin"),
+            ListenerRole::Both => write!(f, "both")
+        }
+    }
+}
+
+// How the requests prove that they know the key or the password
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AuthMode {
+    // The secret travels in the request
+    Plaintext,
+    // The secret or an HMAC over a nonce issued by the server
+    Challenge,
+    // Only the HMAC, the requests with the secret are rejected
+    Strict
+}
+
+impl AuthMode {
+    pub fn new_from_str(auth_mode: &str) -> Option<AuthMode> {
+        match auth_mode {
+            "plaintext" => Some(AuthMode::Plaintext),
+            "challenge" => Some(AuthMode::Challenge),
+            "strict" => Some(AuthMode::Strict),
+            _ => None
+        }
+    }
+
+    pub fn allows_plaintext(self) -> bool {
+        self != AuthMode::Strict
+    }
+
+    pub fn allows_challenges(self) -> bool {
+        self != AuthMode::Plaintext
+    }
+}
+
+impl fmt::Display for AuthMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthMode::Plaintext => write!(f, "plaintext"),
+            AuthMode::Challenge => write!(f, "challenge"),
+            AuthMode::Strict => write!(f, "strict")
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Listener {
+    pub address: net::SocketAddr,
+    pub role: ListenerRole,
+    pub tls: bool
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.tls {
+            return write!(f, "{} ({}, tls)", self.address, self.role);
+        }
+        write!(f, "{} ({})", self.address, self.role)
+    }
+}
+
+impl Listener {
+    // Listeners look like `<address>:<port>[@client|@admin|@both]`, the default role is both
+    pub fn new_from_str(listener: &str) -> Option<Listener> {
+        let (address, role) = match listener.rfind('@') {
+            Some(at) => {
+                let role = match listener[at + 1..].to_lowercase().as_str() {
+                    "client" => ListenerRole::Client,
+                    "admin" => ListenerRole::Admin,
+                    "both" => ListenerRole::Both,
+                    _ => return None
+                };
+                (&listener[..at], role)
+            },
+            None => (listener, ListenerRole::Both)
+        };
+        let address = ipparser::str_to_sockaddr(address)?;
+        Some(Listener { address, role, tls: false })
+    }
+}
 
 pub struct StartConfig {
-    pub address: net::SocketAddrV4,    
+    pub listeners: Vec<Listener>,
     pub drop_votes: u8,
-    pub password: String,
-    pub key: String,
+    pub password: secrets::Secret,
+    pub key: secrets::Secret,
     pub capacity: u16,
     pub list_size: u16,
     pub drop_verification: bool,
-    pub log_level: log::LevelFilter
+    pub log_level: log::LevelFilter,
+    pub snapshot: Option<String>,
+    pub audit_log: Option<String>,
+    // The file with the key of the audit log chain, it's required by the audit log
+    pub audit_key: Option<String>,
+    pub shutdown_timeout: u64,
+    pub io_timeout: u64,
+    pub notify_on_shutdown: bool,
+    pub remote_admin: Vec<ipparser::Cidr>,
+    pub force_signup: bool,
+    pub vote_expiry: u64,
+    pub lease: u64,
+    pub rotation_grace: u64,
+    pub drop_quorum: Option<DropQuorum>,
+    pub report_queue: bool,
+    pub signup_approval: bool,
+    pub lookup_rate: Option<RateLimit>,
+    pub mutation_rate: Option<RateLimit>,
+    pub admin_rate: Option<RateLimit>,
+    pub max_auth_failures: u32,
+    pub lockout: u64,
+    pub auth_mode: AuthMode,
+    pub session_ttl: u64,
+    // Besides the key, that is the built-in superuser account
+    pub admin_accounts: Vec<accounts::Account>,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_admin_certs: Vec<Vec<u8>>,
+    pub client_subnets: Vec<ipparser::Cidr>,
+    pub auto_subnet: bool
 }
 
 impl fmt::Display for StartConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f,
 "MINT running ...
-    => address:            {}
+    => listeners:          {}
     => drop-votes:         {}
     => password:           {}
     => key:                {}
     => capacity:           {} users
     => list-size:          {}
-    => drop-verification:  {}", self.address, self.drop_votes, self.password, self.key, self.capacity, self.list_size, self.drop_verification)
+    => drop-verification:  {}
+    => snapshot:           {}
+    => audit-log:          {}
+    => audit-key:          {}
+    => shutdown-timeout:   {} second(s)
+    => io-timeout:         {} second(s)
+    => notify-on-shutdown: {}
+    => remote-admin:       {}
+    => force-signup:       {}
+    => vote-expiry:        {}
+    => lease:              {}
+    => rotation-grace:     {} second(s)
+    => drop-quorum:        {}
+    => report-queue:       {}
+    => signup-approval:    {}
+    => lookup-rate:        {}
+    => mutation-rate:      {}
+    => admin-rate:         {}
+    => lockout:            {}
+    => auth-mode:          {}
+    => session-ttl:        {} second(s)
+    => admin-accounts:     {}
+    => tls-certificate:    {}
+    => tls-admin-certs:    {}
+    => client-subnets:     {}
+    => auto-subnet:        {}", self.listeners.iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", "), self.drop_votes, self.password, self.key, self.capacity, self.list_size, self.drop_verification,
+        self.snapshot.as_deref().unwrap_or("disabled"), self.audit_log.as_deref().unwrap_or("disabled"), self.audit_key.as_deref().unwrap_or("none"), self.shutdown_timeout, self.io_timeout, self.notify_on_shutdown, cidrs_to_string(&self.remote_admin, "loopback only"), self.force_signup, vote_expiry_to_string(self.vote_expiry), lease_to_string(self.lease), self.rotation_grace, drop_quorum_to_string(&self.drop_quorum), self.report_queue, self.signup_approval,
+        rate_limit_to_string(&self.lookup_rate), rate_limit_to_string(&self.mutation_rate), rate_limit_to_string(&self.admin_rate), lockout_to_string(self.max_auth_failures, self.lockout), self.auth_mode, self.session_ttl, admin_accounts_to_string(&self.admin_accounts),
+        tls_certificate_to_string(&self.listeners, &self.tls_cert), self.tls_admin_certs.len(), cidrs_to_string(&self.client_subnets, "any"), self.auto_subnet)
     }
 }
 
 impl StartConfig {
     pub fn new(matches: clap::ArgMatches) -> Option<StartConfig> {
         if let Some(matches) = matches.subcommand_matches("start") {            
-            let address: net::SocketAddrV4;
-            if let Some(addr) = matches.value_of("address") {
-                if let Some(addr) = ipparser::sockaddrv4str_to_sockaddrv4(addr) {
-                    address = addr;
-                } else { return None; }
-            } else { return None; }            
+            let mut listeners: Vec<Listener> = Vec::new();
+            if let Some(addrs) = matches.values_of("address") {
+                for addr in addrs {
+                    if let Some(listener) = Listener::new_from_str(addr) {
+                        listeners.push(listener);
+                    } else { return None; }
+                }
+            } else { return None; }
+            if let Some(addrs) = matches.values_of("tls-address") {
+                for addr in addrs {
+                    let mut listener = Listener::new_from_str(addr)?;
+                    listener.tls = true;
+                    listeners.push(listener);
+                }
+            }
+
+            let tls_cert = matches.value_of("tls-cert").map(String::from);
+            let tls_key = matches.value_of("tls-key").map(String::from);
+            let mut tls_admin_certs: Vec<Vec<u8>> = Vec::new();
+            if let Some(fingerprints) = matches.values_of("tls-admin-cert") {
+                for fingerprint in fingerprints {
+                    tls_admin_certs.push(tls::parse_fingerprint(fingerprint)?);
+                }
+            }
 
             let drop_votes: u8;
             if let Some(dv) = matches.value_of("drop-votes") {                
@@ -55,14 +240,14 @@ impl StartConfig {
                 } else { return None; }
             } else { return None; }            
 
-            let password: String;
+            let password: secrets::Secret;
             if let Some(pass) = matches.value_of("password") {
-                password = String::from(pass);
+                password = secrets::Secret::new_from_str(pass)?;
             } else { return None; }
 
-            let key: String;
+            let key: secrets::Secret;
             if let Some(k) = matches.value_of("key") {
-                key = String::from(k);
+                key = secrets::Secret::new_from_str(k)?;
             } else { return None; }            
 
             let capacity: u16;
@@ -81,6 +266,89 @@ impl StartConfig {
 
             let drop_verification = matches.is_present("drop-verification");
 
+            let snapshot = matches.value_of("snapshot").map(String::from);
+
+            let audit_log = matches.value_of("audit-log").map(String::from);
+
+            let audit_key = matches.value_of("audit-key").map(String::from);
+
+            let shutdown_timeout: u64;
+            if let Some(st) = matches.value_of("shutdown-timeout") {
+                if let Ok(st) = st.parse::<u64>() {
+                    shutdown_timeout = st;
+                } else { return None; }
+            } else { return None; }
+
+            let io_timeout = matches.value_of("io-timeout")?.parse::<u64>().ok().filter(|t| *t > 0)?;
+
+            let notify_on_shutdown = matches.is_present("notify-on-shutdown");
+
+            let force_signup = matches.is_present("force-signup");
+
+            let report_queue = matches.is_present("report-queue");
+
+            let signup_approval = matches.is_present("signup-approval");
+
+            let lookup_rate = RateLimit::new_from_str(matches.value_of("lookup-rate")?)?;
+            let mutation_rate = RateLimit::new_from_str(matches.value_of("mutation-rate")?)?;
+            let admin_rate = RateLimit::new_from_str(matches.value_of("admin-rate")?)?;
+
+            let max_auth_failures = matches.value_of("max-auth-failures")?.parse::<u32>().ok()?;
+            let lockout = matches.value_of("lockout")?.parse::<u64>().ok()?;
+            let auth_mode = AuthMode::new_from_str(matches.value_of("auth-mode")?)?;
+            let session_ttl = matches.value_of("session-ttl")?.parse::<u64>().ok()?;
+
+            let mut admin_accounts: Vec<accounts::Account> = Vec::new();
+            if let Some(values) = matches.values_of("admin-account") {
+                for value in values {
+                    let account = accounts::Account::new_from_str(value)?;
+                    if admin_accounts.iter().any(|a| a.name == account.name) {
+                        return None;
+                    }
+                    admin_accounts.push(account);
+                }
+            }
+
+            let vote_expiry: u64;
+            if let Some(ve) = matches.value_of("vote-expiry") {
+                if let Ok(ve) = ve.parse::<u64>() {
+                    vote_expiry = ve;
+                } else { return None; }
+            } else { return None; }
+
+            let lease = matches.value_of("lease")?.parse::<u64>().ok()?;
+
+            let rotation_grace = matches.value_of("rotation-grace")?.parse::<u64>().ok()?;
+
+            let mut drop_quorum: Option<DropQuorum> = None;
+            if let Some(dq) = matches.value_of("drop-quorum") {
+                if let Some(mv) = matches.value_of("drop-quorum-min") {
+                    if let Ok(dq) = dq.parse::<u8>() {
+                        if let Ok(mv) = mv.parse::<u16>() {
+                            drop_quorum = Some(DropQuorum::new(dq, mv)?);
+                        } else { return None; }
+                    } else { return None; }
+                } else { return None; }
+            }
+
+            let mut client_subnets: Vec<ipparser::Cidr> = Vec::new();
+            if let Some(cidrs) = matches.values_of("client-subnet") {
+                for cidr in cidrs {
+                    client_subnets.push(ipparser::Cidr::new_from_str(cidr)?);
+                }
+            }
+
+            let auto_subnet = matches.is_present("auto-subnet");
+
+            let mut remote_admin: Vec<ipparser::Cidr> = Vec::new();
+            if let Some(cidrs) = matches.values_of("remote-admin") {
+                for cidr in cidrs {
+                    if let Some(cidr) = ipparser::Cidr::new_from_str(cidr) {
+                        remote_admin.push(cidr);
+                    } else { return None; }
+                }
+            }
+
             let log_level: log::LevelFilter;
             if let Some(ll) = matches.value_of("log-level") {
                 match ll {
@@ -92,12 +360,181 @@ impl StartConfig {
                 }
             } else { return None; }
 
-            return Some( StartConfig { address, drop_votes, password, key, capacity, list_size, drop_verification, log_level } );
+            return Some( StartConfig { listeners, drop_votes, password, key, capacity, list_size, drop_verification, log_level, snapshot, audit_log, audit_key, shutdown_timeout, io_timeout, notify_on_shutdown, remote_admin, force_signup, vote_expiry, lease, rotation_grace, drop_quorum, report_queue, signup_approval, lookup_rate, mutation_rate, admin_rate, max_auth_failures, lockout, auth_mode, session_ttl, admin_accounts, tls_cert, tls_key, tls_admin_certs, client_subnets, auto_subnet } );
         }
         None
     }
 }
 
+// A client is dropped out when its votes reach a percentage of the signed up clients,
+// but never with less than min_votes votes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DropQuorum {
+    pub percent: u8,
+    pub min_votes: u16
+}
+
+impl DropQuorum {
+    pub fn new(percent: u8, min_votes: u16) -> Option<DropQuorum> {
+        if (1..=100).contains(&percent) && min_votes >= 1 {
+            return Some(DropQuorum { percent, min_votes });
+        }
+        None
+    }
+
+    pub fn drop_votes(&self, clients_len: usize) -> u16 {
+        // Rounded up, 50% of 3 clients are 2 votes
+        let votes = (clients_len * usize::from(self.percent)).div_ceil(100);
+        let votes = u16::try_from(votes).unwrap_or(u16::MAX);
+        cmp::max(votes, self.min_votes)
+    }
+}
+
+impl fmt::Display for DropQuorum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}% of the signed up clients, at least {} vote(s)", self.percent, self.min_votes)
+    }
+}
+
+// How many requests per minute an IP can make, and how many of them it can make at once
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RateLimit {
+    pub per_minute: u32,
+    pub burst: u32
+}
+
+impl RateLimit {
+    // <per minute>[/<burst>], without the burst an IP can use a whole minute at once.
+    // A rate of 0 means that there is no limit
+    pub fn new_from_str(rate_limit: &str) -> Option<Option<RateLimit>> {
+        let mut parts = rate_limit.splitn(2, '/');
+        let per_minute = parts.next()?.parse::<u32>().ok()?;
+        let burst = match parts.next() {
+            Some(burst) => burst.parse::<u32>().ok()?,
+            None => per_minute
+        };
+        if per_minute == 0 {
+            return Some(None);
+        }
+        if burst == 0 {
+            return None;
+        }
+        Some(Some(RateLimit { per_minute, burst }))
+    }
+
+    pub fn tokens_per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.per_minute, self.burst)
+    }
+}
+
+pub fn rate_limit_to_string(rate_limit: &Option<RateLimit>) -> String {
+    match rate_limit {
+        Some(rate_limit) => format!("{} request(s) per minute, {} at once", rate_limit.per_minute, rate_limit.burst),
+        None => String::from("unlimited")
+    }
+}
+
+pub fn tls_certificate_to_string(listeners: &[Listener], tls_cert: &Option<String>) -> String {
+    if !listeners.iter().any(|l| l.tls) {
+        return String::from("disabled");
+    }
+    match tls_cert {
+        Some(tls_cert) => tls_cert.clone(),
+        None => String::from("self-signed")
+    }
+}
+
+pub fn admin_accounts_to_string(admin_accounts: &[accounts::Account]) -> String {
+    if admin_accounts.is_empty() {
+        return String::from("only the key");
+    }
+    admin_accounts.iter().map(|a| a.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+pub fn lockout_to_string(max_auth_failures: u32, lockout: u64) -> String {
+    if max_auth_failures == 0 {
+        return String::from("disabled");
+    }
+    format!("{} second(s) after {} failure(s), doubled with every other failure", lockout, max_auth_failures)
+}
+
+pub fn drop_quorum_to_string(drop_quorum: &Option<DropQuorum>) -> String {
+    match drop_quorum {
+        Some(drop_quorum) => drop_quorum.to_string(),
+        None => String::from("disabled")
+    }
+}
+
+pub fn vote_expiry_to_string(vote_expiry: u64) -> String {
+    if vote_expiry == 0 {
+        return String::from("never");
+    }
+    format!("{} second(s)", vote_expiry)
+}
+
+pub fn lease_to_string(lease: u64) -> String {
+    if lease == 0 {
+        return String::from("disabled");
+    }
+    format!("{} second(s)", lease)
+}
+
+// The networks a listener is connected to. A listener on 0.0.0.0 or :: is connected to the
+// networks of every interface, the loopback is left out because it's always allowed
+pub fn subnets_of(address: &net::IpAddr, interfaces: &[(net::IpAddr, u8)]) -> Vec<ipparser::Cidr> {
+    let mut subnets: Vec<ipparser::Cidr> = Vec::new();
+    for (ip, prefix_len) in interfaces.iter() {
+        if ip.is_loopback() {
+            continue;
+        }
+        let serves_ip = match address {
+            net::IpAddr::V4(v4) if v4.is_unspecified() => ip.is_ipv4(),
+            // An IPv6 socket also gets the IPv4 peers
+            net::IpAddr::V6(v6) if v6.is_unspecified() => true,
+            address => address == ip
+        };
+        if serves_ip {
+            if let Some(cidr) = ipparser::Cidr::new(*ip, *prefix_len) {
+                if !subnets.contains(&cidr) {
+                    subnets.push(cidr);
+                }
+            }
+        }
+    }
+    subnets
+}
+
+pub fn detect_client_subnets(listeners: &[Listener]) -> io::Result<Vec<ipparser::Cidr>> {
+    let interfaces: Vec<(net::IpAddr, u8)> = if_addrs::get_if_addrs()?.iter().map(|interface| {
+        match &interface.addr {
+            if_addrs::IfAddr::V4(v4) => (net::IpAddr::V4(v4.ip), v4.prefixlen),
+            if_addrs::IfAddr::V6(v6) => (net::IpAddr::V6(v6.ip), v6.prefixlen)
+        }
+    }).collect();
+    let mut subnets: Vec<ipparser::Cidr> = Vec::new();
+    for listener in listeners.iter().filter(|l| l.role.serves_clients()) {
+        for cidr in subnets_of(&listener.address.ip(), &interfaces) {
+            if !subnets.contains(&cidr) {
+                subnets.push(cidr);
+            }
+        }
+    }
+    Ok(subnets)
+}
+
+pub fn cidrs_to_string(cidrs: &[ipparser::Cidr], if_empty: &str) -> String {
+    if cidrs.is_empty() {
+        return String::from(if_empty);
+    }
+    cidrs.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(", ")
+}
+
 pub fn setup_logging(log_level: &log::LevelFilter) -> Result<(), fern::InitError> {
     let colors = fern::colors::ColoredLevelConfig::new().info(fern::colors::Color::Green)
                                                         .warn(fern::colors::Color::Yellow)
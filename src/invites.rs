@@ -0,0 +1,155 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module keeps the invite codes that the admins mint, a client can sign up with a code
+// instead of the password, so a single person can be let in or out without changing it. The code
+// only signs up, the other requests of the client carry the owner token it was given.
+
+extern crate ring;
+extern crate serde_json;
+
+use std::convert::TryFrom;
+use ring::digest;
+use ring::rand::{
+    self,
+    SecureRandom
+};
+use crate::secrets;
+
+const INVITE_CODE_LEN: usize = 16;
+
+#[derive(Clone)]
+pub struct Invite {
+    pub id: u64,
+    // Only the SHA-256 of the code is kept, in hex, the code is shown once when it's minted
+    code_digest: String,
+    pub max_uses: u32,
+    pub uses: u32,
+    // Seconds since the UNIX epoch, None means that the invite doesn't expire
+    pub expires_at: Option<u64>,
+    // The admin account that minted it
+    pub created_by: String,
+    pub created_at: u64
+}
+
+impl Invite {
+    pub fn has_expired(&self, now: u64) -> bool {
+        match self.expires_at {
+            Some(expires_at) => now >= expires_at,
+            None => false
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.uses >= self.max_uses
+    }
+
+    pub fn is_code(&self, code: &str) -> bool {
+        digest_of(code) == self.code_digest
+    }
+
+    pub fn to_json_string(&self) -> String {
+        let expires_at = match self.expires_at {
+            Some(expires_at) => expires_at.to_string(),
+            None => String::from("null")
+        };
+        format!("{{\"id\":{},\"code_digest\":\"{}\",\"max_uses\":{},\"uses\":{},\"expires_at\":{},\"created_by\":\"{}\",\"created_at\":{}}}",
+            self.id, self.code_digest, self.max_uses, self.uses, expires_at, self.created_by, self.created_at)
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<Invite> {
+        let id = val.get("id")?.as_u64()?;
+        let code_digest = String::from(val.get("code_digest")?.as_str()?);
+        let max_uses = u32::try_from(val.get("max_uses")?.as_u64()?).ok()?;
+        let uses = u32::try_from(val.get("uses")?.as_u64()?).ok()?;
+        let expires_at = match val.get("expires_at")? {
+            serde_json::Value::Null => None,
+            expires_at => Some(expires_at.as_u64()?)
+        };
+        let created_by = String::from(val.get("created_by")?.as_str()?);
+        let created_at = val.get("created_at")?.as_u64()?;
+        Some(Invite { id, code_digest, max_uses, uses, expires_at, created_by, created_at })
+    }
+}
+
+pub struct InviteList {
+    next_id: u64,
+    invites: Vec<Invite>
+}
+
+impl InviteList {
+    pub fn new() -> InviteList {
+        InviteList { next_id: 1, invites: Vec::new() }
+    }
+
+    // Returns the id of the invite and its code
+    pub fn mint(&mut self, max_uses: u32, expires_at: Option<u64>, created_by: &str, now: u64) -> Option<(u64, String)> {
+        let mut code = [0u8; INVITE_CODE_LEN];
+        rand::SystemRandom::new().fill(&mut code).ok()?;
+        let code = secrets::to_hex(&code);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.invites.push(Invite { id, code_digest: digest_of(&code), max_uses, uses: 0, expires_at, created_by: String::from(created_by), created_at: now });
+        Some((id, code))
+    }
+
+    pub fn revoke(&mut self, id: u64) -> Option<Invite> {
+        let index = self.invites.iter().position(|i| i.id == id)?;
+        Some(self.invites.remove(index))
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Invite> {
+        self.invites.iter().find(|i| i.id == id)
+    }
+
+    pub fn get_by_code(&self, code: &str) -> Option<&Invite> {
+        self.invites.iter().find(|i| i.is_code(code))
+    }
+
+    // The id of the invite of the code if it can still admit a new client
+    pub fn admits(&self, code: &str, now: u64) -> Option<u64> {
+        self.get_by_code(code).filter(|i| !i.has_expired(now) && !i.is_exhausted()).map(|i| i.id)
+    }
+
+    // Counts a client admitted by the invite
+    pub fn redeem(&mut self, id: u64) -> bool {
+        if let Some(invite) = self.invites.iter_mut().find(|i| i.id == id) {
+            invite.uses = invite.uses.saturating_add(1);
+            return true;
+        }
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.invites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.invites.is_empty()
+    }
+
+    pub fn to_json_string(&self) -> String {
+        format!("[{}]", self.invites.iter().map(|i| i.to_json_string()).collect::<Vec<String>>().join(","))
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<InviteList> {
+        let mut invite_list = InviteList::new();
+        for invite in val.as_array()? {
+            let invite = Invite::from_json_value(invite)?;
+            if invite.id >= invite_list.next_id {
+                invite_list.next_id = invite.id + 1;
+            }
+            invite_list.invites.push(invite);
+        }
+        Some(invite_list)
+    }
+}
+
+impl Default for InviteList {
+    fn default() -> Self {
+        InviteList::new()
+    }
+}
+
+fn digest_of(code: &str) -> String {
+    secrets::to_hex(digest::digest(&digest::SHA256, code.as_bytes()).as_ref())
+}
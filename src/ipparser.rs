@@ -451,3 +451,127 @@ pub fn ipv4addr_str_to_u32(ipaddr: &IpAddr) -> Option<u32> {
     }
     None
 }
+
+// Parses `<ip>` or `<ipv6>%<scope_id>`, the scope id of an IPv4 address is always 0
+pub fn parse_scoped_ip(addr: &str) -> Option<(IpAddr, u32)> {
+    if addr.contains('%') {
+        let addr_scope: Vec<&str> = addr.split('%').collect();
+        if addr_scope.len() == 2 {
+            if let Some(ipv6) = str_to_ipv6addr(addr_scope[0]) {
+                if let Ok(scope_id) = addr_scope[1].parse::<u32>() {
+                    return Some((IpAddr::V6(ipv6), scope_id));
+                }
+            }
+        }
+        return None;
+    }
+    match parse_ip(addr) {
+        Ok(ip) => Some((ip, 0)),
+        Err(()) => None
+    }
+}
+
+pub fn scoped_ip_to_string(ip: &IpAddr, scope_id: u32) -> String {
+    match ip {
+        IpAddr::V6(ipv6) if scope_id != 0 => format!("{}%{}", ipv6, scope_id),
+        _ => ip.to_string()
+    }
+}
+
+pub fn scope_id_of(sock_addr: &SocketAddr) -> u32 {
+    match sock_addr {
+        SocketAddr::V6(sock_addr) => sock_addr.scope_id(),
+        SocketAddr::V4(_) => 0
+    }
+}
+
+// Accepts `<ipv4>:<port>`, `[<ipv6>]:<port>` and the `<ipv6>%<scope_id>:<port>` form of `AddrContainer`
+pub fn str_to_sockaddr(sockaddr: &str) -> Option<SocketAddr> {
+    if let Ok(sockaddr) = SocketAddr::from_str(sockaddr) {
+        return Some(sockaddr);
+    }
+    if let Ok(addr_container) = AddrContainer::new(sockaddr) {
+        if addr_container.is_v6() {
+            if let Ok(sockaddr) = addr_container.to_sock_addr6() {
+                return Some(SocketAddr::V6(sockaddr));
+            }
+        }
+    }
+    None
+}
+
+// A network like 192.168.1.0/24 or fe80::/10
+#[derive(Clone, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+impl Cidr {
+    pub fn new(network: IpAddr, prefix_len: u8) -> Option<Cidr> {
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_prefix_len {
+            return None;
+        }
+        // The host bits are cleared, so 192.168.1.70/24 becomes 192.168.1.0/24
+        let network = match network {
+            IpAddr::V4(ipv4) => IpAddr::V4(Ipv4Addr::from(u32::from(ipv4) & ipv4_mask(prefix_len))),
+            IpAddr::V6(ipv6) => IpAddr::V6(Ipv6Addr::from(u128::from(ipv6) & ipv6_mask(prefix_len)))
+        };
+        Some(Cidr { network, prefix_len })
+    }
+
+    // Accepts `<ip>/<prefix_len>` or a single `<ip>`, which is the same as a /32 or a /128
+    pub fn new_from_str(cidr: &str) -> Option<Cidr> {
+        let ip_prefix: Vec<&str> = cidr.split('/').collect();
+        match ip_prefix.len() {
+            1 => {
+                let ip = parse_ip(ip_prefix[0]).ok()?;
+                let prefix_len = if ip.is_ipv4() { 32 } else { 128 };
+                Cidr::new(ip, prefix_len)
+            },
+            2 => {
+                let ip = parse_ip(ip_prefix[0]).ok()?;
+                let prefix_len = ip_prefix[1].parse::<u8>().ok()?;
+                Cidr::new(ip, prefix_len)
+            },
+            _ => None
+        }
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ipv4)) => u32::from(*ipv4) & ipv4_mask(self.prefix_len) == u32::from(network),
+            (IpAddr::V6(network), IpAddr::V6(ipv6)) => u128::from(*ipv6) & ipv6_mask(self.prefix_len) == u128::from(network),
+            (IpAddr::V4(_), IpAddr::V6(_)) => match unmap_ipv4(ip) {
+                IpAddr::V4(ipv4) => self.contains(&IpAddr::V4(ipv4)),
+                IpAddr::V6(_) => false
+            },
+            (IpAddr::V6(_), IpAddr::V4(_)) => false
+        }
+    }
+}
+
+fn ipv4_mask(prefix_len: u8) -> u32 {
+    if prefix_len == 0 { 0 } else { u32::MAX << (32 - u32::from(prefix_len)) }
+}
+
+fn ipv6_mask(prefix_len: u8) -> u128 {
+    if prefix_len == 0 { 0 } else { u128::MAX << (128 - u32::from(prefix_len)) }
+}
+
+// IPv4 peers reaching an IPv6 socket look like ::ffff:192.168.1.70, this returns 192.168.1.70 for them
+pub fn unmap_ipv4(ip: &IpAddr) -> IpAddr {
+    if let IpAddr::V6(ipv6) = ip {
+        if let Some(ipv4) = ipv6.to_ipv4_mapped() {
+            return IpAddr::V4(ipv4);
+        }
+    }
+    *ip
+}
@@ -10,6 +10,18 @@ pub mod clients;
 pub mod requests;
 pub mod replies;
 pub mod server;
+pub mod snapshot;
+pub mod reports;
+pub mod ratelimit;
+pub mod auth;
+pub mod bans;
+pub mod secrets;
+pub mod tls;
+pub mod sessions;
+pub mod accounts;
+pub mod audit;
+pub mod invites;
+pub mod signups;
 
 #[cfg(test)]
 mod tests;
@@ -6,16 +6,20 @@ extern crate clap;
 
 use cinnamon::config;
 use cinnamon::ipparser;
+use cinnamon::secrets;
+use cinnamon::tls;
+use cinnamon::accounts;
+use cinnamon::audit;
 use cinnamon::run_start_command;
 use std::process;
 use clap::{Arg, App, SubCommand, AppSettings};
 
 
 fn sock_addr_validator(addr: String) -> Result<(), String> {
-    if ipparser::is_socket_addr_v4(addr.as_str()) {
+    if config::Listener::new_from_str(addr.as_str()).is_some() {
         return Ok(());
     }
-    Err(format!("The address must be <address>:<port>"))
+    Err(String::from("The address must be <ipv4>:<port>, [<ipv6>]:<port> or <ipv6>%<scope_id>:<port>, optionally followed by @client, @admin or @both"))
 }
 
 fn drop_votes_validator(dv: String) -> Result<(), String> {
@@ -28,10 +32,10 @@ fn drop_votes_validator(dv: String) -> Result<(), String> {
 }
 
 fn password_validator(pass: String) -> Result<(), String> {
-    if pass.is_ascii() && pass.len() < 33 {
+    if secrets::is_valid_secret(&pass) {
         return Ok(());
     }    
-    Err(format!("Password and key must contain only ascii characters and less than 33 characters"))
+    Err(format!("Password and key must contain only ascii characters and less than 33 characters, or be a hash made by the hash-secret command"))
 }
 
 fn list_size_validator(l: String) -> Result<(), String> {
@@ -54,6 +58,114 @@ fn capacity_validator(c: String) -> Result<(), String> {
     }
 }
 
+fn shutdown_timeout_validator(t: String) -> Result<(), String> {
+    if let Ok(_v) = t.parse::<u64>() {
+        return Ok(());
+    }
+    Err(String::from("The shutdown timeout must be a number of seconds"))
+}
+
+fn io_timeout_validator(t: String) -> Result<(), String> {
+    if let Ok(v) = t.parse::<u64>() {
+        if v >= 1 {
+            return Ok(());
+        }
+    }
+    Err(String::from("The I/O timeout must be a number of seconds greater than 0"))
+}
+
+fn vote_expiry_validator(e: String) -> Result<(), String> {
+    if let Ok(_v) = e.parse::<u64>() {
+        return Ok(());
+    }
+    Err(String::from("The vote expiry must be a number of seconds, 0 means that the votes never expire"))
+}
+
+fn drop_quorum_validator(q: String) -> Result<(), String> {
+    if let Ok(v) = q.parse::<u8>() {
+        if (1..=100).contains(&v) {
+            return Ok(());
+        }
+    }
+    Err(String::from("The drop quorum must be a percentage between [1,100]"))
+}
+
+fn drop_quorum_min_validator(m: String) -> Result<(), String> {
+    if let Ok(v) = m.parse::<u16>() {
+        if v >= 1 {
+            return Ok(());
+        }
+    }
+    Err(String::from("This value must be between [1,65535]"))
+}
+
+fn rate_limit_validator(r: String) -> Result<(), String> {
+    if config::RateLimit::new_from_str(r.as_str()).is_some() {
+        return Ok(());
+    }
+    Err(String::from("The rate must be <requests per minute>[/<burst>], 0 means unlimited"))
+}
+
+fn max_auth_failures_validator(m: String) -> Result<(), String> {
+    if let Ok(_v) = m.parse::<u32>() {
+        return Ok(());
+    }
+    Err(String::from("The maximum of failures must be a number, 0 means that the IPs are never locked out"))
+}
+
+fn lockout_validator(l: String) -> Result<(), String> {
+    if let Ok(v) = l.parse::<u64>() {
+        if v >= 1 {
+            return Ok(());
+        }
+    }
+    Err(String::from("The lockout must be a number of seconds greater than 0"))
+}
+
+fn lease_validator(l: String) -> Result<(), String> {
+    if let Ok(_v) = l.parse::<u64>() {
+        return Ok(());
+    }
+    Err(String::from("The lease must be a number of seconds, 0 means that the MACs are never leased"))
+}
+
+fn rotation_grace_validator(g: String) -> Result<(), String> {
+    if let Ok(_v) = g.parse::<u64>() {
+        return Ok(());
+    }
+    Err(String::from("The rotation grace must be a number of seconds, 0 means that the old secrets stop working at once"))
+}
+
+fn session_ttl_validator(t: String) -> Result<(), String> {
+    if let Ok(v) = t.parse::<u64>() {
+        if v >= 1 {
+            return Ok(());
+        }
+    }
+    Err(String::from("The session TTL must be a number of seconds greater than 0"))
+}
+
+fn fingerprint_validator(fingerprint: String) -> Result<(), String> {
+    if tls::parse_fingerprint(fingerprint.as_str()).is_some() {
+        return Ok(());
+    }
+    Err(String::from("The fingerprint must be the SHA-256 of the certificate in hex, with or without colons"))
+}
+
+fn admin_account_validator(account: String) -> Result<(), String> {
+    if accounts::Account::new_from_str(account.as_str()).is_some() {
+        return Ok(());
+    }
+    Err(format!("The account must be <name>:<role>:<secret>, the name must have up to {} letters, digits, '-', '_' or '.' and can't be \"{}\", the role must be read-only, operator or superuser", accounts::MAX_ACCOUNT_NAME_LEN, accounts::BUILTIN_ADMIN))
+}
+
+fn cidr_validator(cidr: String) -> Result<(), String> {
+    if ipparser::Cidr::new_from_str(cidr.as_str()).is_some() {
+        return Ok(());
+    }
+    Err(String::from("The network must be <ip>/<prefix length> or a single <ip>"))
+}
+
 fn main() {
     let matches = App::new("MINT Server")
                           .version("1.0")
@@ -67,11 +179,13 @@ fn main() {
                                        .arg(Arg::with_name("address")
                                             .short("a")
                                             .long("address")
-                                            .value_name("IP_ADDRESS:PORT")
-                                            .help("Sets the IP address and port the server will listen to")
+                                            .value_name("IP_ADDRESS:PORT[@ROLE]")
+                                            .help("Sets an IP address and port the server will listen to, can be repeated")
+                                            .long_help("Sets an IP address and port the server will listen to, can be repeated. The role says who can use the address: client, admin or both (default)")
                                             .default_value("127.0.0.1:42000")
                                             .takes_value(true)
                                             .required(false)
+                                            .multiple(true)
                                             .number_of_values(1)
                                             .validator(sock_addr_validator))                                        
                                         .arg(Arg::with_name("key")
@@ -139,9 +253,313 @@ fn main() {
                                             .takes_value(true)
                                             .required(false)
                                             .number_of_values(1)                                            
-                                            .validator(capacity_validator)))
+                                            .validator(capacity_validator))
+                                        .arg(Arg::with_name("snapshot")
+                                            .short("s")
+                                            .long("snapshot")
+                                            .value_name("FILE")
+                                            .help("Restores the clients from this file when the server starts and saves them into it when the server stops, the bans and the admin accounts are saved as soon as they change")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1))
+                                        .arg(Arg::with_name("audit-log")
+                                            .long("audit-log")
+                                            .value_name("FILE")
+                                            .help("Appends every admin action and its result to this file, the refused admin requests too. Each entry is chained to the previous one by its HMAC-SHA256 keyed with --audit-key, check it with the audit verify command")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .requires("audit-key")
+                                            .number_of_values(1))
+                                        .arg(Arg::with_name("audit-key")
+                                            .long("audit-key")
+                                            .value_name("FILE")
+                                            .help("Sets the file with the key of the audit log chain, at least 16 characters. Keep it away from the audit log, whoever can read it and write the log can forge the chain")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .requires("audit-log")
+                                            .number_of_values(1))
+                                        .arg(Arg::with_name("shutdown-timeout")
+                                            .short("t")
+                                            .long("shutdown-timeout")
+                                            .value_name("SECONDS")
+                                            .help("Sets how long the server waits for pending requests and notifies the clients when it's asked to stop")
+                                            .default_value("5")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(shutdown_timeout_validator))
+                                        .arg(Arg::with_name("io-timeout")
+                                            .long("io-timeout")
+                                            .value_name("SECONDS")
+                                            .help("Sets how long the server waits to read a request or to send a reply before it drops the connection")
+                                            .default_value("5")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(io_timeout_validator))
+                                        .arg(Arg::with_name("notify-on-shutdown")
+                                            .short("n")
+                                            .long("notify-on-shutdown")
+                                            .help("When enabled, the server tells every signed up client that it's going to stop")
+                                            .multiple(false)
+                                            .required(false))
+                                        .arg(Arg::with_name("remote-admin")
+                                            .short("r")
+                                            .long("remote-admin")
+                                            .value_name("NETWORK/PREFIX")
+                                            .help("Allows admin requests from this network, can be repeated. Without it only the loopback can administrate the server")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .multiple(true)
+                                            .number_of_values(1)
+                                            .validator(cidr_validator))
+                                        .arg(Arg::with_name("lease")
+                                            .long("lease")
+                                            .value_name("SECONDS")
+                                            .help("Sets how long a sign up owns its MAC. A MAC can only be signed up again from another IP with the owner token it was given, which renews the lease. While the lease lasts the token is needed from the IP of the entry too, and no other MAC can replace it from that IP. 0 means no lease")
+                                            .default_value("0")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(lease_validator))
+                                        .arg(Arg::with_name("rotation-grace")
+                                            .long("rotation-grace")
+                                            .value_name("SECONDS")
+                                            .help("Sets for how long the old key or password is still accepted after an admin changes it, the admins can give another grace when they change it")
+                                            .default_value("0")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(rotation_grace_validator))
+                                        .arg(Arg::with_name("vote-expiry")
+                                            .short("e")
+                                            .long("vote-expiry")
+                                            .value_name("SECONDS")
+                                            .help("Sets how long a drop vote counts before it expires, 0 means that the votes never expire")
+                                            .default_value("0")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(vote_expiry_validator))
+                                        .arg(Arg::with_name("drop-quorum")
+                                            .short("q")
+                                            .long("drop-quorum")
+                                            .value_name("PERCENT")
+                                            .help("Drops out a client when its votes reach this percentage of the signed up clients, instead of a fixed drop-votes value")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(drop_quorum_validator))
+                                        .arg(Arg::with_name("drop-quorum-min")
+                                            .short("m")
+                                            .long("drop-quorum-min")
+                                            .value_name("VOTES")
+                                            .help("Sets the minimum amount of votes needed to drop out a client when the drop-quorum is used")
+                                            .default_value("2")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(drop_quorum_min_validator))
+                                        .arg(Arg::with_name("report-queue")
+                                            .short("R")
+                                            .long("report-queue")
+                                            .help("When enabled, the drop requests of the clients become reports that an admin approves or dismisses")
+                                            .multiple(false)
+                                            .required(false))
+                                        .arg(Arg::with_name("signup-approval")
+                                            .long("signup-approval")
+                                            .help("When enabled, the new clients wait for an admin to approve their sign up before anyone can look them up")
+                                            .multiple(false)
+                                            .required(false))
+                                        .arg(Arg::with_name("lookup-rate")
+                                            .long("lookup-rate")
+                                            .value_name("PER_MINUTE[/BURST]")
+                                            .help("Limits how many get requests each IP can make, 0 means unlimited")
+                                            .default_value("0")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(rate_limit_validator))
+                                        .arg(Arg::with_name("mutation-rate")
+                                            .long("mutation-rate")
+                                            .value_name("PER_MINUTE[/BURST]")
+                                            .help("Limits how many sign up and drop requests each IP can make, 0 means unlimited")
+                                            .default_value("0")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(rate_limit_validator))
+                                        .arg(Arg::with_name("admin-rate")
+                                            .long("admin-rate")
+                                            .value_name("PER_MINUTE[/BURST]")
+                                            .help("Limits how many admin requests each IP can make, 0 means unlimited")
+                                            .default_value("0")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(rate_limit_validator))
+                                        .arg(Arg::with_name("max-auth-failures")
+                                            .long("max-auth-failures")
+                                            .value_name("FAILURES")
+                                            .help("Locks out an IP after it sends this amount of wrong keys or passwords in a row, 0 disables the lockouts")
+                                            .long_help("Locks out an IP after it sends this amount of wrong keys or passwords in a row, 0 disables the lockouts. Unlike the rate limits the lockouts are on by default, which changes how the server answered the wrong secrets before: every secret is checked with a slow hash, and the lockout is what stops a single IP from keeping the server busy with wrong guesses")
+                                            .default_value("5")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(max_auth_failures_validator))
+                                        .arg(Arg::with_name("lockout")
+                                            .long("lockout")
+                                            .value_name("SECONDS")
+                                            .help("Sets how long the first lockout lasts, every failure after it doubles it up to an hour")
+                                            .default_value("30")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(lockout_validator))
+                                        .arg(Arg::with_name("auth-mode")
+                                            .short("A")
+                                            .long("auth-mode")
+                                            .value_name("AUTH_MODE")
+                                            .help("Sets how the requests prove that they know the key or the password")
+                                            .long_help("Sets how the requests prove that they know the key or the password. plaintext: the secret travels in the request. challenge: the requests can also be signed with an HMAC over a nonce issued by the server. strict: only the signed requests are accepted")
+                                            .possible_values(&["plaintext", "challenge", "strict"])
+                                            .default_value("plaintext")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .required(false))
+                                        .arg(Arg::with_name("session-ttl")
+                                            .long("session-ttl")
+                                            .value_name("SECONDS")
+                                            .help("Sets how long the admin sessions last, the admins log in with the key and then send the token of their session instead")
+                                            .default_value("900")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .number_of_values(1)
+                                            .validator(session_ttl_validator))
+                                        .arg(Arg::with_name("admin-account")
+                                            .long("admin-account")
+                                            .value_name("NAME:ROLE:SECRET")
+                                            .help("Adds an admin account, the role is read-only, operator or superuser and the secret can be a hash made by the hash-secret command, can be repeated. The key is the superuser account \"admin\". The accounts added by the admins are saved in the --snapshot, these replace the ones with the same name")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .multiple(true)
+                                            .number_of_values(1)
+                                            .validator(admin_account_validator))
+                                        .arg(Arg::with_name("tls-address")
+                                            .short("T")
+                                            .long("tls-address")
+                                            .value_name("IP_ADDRESS:PORT[@ROLE]")
+                                            .help("Sets an IP address and port the server will listen to with TLS, can be repeated and used alongside --address")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .multiple(true)
+                                            .number_of_values(1)
+                                            .validator(sock_addr_validator))
+                                        .arg(Arg::with_name("tls-cert")
+                                            .long("tls-cert")
+                                            .value_name("FILE")
+                                            .help("Sets the PEM certificate chain of the TLS listeners, without it a self-signed certificate is generated at startup")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .requires("tls-key")
+                                            .number_of_values(1))
+                                        .arg(Arg::with_name("tls-key")
+                                            .long("tls-key")
+                                            .value_name("FILE")
+                                            .help("Sets the PEM private key of the --tls-cert certificate")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .requires("tls-cert")
+                                            .number_of_values(1))
+                                        .arg(Arg::with_name("tls-admin-cert")
+                                            .long("tls-admin-cert")
+                                            .value_name("SHA256_FINGERPRINT")
+                                            .help("Grants admin rights without the key to the TLS clients that present the certificate with this fingerprint, can be repeated")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .multiple(true)
+                                            .number_of_values(1)
+                                            .validator(fingerprint_validator))
+                                        .arg(Arg::with_name("client-subnet")
+                                            .long("client-subnet")
+                                            .value_name("NETWORK/PREFIX")
+                                            .help("Allows client requests only from this network and the loopback, can be repeated. Without it and --auto-subnet any client is allowed")
+                                            .takes_value(true)
+                                            .required(false)
+                                            .multiple(true)
+                                            .number_of_values(1)
+                                            .validator(cidr_validator))
+                                        .arg(Arg::with_name("auto-subnet")
+                                            .long("auto-subnet")
+                                            .help("Allows client requests from the networks of the interfaces the client addresses listen on")
+                                            .multiple(false)
+                                            .required(false))
+                                        .arg(Arg::with_name("force-signup")
+                                            .short("f")
+                                            .long("force-signup")
+                                            .help("When enabled, only the signed up clients can get or drop other clients")
+                                            .multiple(false)
+                                            .required(false)))
+                          .subcommand(SubCommand::with_name("hash-secret")
+                                       .about("Hashes a key or a password, so it can be given to the start command instead of the plaintext")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("secret")
+                                            .value_name("SECRET")
+                                            .help("The key or password to hash")
+                                            .required(true)
+                                            .validator(password_validator)))
+                          .subcommand(SubCommand::with_name("audit")
+                                       .about("Works with the audit log of the admin actions")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .setting(AppSettings::SubcommandRequiredElseHelp)
+                                       .subcommand(SubCommand::with_name("verify")
+                                                    .about("Checks that no entry of the audit log was changed, removed or reordered")
+                                                    .arg(Arg::with_name("file")
+                                                         .value_name("FILE")
+                                                         .help("The audit log given to --audit-log")
+                                                         .required(true))
+                                                    .arg(Arg::with_name("key")
+                                                         .long("key")
+                                                         .value_name("FILE")
+                                                         .help("The key given to --audit-key")
+                                                         .takes_value(true)
+                                                         .required(true))))
                           .get_matches();
 
+    if let Some(hash_matches) = matches.subcommand_matches("hash-secret") {
+        if let Some(secret) = hash_matches.value_of("secret").and_then(secrets::Secret::new_from_str) {
+            println!("{}", secret.to_hash_string());
+            process::exit(0);
+        }
+        eprintln!("I couldn't hash the secret :/");
+        process::exit(1);
+    }
+
+    if let Some(verify_matches) = matches.subcommand_matches("audit").and_then(|m| m.subcommand_matches("verify")) {
+        if let (Some(path), Some(key_path)) = (verify_matches.value_of("file"), verify_matches.value_of("key")) {
+            let key = match audit::read_key(key_path) {
+                Ok(key) => key,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+            match audit::verify(path, &key) {
+                Ok(entries) => {
+                    println!("{} is intact, it has {} entries", path, entries);
+                    process::exit(0);
+                },
+                Err(e) => {
+                    eprintln!("{} is not intact: {}", path, e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+
     let start_command_config: config::StartConfig;
     match config::StartConfig::new(matches) {
         Some(config) => start_command_config = config,
@@ -0,0 +1,147 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module limits how many requests each IP can make, every IP has a token bucket per
+// category of request so hammering the lookups doesn't use up the budget of the sign ups.
+
+use std::collections;
+use std::fmt;
+use std::net;
+use std::time;
+use crate::config;
+
+// When there are more buckets than this, the full ones are forgotten
+const MAX_IDLE_BUCKETS: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RequestCategory {
+    Lookup,
+    Mutation,
+    Admin
+}
+
+impl RequestCategory {
+    pub fn all() -> [RequestCategory; 3] {
+        [RequestCategory::Lookup, RequestCategory::Mutation, RequestCategory::Admin]
+    }
+}
+
+impl fmt::Display for RequestCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestCategory::Lookup => write!(f, "lookup"),
+            RequestCategory::Mutation => write!(f, "mutation"),
+            RequestCategory::Admin => write!(f, "admin")
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: time::Instant
+}
+
+impl TokenBucket {
+    fn refill(&mut self, limit: &config::RateLimit, now: time::Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.tokens_per_second()).min(f64::from(limit.burst));
+        self.last_refill = now;
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Counters {
+    pub allowed: u64,
+    pub limited: u64
+}
+
+pub struct RateLimiter {
+    lookup: Option<config::RateLimit>,
+    mutation: Option<config::RateLimit>,
+    admin: Option<config::RateLimit>,
+    buckets: collections::HashMap<(net::IpAddr, RequestCategory), TokenBucket>,
+    counters: collections::HashMap<RequestCategory, Counters>,
+    limited_by_ip: collections::HashMap<net::IpAddr, u64>
+}
+
+impl RateLimiter {
+    // None means that the category is not limited
+    pub fn new(lookup: Option<config::RateLimit>, mutation: Option<config::RateLimit>, admin: Option<config::RateLimit>) -> RateLimiter {
+        RateLimiter { lookup, mutation, admin, buckets: collections::HashMap::new(), counters: collections::HashMap::new(), limited_by_ip: collections::HashMap::new() }
+    }
+
+    pub fn limit_of(&self, category: RequestCategory) -> Option<config::RateLimit> {
+        match category {
+            RequestCategory::Lookup => self.lookup,
+            RequestCategory::Mutation => self.mutation,
+            RequestCategory::Admin => self.admin
+        }
+    }
+
+    // Takes a token from the bucket of the IP, if there are none it returns how many seconds
+    // the IP has to wait for the next one
+    pub fn check(&mut self, ip: &net::IpAddr, category: RequestCategory, now: time::Instant) -> Result<(), u64> {
+        let limit = match self.limit_of(category) {
+            Some(limit) => limit,
+            None => {
+                self.counters.entry(category).or_default().allowed += 1;
+                return Ok(());
+            }
+        };
+
+        if self.buckets.len() > MAX_IDLE_BUCKETS {
+            self.forget_full_buckets(now);
+        }
+        let bucket = self.buckets.entry((*ip, category)).or_insert(TokenBucket { tokens: f64::from(limit.burst), last_refill: now });
+        bucket.refill(&limit, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            self.counters.entry(category).or_default().allowed += 1;
+            return Ok(());
+        }
+
+        let retry_after = ((1.0 - bucket.tokens) / limit.tokens_per_second()).ceil() as u64;
+        self.counters.entry(category).or_default().limited += 1;
+        if self.limited_by_ip.len() < MAX_IDLE_BUCKETS || self.limited_by_ip.contains_key(ip) {
+            *self.limited_by_ip.entry(*ip).or_insert(0) += 1;
+        }
+        Err(retry_after.max(1))
+    }
+
+    fn forget_full_buckets(&mut self, now: time::Instant) {
+        let (lookup, mutation, admin) = (self.lookup, self.mutation, self.admin);
+        self.buckets.retain(|(_ip, category), bucket| {
+            let limit = match category {
+                RequestCategory::Lookup => lookup,
+                RequestCategory::Mutation => mutation,
+                RequestCategory::Admin => admin
+            };
+            match limit {
+                Some(limit) => {
+                    bucket.refill(&limit, now);
+                    bucket.tokens < f64::from(limit.burst)
+                },
+                None => false
+            }
+        });
+    }
+
+    pub fn counters(&self, category: RequestCategory) -> Counters {
+        self.counters.get(&category).copied().unwrap_or_default()
+    }
+
+    pub fn to_json_string(&self) -> String {
+        let categories = RequestCategory::all().iter().map(|category| {
+            let counters = self.counters(*category);
+            let limit = match self.limit_of(*category) {
+                Some(limit) => format!("\"{}\"", limit),
+                None => String::from("null")
+            };
+            format!("\"{}\":{{\"limit\":{},\"allowed\":{},\"limited\":{}}}", category, limit, counters.allowed, counters.limited)
+        }).collect::<Vec<String>>().join(",");
+
+        let mut limited_by_ip: Vec<(&net::IpAddr, &u64)> = self.limited_by_ip.iter().collect();
+        limited_by_ip.sort_by(|a, b| b.1.cmp(a.1));
+        let limited_by_ip = limited_by_ip.iter().map(|(ip, limited)| format!("{{\"ip_addr\":\"{}\",\"limited\":{}}}", ip, limited)).collect::<Vec<String>>().join(",");
+        format!("{{{},\"limited_ips\":[{}]}}", categories, limited_by_ip)
+    }
+}
@@ -2,8 +2,6 @@
 // Email:  jorge4larcon@gmail.com
 // This module contains the server replies for the incomming clients.
 
-// TODO: to make drop or get the client must be signed up
-
 extern crate log;
 
 use crate::clients;
@@ -11,7 +9,19 @@ use crate::ipparser;
 use std::fmt;
 use std::convert::TryFrom;
 use std::net;
+use std::time;
+use std::mem;
 use crate::server;
+use crate::config;
+use crate::reports;
+use crate::ratelimit;
+use crate::auth;
+use crate::bans;
+use crate::secrets;
+use crate::sessions;
+use crate::accounts;
+use crate::invites;
+use crate::signups;
 
 pub enum ReplyErrCodes {
     ClientDoesNotExist,
@@ -21,7 +31,25 @@ pub enum ReplyErrCodes {
     WrongPassword,
     OnlyIpv4Supported,
     UnparsableRequest,
-    RemoteAdminIsNotAllowed
+    RemoteAdminIsNotAllowed,
+    ClientRequestNotAllowed,
+    ClientIsNotSignedUp,
+    ReportDoesNotExist,
+    RateLimited { retry_after: u64 },
+    LockedOut { retry_after: u64 },
+    Banned { until: Option<u64> },
+    BanDoesNotExist,
+    OutsideClientSubnet,
+    InvalidNonce,
+    AuthMethodNotAllowed,
+    SessionDoesNotExist,
+    InsufficientRole,
+    AccountDoesNotExist,
+    AccountAlreadyExists,
+    WrongOwnerToken,
+    NotClientOwner,
+    InviteDoesNotExist,
+    SignupDoesNotExist
 }
 
 impl fmt::Display for ReplyErrCodes {
@@ -34,31 +62,57 @@ impl fmt::Display for ReplyErrCodes {
             ReplyErrCodes::WrongPassword => write!(f, "{{\"error\":5,\"name\":\"WrongPassword\"}}"),
             ReplyErrCodes::OnlyIpv4Supported => write!(f, "{{\"error\":6,\"name\":\"OnlyIPv4Supported\"}}"),
             ReplyErrCodes::UnparsableRequest => write!(f, "{{\"error\":7,\"name\":\"UnparsableRequest\"}}"),
-            ReplyErrCodes::RemoteAdminIsNotAllowed => write!(f, "{{\"error\":8,\"name\":\"RemoteAdminIsNotAllowed\"}}")
+            ReplyErrCodes::RemoteAdminIsNotAllowed => write!(f, "{{\"error\":8,\"name\":\"RemoteAdminIsNotAllowed\"}}"),
+            ReplyErrCodes::ClientRequestNotAllowed => write!(f, "{{\"error\":9,\"name\":\"ClientRequestNotAllowed\"}}"),
+            ReplyErrCodes::ClientIsNotSignedUp => write!(f, "{{\"error\":10,\"name\":\"ClientIsNotSignedUp\"}}"),
+            ReplyErrCodes::ReportDoesNotExist => write!(f, "{{\"error\":11,\"name\":\"ReportDoesNotExist\"}}"),
+            ReplyErrCodes::RateLimited { retry_after } => write!(f, "{{\"error\":12,\"name\":\"RateLimited\",\"retry_after\":{}}}", retry_after),
+            ReplyErrCodes::LockedOut { retry_after } => write!(f, "{{\"error\":13,\"name\":\"LockedOut\",\"retry_after\":{}}}", retry_after),
+            ReplyErrCodes::Banned { until: Some(until) } => write!(f, "{{\"error\":14,\"name\":\"Banned\",\"until\":{}}}", until),
+            ReplyErrCodes::Banned { until: None } => write!(f, "{{\"error\":14,\"name\":\"Banned\",\"until\":null}}"),
+            ReplyErrCodes::BanDoesNotExist => write!(f, "{{\"error\":15,\"name\":\"BanDoesNotExist\"}}"),
+            ReplyErrCodes::OutsideClientSubnet => write!(f, "{{\"error\":16,\"name\":\"OutsideClientSubnet\"}}"),
+            ReplyErrCodes::InvalidNonce => write!(f, "{{\"error\":17,\"name\":\"InvalidNonce\"}}"),
+            ReplyErrCodes::AuthMethodNotAllowed => write!(f, "{{\"error\":18,\"name\":\"AuthMethodNotAllowed\"}}"),
+            ReplyErrCodes::SessionDoesNotExist => write!(f, "{{\"error\":19,\"name\":\"SessionDoesNotExist\"}}"),
+            ReplyErrCodes::InsufficientRole => write!(f, "{{\"error\":20,\"name\":\"InsufficientRole\"}}"),
+            ReplyErrCodes::AccountDoesNotExist => write!(f, "{{\"error\":21,\"name\":\"AccountDoesNotExist\"}}"),
+            ReplyErrCodes::AccountAlreadyExists => write!(f, "{{\"error\":22,\"name\":\"AccountAlreadyExists\"}}"),
+            ReplyErrCodes::WrongOwnerToken => write!(f, "{{\"error\":23,\"name\":\"WrongOwnerToken\"}}"),
+            ReplyErrCodes::NotClientOwner => write!(f, "{{\"error\":24,\"name\":\"NotClientOwner\"}}"),
+            ReplyErrCodes::InviteDoesNotExist => write!(f, "{{\"error\":25,\"name\":\"InviteDoesNotExist\"}}"),
+            ReplyErrCodes::SignupDoesNotExist => write!(f, "{{\"error\":26,\"name\":\"SignupDoesNotExist\"}}")
         }
     }
 }
 
-pub fn reply_admin_drop(ip: &net::Ipv4Addr, clients_map: &mut clients::ClientsMap, guilty: &net::SocketAddrV4) -> String {
-    let ipv4 = ipparser::ipv4addr_to_u32(ip);
-    if clients_map.exists_by_ipv4(ipv4) {
-        if clients_map.drop_by_ipv4(ipv4) {
+// The client dropped out loses the approval of its sign up
+pub fn reply_admin_drop(ip: &net::IpAddr, clients_map: &mut clients::ClientsMap, signup_queue: &mut signups::SignupQueue, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(mac) = clients_map.get_mac_by_ip(ip) {
+        if let Some(client) = clients_map.drop_by_mac(&mac) {
+            signup_queue.revoke(&mac);
             log::info!("The admin {} dropped out the client {}", guilty, ip);
-            return format!("{{\"result\":\"Client was dropped out\"}}");
+            // The client goes in the reply, so the audit log keeps what was dropped out
+            format!("{{\"result\":\"Client was dropped out\",\"client\":{}}}", client.to_json_string_with_mac(&mac))
         } else {            
             log::error!("The admin {} wanted to drop the client {}, the client exists but was not dropped out", guilty, ip);
-            return format!("{{\"result\":\"Client was not dropped out\"}}");
+            String::from("{\"result\":\"Client was not dropped out\"}")
         }
     } else {
         log::info!("The client {} doesn't exist but the admin {} tried to drop it", ip, guilty);
-        return format!("{}", ReplyErrCodes::ClientDoesNotExist);
+        format!("{}", ReplyErrCodes::ClientDoesNotExist)
     }
 }
 
-pub fn reply_admin_setdropvotes(new_dv: u8, server_dv: &mut u8, clients_map: &mut clients::ClientsMap, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_admin_setdropvotes(new_dv: u8, server_dv: &mut u8, server_dq: &mut Option<config::DropQuorum>, clients_map: &mut clients::ClientsMap, guilty: &accounts::ActingAdmin) -> String {
     if new_dv > 0 {
         *server_dv = new_dv;
-        let dropped_clients = clients_map.drop_amount(*server_dv);
+        // A fixed drop-votes value replaces the drop-quorum
+        if server_dq.is_some() {
+            *server_dq = None;
+            log::info!("The admin {} disabled the drop-quorum", guilty);
+        }
+        let dropped_clients = clients_map.drop_amount(u16::from(*server_dv));
         let mut list_of_dropped_clients = String::default();
         for (index, (mac, client)) in dropped_clients.iter().enumerate() {
             list_of_dropped_clients.push_str(&format!("[{}] {} {}\n", index, mac, client));
@@ -83,7 +137,305 @@ pub fn reply_admin_setdropvotes(new_dv: u8, server_dv: &mut u8, clients_map: &mu
     }
 }
 
-pub fn reply_admin_setdropverification(new_dv: bool, server_dv: &mut bool, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_admin_setdropquorum(new_dq: u8, min_votes: Option<u16>, server_dq: &mut Option<config::DropQuorum>, clients_map: &mut clients::ClientsMap, guilty: &accounts::ActingAdmin) -> String {
+    if new_dq == 0 {
+        *server_dq = None;
+        log::info!("The admin {} disabled the drop-quorum, the fixed drop-votes value is used again", guilty);
+        return String::from("{\"result\":\"The drop-quorum has been disabled, the drop-votes value is used again\",\"dropped_clients\":[]}");
+    }
+    // Without min_votes the actual minimum is kept
+    let min_votes = min_votes.unwrap_or_else(|| server_dq.map(|dq| dq.min_votes).unwrap_or(2));
+    if let Some(drop_quorum) = config::DropQuorum::new(new_dq, min_votes) {
+        *server_dq = Some(drop_quorum);
+        let drop_votes = drop_quorum.drop_votes(clients_map.len());
+        let dropped_clients = clients_map.drop_amount(drop_votes);
+        let clients_json_array = dropped_clients.iter().map(|(mac, client)| client.to_json_string_with_mac(mac)).collect::<Vec<String>>().join(",");
+
+        log::warn!("The admin {} set the drop-quorum to {}, {} vote(s) are needed now and {} client(s) were dropped out", guilty, drop_quorum, drop_votes, dropped_clients.len());
+        return format!("{{\"result\":\"The drop-quorum has been set to {}, {} vote(s) are needed now\",\"dropped_clients\":[{}]}}", drop_quorum, drop_votes, clients_json_array);
+    }
+    log::warn!("The admin {} tried to set the drop-quorum to {}%", guilty, new_dq);
+    String::from("{\"result\":\"The drop-quorum must be in the range of [1,100]\",\"dropped_clients\":[]}")
+}
+
+pub fn reply_admin_setreportqueue(new_rq: bool, server_rq: &mut bool, guilty: &accounts::ActingAdmin) -> String {
+    *server_rq = new_rq;
+    if *server_rq {
+        log::info!("The admin {} enabled the report-queue", guilty);
+        return String::from("{\"result\":\"The report-queue has been enabled, the clients report each other instead of voting\"}");
+    }
+    log::info!("The admin {} disabled the report-queue", guilty);
+    String::from("{\"result\":\"The report-queue has been disabled, the clients vote to drop each other out\"}")
+}
+
+pub fn reply_admin_getbans(ban_list: &bans::BanList, guilty: &accounts::ActingAdmin) -> String {
+    log::info!("The admin {} asked for the {} ban(s)", guilty, ban_list.len());
+    format!("{{\"bans\":{}}}", ban_list.to_json_string())
+}
+
+// The banned clients that are signed up are dropped out too, and with the banned MAC they lose
+// the approval of their sign ups
+pub fn reply_admin_ban(target: bans::BanTarget, until: Option<u64>, reason: &str, ban_list: &mut bans::BanList, clients_map: &mut clients::ClientsMap, signup_queue: &mut signups::SignupQueue, guilty: &accounts::ActingAdmin) -> String {
+    let dropped_clients = clients_map.drop_matching(|mac, client| target.matches(Some(mac), &client.get_ip_addr()));
+    for (mac, _client) in dropped_clients.iter() {
+        signup_queue.revoke(mac);
+    }
+    if let bans::BanTarget::Mac(mac) = &target {
+        signup_queue.revoke(mac);
+    }
+    let clients_json_array = dropped_clients.iter().map(|(mac, client)| client.to_json_string_with_mac(mac)).collect::<Vec<String>>().join(",");
+    match until {
+        Some(until) => log::warn!("The admin {} banned {} until {} ({:?}), {} client(s) were dropped out", guilty, target, until, reason, dropped_clients.len()),
+        None => log::warn!("The admin {} banned {} permanently ({:?}), {} client(s) were dropped out", guilty, target, reason, dropped_clients.len())
+    }
+    let id = ban_list.ban(target, until, reason);
+    format!("{{\"result\":\"Banned\",\"ban\":{},\"dropped_clients\":[{}]}}", id, clients_json_array)
+}
+
+pub fn reply_admin_liftban(id: u64, ban_list: &mut bans::BanList, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(ban) = ban_list.lift(id) {
+        log::info!("The admin {} lifted the ban {} of {}", guilty, id, ban.target);
+        return String::from("{\"result\":\"The ban was lifted\"}");
+    }
+    log::info!("The admin {} tried to lift the ban {}, but it doesn't exist", guilty, id);
+    ReplyErrCodes::BanDoesNotExist.to_string()
+}
+
+pub fn reply_admin_getinvites(invite_list: &invites::InviteList, guilty: &accounts::ActingAdmin) -> String {
+    log::info!("The admin {} asked for the {} invite(s)", guilty, invite_list.len());
+    format!("{{\"invites\":{}}}", invite_list.to_json_string())
+}
+
+// The code is only shown here, the server keeps its digest
+pub fn reply_admin_mintinvite(uses: u32, expires_at: Option<u64>, invite_list: &mut invites::InviteList, guilty: &accounts::ActingAdmin) -> String {
+    if let Some((id, code)) = invite_list.mint(uses, expires_at, &guilty.name, clients::unix_now()) {
+        match expires_at {
+            Some(expires_at) => log::info!("The admin {} minted the invite {} for {} client(s) until {}", guilty, id, uses, expires_at),
+            None => log::info!("The admin {} minted the invite {} for {} client(s)", guilty, id, uses)
+        }
+        return format!("{{\"result\":\"Invite minted\",\"invite\":{},\"code\":\"{}\"}}", id, code);
+    }
+    log::error!("The admin {} tried to mint an invite, but there was an internal error generating the code", guilty);
+    ReplyErrCodes::ServerInternalError.to_string()
+}
+
+// The clients that the invite admitted stay, but they can't use its code anymore
+pub fn reply_admin_revokeinvite(id: u64, invite_list: &mut invites::InviteList, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(invite) = invite_list.revoke(id) {
+        log::info!("The admin {} revoked the invite {}, it admitted {} client(s)", guilty, id, invite.uses);
+        return String::from("{\"result\":\"The invite was revoked\"}");
+    }
+    log::info!("The admin {} tried to revoke the invite {}, but it doesn't exist", guilty, id);
+    ReplyErrCodes::InviteDoesNotExist.to_string()
+}
+
+pub fn reply_admin_getsignups(signup_queue: &signups::SignupQueue, guilty: &accounts::ActingAdmin) -> String {
+    let pending = signup_queue.pending();
+    log::info!("The admin {} asked for the {} pending sign up(s)", guilty, pending.len());
+    format!("{{\"signups\":{}}}", signups::signups_to_json_array(&pending))
+}
+
+// The lease starts again, the owner may have waited for the approval longer than it
+#[allow(clippy::too_many_arguments)]
+pub fn reply_admin_approvesignup(id: u64, signup_queue: &mut signups::SignupQueue, clients_map: &mut clients::ClientsMap, ban_list: &bans::BanList, capacity: u16, lease: u64, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(signup) = signup_queue.get(id).filter(|s| s.status == signups::SignupStatus::Pending) {
+        if let Some(ban) = ban_list.banned_client(&signup.mac, &signup.client.get_ip_addr()) {
+            log::info!("The admin {} tried to approve the sign up {}, but {} is banned by the ban {}", guilty, id, signup.mac, ban.id);
+            return ReplyErrCodes::Banned { until: ban.until }.to_string();
+        }
+        if clients_map.len() >= usize::from(capacity) && !clients_map.exists_by_ip(&signup.client.get_ip_addr()) && !clients_map.exists_by_mac(&signup.mac) {
+            log::info!("The admin {} tried to approve the sign up {}, but the server capacity ({} clients) is full", guilty, id, capacity);
+            return ReplyErrCodes::ServerCapacityIsFull.to_string();
+        }
+    }
+    if let Some(mut signup) = signup_queue.approve(id) {
+        if let Some(owner) = signup.client.owner.as_mut() {
+            owner.renew(clients::unix_now(), lease);
+        }
+        clients_map.insert(&signup.mac, &signup.client);
+        log::info!("The admin {} approved the sign up {} of {} {}", guilty, id, signup.mac, signup.client);
+        return format!("{{\"result\":\"The sign up was approved\",\"client\":{}}}", signup.client.to_json_string_with_mac(&signup.mac));
+    }
+    log::info!("The admin {} tried to approve the sign up {}, but it's not pending", guilty, id);
+    ReplyErrCodes::SignupDoesNotExist.to_string()
+}
+
+pub fn reply_admin_rejectsignup(id: u64, signup_queue: &mut signups::SignupQueue, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(signup) = signup_queue.reject(id) {
+        log::info!("The admin {} rejected the sign up {} of {} {}", guilty, id, signup.mac, signup.client);
+        return String::from("{\"result\":\"The sign up was rejected\"}");
+    }
+    log::info!("The admin {} tried to reject the sign up {}, but it's not pending", guilty, id);
+    ReplyErrCodes::SignupDoesNotExist.to_string()
+}
+
+pub fn reply_admin_getlockouts(auth_guard: &auth::AuthGuard, guilty: &accounts::ActingAdmin) -> String {
+    log::info!("The admin {} asked for the locked out addresses", guilty);
+    format!("{{\"lockouts\":{}}}", auth_guard.lockouts_to_json_string(time::Instant::now()))
+}
+
+pub fn reply_admin_login(sessions: &mut sessions::SessionStore, guilty: &accounts::ActingAdmin) -> String {
+    if let Some((id, token)) = sessions.login(&ipparser::unmap_ipv4(&guilty.peer_addr.ip()), &guilty.name, clients::unix_now()) {
+        log::info!("The admin {} logged in, its session is {}", guilty, id);
+        format!("{{\"result\":\"logged in\",\"session\":{},\"token\":\"{}\",\"expires_in\":{}}}", id, token, sessions.ttl())
+    } else {
+        log::error!("The admin {} tried to log in, but there was an internal error generating the token", guilty);
+        format!("{}", ReplyErrCodes::ServerInternalError)
+    }
+}
+
+pub fn reply_admin_logout(sessions: &mut sessions::SessionStore, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(id) = guilty.session {
+        sessions.revoke(id);
+        log::info!("The admin {} logged out of the session {}", guilty, id);
+        format!("{{\"result\":\"The session {} has been logged out\"}}", id)
+    } else {
+        log::info!("The admin {} tried to log out, but it didn't send the token of a session", guilty);
+        format!("{}", ReplyErrCodes::SessionDoesNotExist)
+    }
+}
+
+pub fn reply_admin_getsessions(sessions: &mut sessions::SessionStore, guilty: &accounts::ActingAdmin) -> String {
+    sessions.expire(clients::unix_now());
+    log::info!("The admin {} asked for the admin sessions", guilty);
+    format!("{{\"sessions\":{}}}", sessions.to_json_string())
+}
+
+pub fn reply_admin_revokesession(id: u64, sessions: &mut sessions::SessionStore, guilty: &accounts::ActingAdmin) -> String {
+    if sessions.revoke(id) {
+        log::info!("The admin {} revoked the session {}", guilty, id);
+        format!("{{\"result\":\"The session {} has been revoked\"}}", id)
+    } else {
+        log::info!("The admin {} tried to revoke the session {}, but it doesn't exist", guilty, id);
+        format!("{}", ReplyErrCodes::SessionDoesNotExist)
+    }
+}
+
+pub fn reply_admin_getaccounts(accounts_store: &accounts::AccountStore, guilty: &accounts::ActingAdmin) -> String {
+    log::info!("The admin {} asked for the {} admin account(s)", guilty, accounts_store.len());
+    format!("{{\"accounts\":{}}}", accounts_store.to_json_string())
+}
+
+// The secret of the new account is never logged nor sent back
+pub fn reply_admin_addaccount(name: &str, role: accounts::AdminRole, secret: &str, accounts_store: &mut accounts::AccountStore, guilty: &accounts::ActingAdmin) -> String {
+    if name == accounts::BUILTIN_ADMIN || accounts_store.get(name).is_some() {
+        log::info!("The admin {} tried to add the account {}, but it already exists", guilty, name);
+        return ReplyErrCodes::AccountAlreadyExists.to_string();
+    }
+    if let Some(secret) = secrets::Secret::new_from_str(secret) {
+        accounts_store.add(accounts::Account::new(name, role, secret));
+        log::info!("The admin {} added the {} account {}", guilty, role, name);
+        return format!("{{\"result\":\"The account has been added\",\"account\":{{\"name\":\"{}\",\"role\":\"{}\"}}}}", name, role);
+    }
+    log::error!("The admin {} tried to add the account {}, but there was an internal error hashing its secret", guilty, name);
+    ReplyErrCodes::ServerInternalError.to_string()
+}
+
+// The sessions of the account are logged out with it
+pub fn reply_admin_removeaccount(name: &str, accounts_store: &mut accounts::AccountStore, sessions: &mut sessions::SessionStore, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(account) = accounts_store.remove(name) {
+        let logged_out = sessions.revoke_account(name);
+        log::info!("The admin {} removed the account {}, {} session(s) were logged out", guilty, account, logged_out);
+        return String::from("{\"result\":\"The account has been removed\"}");
+    }
+    log::info!("The admin {} tried to remove the account {}, but it doesn't exist", guilty, name);
+    ReplyErrCodes::AccountDoesNotExist.to_string()
+}
+
+pub fn reply_admin_setaccountrole(name: &str, role: accounts::AdminRole, accounts_store: &mut accounts::AccountStore, guilty: &accounts::ActingAdmin) -> String {
+    if accounts_store.set_role(name, role) {
+        log::info!("The admin {} gave the {} role to the account {}", guilty, role, name);
+        return format!("{{\"result\":\"The role of the account has been changed to {}\"}}", role);
+    }
+    log::info!("The admin {} tried to change the role of the account {}, but it doesn't exist", guilty, name);
+    ReplyErrCodes::AccountDoesNotExist.to_string()
+}
+
+// The salt and the iterations let the client derive its client key from its secret
+// While an old secret is still accepted its salt goes too, the clients that haven't changed it
+// sign with it
+pub fn reply_challenge(nonce: &str, salt: &str, iterations: u32, old_salt: Option<(String, u32)>, kind: auth::SecretKind, guilty: &net::SocketAddr) -> String {
+    log::info!("{} was given a nonce to sign a request with the {}", guilty, kind);
+    match old_salt {
+        Some((old_salt, old_iterations)) => format!("{{\"result\":\"challenge\",\"nonce\":\"{}\",\"salt\":\"{}\",\"iterations\":{},\"old_salt\":\"{}\",\"old_iterations\":{},\"expires_in\":{}}}", nonce, salt, iterations, old_salt, old_iterations, auth::NONCE_TTL.as_secs()),
+        None => format!("{{\"result\":\"challenge\",\"nonce\":\"{}\",\"salt\":\"{}\",\"iterations\":{},\"expires_in\":{}}}", nonce, salt, iterations, auth::NONCE_TTL.as_secs())
+    }
+}
+
+pub fn reply_admin_getratelimits(rate_limiter: &ratelimit::RateLimiter, guilty: &accounts::ActingAdmin) -> String {
+    log::info!("The admin {} asked for the rate limits", guilty);
+    format!("{{\"rate_limits\":{}}}", rate_limiter.to_json_string())
+}
+
+pub fn reply_admin_getreports(reports_queue: &reports::ReportsQueue, guilty: &accounts::ActingAdmin) -> String {
+    let pending = reports_queue.pending();
+    log::info!("The admin {} asked for the {} pending report(s)", guilty, pending.len());
+    format!("{{\"reports\":{}}}", reports::reports_to_json_array(&pending))
+}
+
+pub fn reply_admin_getreporthistory(mac: &ipparser::MacAddress, reports_queue: &reports::ReportsQueue, guilty: &accounts::ActingAdmin) -> String {
+    let history = reports_queue.history(mac);
+    log::info!("The admin {} asked for the {} report(s) against {}", guilty, history.len(), mac);
+    format!("{{\"reports\":{}}}", reports::reports_to_json_array(&history))
+}
+
+// The reported MAC loses the approval of its sign up, even if it had already left
+pub fn reply_admin_approvereport(id: u64, reports_queue: &mut reports::ReportsQueue, clients_map: &mut clients::ClientsMap, signup_queue: &mut signups::SignupQueue, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(target_mac) = reports_queue.approve(id) {
+        signup_queue.revoke(&target_mac);
+        if let Some(client) = clients_map.drop_by_mac(&target_mac) {
+            log::info!("The admin {} approved the report {} and dropped out the client {} {}", guilty, id, target_mac, client);
+            return format!("{{\"result\":\"Report approved, the client was dropped out\",\"client\":{}}}", client.to_json_string_with_mac(&target_mac));
+        }
+        log::info!("The admin {} approved the report {}, the client {} had already left", guilty, id, target_mac);
+        return String::from("{\"result\":\"Report approved, the client had already left\"}");
+    }
+    log::info!("The admin {} tried to approve the report {}, but it doesn't exist or was already reviewed", guilty, id);
+    ReplyErrCodes::ReportDoesNotExist.to_string()
+}
+
+pub fn reply_admin_dismissreport(id: u64, reports_queue: &mut reports::ReportsQueue, guilty: &accounts::ActingAdmin) -> String {
+    if reports_queue.dismiss(id) {
+        log::info!("The admin {} dismissed the report {}", guilty, id);
+        return String::from("{\"result\":\"Report dismissed\"}");
+    }
+    log::info!("The admin {} tried to dismiss the report {}, but it doesn't exist or was already reviewed", guilty, id);
+    ReplyErrCodes::ReportDoesNotExist.to_string()
+}
+
+pub fn reply_admin_resetvotes(ip: &Option<net::IpAddr>, clients_map: &mut clients::ClientsMap, guilty: &accounts::ActingAdmin) -> String {
+    match ip {
+        Some(ip) => {
+            if clients_map.reset_drop_votes_by_ip(ip) {
+                log::info!("The admin {} reset the drop votes of {}", guilty, ip);
+                String::from("{\"result\":\"The drop votes of the client were reset\"}")
+            } else {
+                log::info!("The client {} doesn't exist but the admin {} tried to reset its drop votes", ip, guilty);
+                ReplyErrCodes::ClientDoesNotExist.to_string()
+            }
+        },
+        None => {
+            let reset = clients_map.reset_all_drop_votes();
+            log::warn!("The admin {} reset the drop votes of every client, {} client(s) had votes", guilty, reset);
+            format!("{{\"result\":\"The drop votes of every client were reset\",\"clients_reset\":{}}}", reset)
+        }
+    }
+}
+
+pub fn reply_admin_setimmunity(ip: &net::IpAddr, immunity: clients::Immunity, clients_map: &mut clients::ClientsMap, guilty: &accounts::ActingAdmin) -> String {
+    if let Some((mac, client)) = clients_map.set_immunity_by_ip(ip, immunity) {
+        match immunity {
+            clients::Immunity::NotImmune => log::info!("The admin {} revoked the immunity of {} {}", guilty, mac, ip),
+            clients::Immunity::Until(until) => log::info!("The admin {} made {} {} immune until {}", guilty, mac, ip, until),
+            clients::Immunity::Permanent => log::info!("The admin {} made {} {} immune permanently", guilty, mac, ip)
+        }
+        return format!("{{\"result\":\"The immunity of the client was updated\",\"client\":{}}}", client.to_json_string_with_mac(&mac));
+    }
+    log::info!("The client {} doesn't exist but the admin {} tried to change its immunity", ip, guilty);
+    ReplyErrCodes::ClientDoesNotExist.to_string()
+}
+
+pub fn reply_admin_setdropverification(new_dv: bool, server_dv: &mut bool, guilty: &accounts::ActingAdmin) -> String {
     *server_dv = new_dv;
     if *server_dv {
         log::info!("The admin {} enabled the drop-verification", guilty);
@@ -93,7 +445,7 @@ pub fn reply_admin_setdropverification(new_dv: bool, server_dv: &mut bool, guilt
     format!("{{\"result\":\"The drop-verification has been set to {}\"}}", server_dv)
 }
 
-pub fn reply_admin_setlistsize(new_list_size: u16, server_list_size: &mut u16, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_admin_setlistsize(new_list_size: u16, server_list_size: &mut u16, guilty: &accounts::ActingAdmin) -> String {
     *server_list_size = new_list_size;
 
     if *server_list_size == 0 {
@@ -105,7 +457,7 @@ pub fn reply_admin_setlistsize(new_list_size: u16, server_list_size: &mut u16, g
     }
 }
 
-pub fn reply_admin_setcapacity(new_capacity: u16, server_capacity: &mut u16, clients_map_len: usize, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_admin_setcapacity(new_capacity: u16, server_capacity: &mut u16, clients_map_len: usize, guilty: &accounts::ActingAdmin) -> String {
     if new_capacity >= 2 {
         *server_capacity = new_capacity;
         if let Ok(clients_map_len) = u16::try_from(clients_map_len) {
@@ -126,21 +478,44 @@ pub fn reply_admin_setcapacity(new_capacity: u16, server_capacity: &mut u16, cli
     }
 }
 
-pub fn reply_admin_setpassword(new_password: &str, server_password: &mut String, guilty: &net::SocketAddrV4) -> String {
-    server_password.clear();
-    server_password.push_str(new_password);
-    log::info!("The admin {} set the password to {}", guilty, server_password);
-    format!("{{\"result\":\"The password has been changed to {}\"}}", server_password)
+// The new secrets are never logged nor sent back, the admin already knows them
+// With a grace period the old password is still accepted for a while, so the clients can change it
+pub fn reply_admin_setpassword(new_password: &str, grace: u64, server_password: &mut secrets::Secret, old_password: &mut Option<secrets::RetiredSecret>, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(new_password) = secrets::Secret::new_from_str(new_password) {
+        let replaced = mem::replace(server_password, new_password);
+        *old_password = secrets::RetiredSecret::new(replaced, clients::unix_now(), grace);
+        if let Some(old_password) = old_password {
+            log::info!("The admin {} changed the password, the old one is accepted until {}", guilty, old_password.until);
+            return format!("{{\"result\":\"The password has been changed\",\"old_accepted_until\":{}}}", old_password.until);
+        }
+        log::info!("The admin {} changed the password", guilty);
+        String::from("{\"result\":\"The password has been changed\"}")
+    } else {
+        log::error!("The admin {} tried to change the password, but there was an internal error hashing it", guilty);
+        format!("{}", ReplyErrCodes::ServerInternalError)
+    }
 }
 
-pub fn reply_admin_setkey(new_key: &str, server_key: &mut String, guilty: &net::SocketAddrV4) -> String {
-    server_key.clear();
-    server_key.push_str(new_key);
-    log::info!("The admin {} set the key to {}", guilty, server_key);
-    format!("{{\"result\":\"The key has been changed to {}\"}}", server_key)
+// Changing the key logs out every admin session, like it did before there were accounts, even if
+// the old key is still accepted for a while
+pub fn reply_admin_setkey(new_key: &str, grace: u64, server_key: &mut secrets::Secret, old_key: &mut Option<secrets::RetiredSecret>, sessions: &mut sessions::SessionStore, guilty: &accounts::ActingAdmin) -> String {
+    if let Some(new_key) = secrets::Secret::new_from_str(new_key) {
+        let replaced = mem::replace(server_key, new_key);
+        *old_key = secrets::RetiredSecret::new(replaced, clients::unix_now(), grace);
+        let logged_out = sessions.revoke_all();
+        if let Some(old_key) = old_key {
+            log::info!("The admin {} changed the key, the old one is accepted until {}, {} admin session(s) were logged out", guilty, old_key.until, logged_out);
+            return format!("{{\"result\":\"The key has been changed\",\"old_accepted_until\":{}}}", old_key.until);
+        }
+        log::info!("The admin {} changed the key, {} admin session(s) were logged out", guilty, logged_out);
+        String::from("{\"result\":\"The key has been changed\"}")
+    } else {
+        log::error!("The admin {} tried to change the key, but there was an internal error hashing it", guilty);
+        format!("{}", ReplyErrCodes::ServerInternalError)
+    }
 }
 
-pub fn reply_admin_getbymac(mac: &ipparser::MacAddress, clients_map: &clients::ClientsMap, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_admin_getbymac(mac: &ipparser::MacAddress, clients_map: &clients::ClientsMap, guilty: &accounts::ActingAdmin) -> String {
     if let Some(client) = clients_map.get_by_mac(mac) {
         log::info!("{} was sent to the admin {}", mac, guilty);
         format!("{{\"result\":\"the client was found\",\"client\":{}}}", client.to_json_string_with_mac(&mac))
@@ -150,7 +525,7 @@ pub fn reply_admin_getbymac(mac: &ipparser::MacAddress, clients_map: &clients::C
     }
 }
 
-pub fn reply_admin_getbyusername(username: &str, clients_map: &clients::ClientsMap, list_size: u16, start_index: usize, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_admin_getbyusername(username: &str, clients_map: &clients::ClientsMap, list_size: u16, start_index: usize, guilty: &accounts::ActingAdmin) -> String {
     if let Ok(list_size) = usize::try_from(list_size) {
         let (clients, end_index) = clients_map.usernames_that_contain_with_macs(start_index, list_size, username);
         if !clients.is_empty() { // This ensures that the vector at least contains 1 element
@@ -174,12 +549,12 @@ pub fn reply_admin_getbyusername(username: &str, clients_map: &clients::ClientsM
     }
 }
 
-pub fn reply_admin_getrunningconfiguration(server: &server::Server, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_admin_getrunningconfiguration(server: &server::Server, guilty: &accounts::ActingAdmin) -> String {
     log::info!("The admin {} asked for the server configuration", guilty);
     format!("{{\"result\": \"running-config\",\"running_config\":\"{}\"}}", server)
 }
 
-pub fn reply_admin_getbyindex(start_index: usize, end_index: usize, clients_map: &clients::ClientsMap, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_admin_getbyindex(start_index: usize, end_index: usize, clients_map: &clients::ClientsMap, guilty: &accounts::ActingAdmin) -> String {
     let clients_range = clients_map.range(start_index, end_index);
     let list_len = clients_range.len();
     if !clients_range.is_empty() { // This ensures that at least the vector contains 1 element
@@ -198,7 +573,7 @@ pub fn reply_admin_getbyindex(start_index: usize, end_index: usize, clients_map:
     }
 }
 
-pub fn reply_client_getbymac(mac: &ipparser::MacAddress, clients_map: &clients::ClientsMap, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_client_getbymac(mac: &ipparser::MacAddress, clients_map: &clients::ClientsMap, guilty: &net::SocketAddr) -> String {
     if let Some(client) = clients_map.get_by_mac(mac) {
         log::info!("{} was sent to {}", mac, guilty);
         format!("{{\"client\":{}}}", client.to_json_string_without_drop_votes_get_only_by_mac())
@@ -208,7 +583,7 @@ pub fn reply_client_getbymac(mac: &ipparser::MacAddress, clients_map: &clients::
     }
 }
 
-pub fn reply_client_getbyusername(username: &str, clients_map: &clients::ClientsMap, list_size: u16, start_index: usize, guilty: &net::SocketAddrV4) -> String {
+pub fn reply_client_getbyusername(username: &str, clients_map: &clients::ClientsMap, list_size: u16, start_index: usize, guilty: &net::SocketAddr) -> String {
     if let Ok(list_size) = usize::try_from(list_size) {
         let (clients, end_index) = clients_map.usernames_that_contain_get_by_mac_only(start_index, list_size, username);
         if !clients.is_empty() { // This ensures that at least the vector contains 1 element
@@ -232,63 +607,227 @@ pub fn reply_client_getbyusername(username: &str, clients_map: &clients::Clients
     }
 }
 
-pub fn reply_client_drop(ip: &net::Ipv4Addr, clients_map: &mut clients::ClientsMap, max_drop_votes: u8, guilty: &net::SocketAddrV4) -> String {
-    let ipv4 = ipparser::ipv4addr_to_u32(ip);
-    if clients_map.exists_by_ipv4(ipv4) {
-        if clients_map.drop_vote_by_ipv4(ipv4, 1, max_drop_votes) {
+pub fn reply_client_drop(ip: &net::IpAddr, clients_map: &mut clients::ClientsMap, max_drop_votes: u16, guilty: &net::SocketAddr) -> String {
+    match clients_map.drop_vote_by_ip(ip, &ipparser::unmap_ipv4(&guilty.ip()), max_drop_votes) {
+        clients::DropVoteResult::Dropped => {
             log::info!("The client {} was dropped out by {}", ip, guilty);
-            return format!("{{\"result\":\"Client was dropped out\"}}");
-        } else {            
+            String::from("{\"result\":\"Client was dropped out\"}")
+        },
+        clients::DropVoteResult::Voted => {
             log::info!("The client {} tried to drop out {}", guilty, ip);
-            return format!("{{\"result\":\"Client was not dropped out\"}}");
+            String::from("{\"result\":\"Client was not dropped out\"}")
+        },
+        clients::DropVoteResult::ClientIsImmune => {
+            log::info!("The client {} tried to drop out {}, but it's immune", guilty, ip);
+            String::from("{\"result\":\"This client is immune, it can't be dropped out\"}")
+        },
+        clients::DropVoteResult::AlreadyVoted => {
+            log::info!("The client {} tried to drop out {} again, the vote was ignored", guilty, ip);
+            String::from("{\"result\":\"You already voted to drop out this client\"}")
+        },
+        clients::DropVoteResult::ClientDoesNotExist => {
+            log::info!("The client {} doesn't exist but {} tried to drop it", ip, guilty);
+            format!("{}", ReplyErrCodes::ClientDoesNotExist)
+        }
+    }
+}
+
+pub fn reply_client_report(ip: &net::IpAddr, clients_map: &clients::ClientsMap, reports_queue: &mut reports::ReportsQueue, reason: reports::ReportReason, text: &str, guilty: &net::SocketAddr) -> String {
+    if let Some(target_mac) = clients_map.get_mac_by_ip(ip) {
+        if let Some(target) = clients_map.get_by_mac(&target_mac) {
+            let reporter_ip = ipparser::unmap_ipv4(&guilty.ip());
+            let reporter = clients::DropVote { voter_ip: reporter_ip, voter_mac: clients_map.get_mac_by_ip(&reporter_ip), cast_at: clients::unix_now() };
+            match reports_queue.report(&target_mac, &target, reporter, reason, text) {
+                reports::ReportResult::Reported { id } => {
+                    log::info!("The client {} reported {} ({}): {:?}, the report {} waits for an admin", guilty, ip, reason, text, id);
+                    return format!("{{\"result\":\"Client was reported, an admin will review it\",\"report\":{}}}", id);
+                },
+                reports::ReportResult::AlreadyReported => {
+                    log::info!("The client {} reported {} again, the report was ignored", guilty, ip);
+                    return String::from("{\"result\":\"You already reported this client\"}");
+                }
+            }
         }
-    } else {
-        log::info!("The client {} doesn't exist but {} tried to drop it", ip, guilty);
-        return format!("{}", ReplyErrCodes::ClientDoesNotExist);
     }
+    log::info!("The client {} doesn't exist but {} tried to report it", ip, guilty);
+    ReplyErrCodes::ClientDoesNotExist.to_string()
 }
 
-pub fn reply_client_signup(clients_map: &mut clients::ClientsMap, username: &str, mac: &ipparser::MacAddress, ip: &net::Ipv4Addr, port: u16, get_only_by_mac: bool, capaciy: u16) -> String {
-    let client = clients::Client { ipv4_addr: ipparser::ipv4addr_to_u32(ip), port, username: String::from(username), get_only_by_mac, drop_votes: 0 };    
+// Only the client with the owner token of a MAC can sign it up again from another IP, that renews
+// the lease. Without a lease the IP of the entry can sign it up again without the token, like it
+// could before the owner tokens. While the lease lasts the token is always needed, and no other
+// MAC can replace the entry from its IP. Any other sign up gets a new token. With the signup-approval the MACs that are not in the directory and were never approved
+// wait in the queue of sign ups
+#[allow(clippy::too_many_arguments)]
+pub fn reply_client_signup(clients_map: &mut clients::ClientsMap, signup_queue: Option<&mut signups::SignupQueue>, username: &str, mac: &ipparser::MacAddress, peer_addr: &net::SocketAddr, port: u16, get_only_by_mac: bool, capaciy: u16, owner_token: Option<&str>, lease: u64) -> String {
+    let ip = &ipparser::unmap_ipv4(&peer_addr.ip());
+    let now = clients::unix_now();
+    let mut owner = None;
+    let waiting_client = signup_queue.as_ref().and_then(|q| q.get_by_mac(mac)).map(|s| s.client.clone());
+    if let Some(existing_client) = clients_map.get_by_mac(mac).or(waiting_client) {
+        if let Some(mut existing_owner) = existing_client.owner.clone() {
+            let has_token = owner_token.map(|t| existing_owner.is_owned_by(t)).unwrap_or(false);
+            let from_its_ip = existing_client.get_ip_addr() == *ip && !existing_owner.is_leased(now);
+            if !has_token && !from_its_ip {
+                log::warn!("{} tried to sign up as {}, but it doesn't have the owner token of the MAC", ip, mac);
+                return ReplyErrCodes::WrongOwnerToken.to_string();
+            }
+            if has_token {
+                existing_owner.renew(now, lease);
+                owner = Some((existing_owner, None));
+            }
+        }
+    }
+    // Signing up another MAC from the IP of a leased record would replace that record
+    if let Some(replaced_mac) = clients_map.get_mac_by_ip(ip).filter(|m| m != mac) {
+        if let Some(replaced_owner) = clients_map.get_by_mac(&replaced_mac).and_then(|c| c.owner).filter(|o| o.is_leased(now)) {
+            if !owner_token.map(|t| replaced_owner.is_owned_by(t)).unwrap_or(false) {
+                log::warn!("{} tried to sign up as {}, but it doesn't have the owner token of {} that has the IP", ip, mac, replaced_mac);
+                return ReplyErrCodes::WrongOwnerToken.to_string();
+            }
+        }
+    }
+    let (owner, token) = match owner {
+        Some(owner) => owner,
+        None => {
+            if let Some((owner, token)) = clients::Ownership::issue(now, lease) {
+                (owner, Some(token))
+            } else {
+                log::error!("The client {} tried to sign up, but there was an internal error generating its owner token", ip);
+                return format!("{}", ReplyErrCodes::ServerInternalError);
+            }
+        }
+    };
+    let ownership = ownership_to_json_fields(&owner, &token);
+    let client = clients::Client { ip_addr: *ip, scope_id: ipparser::scope_id_of(peer_addr), port, username: String::from(username), get_only_by_mac, drop_votes: 0, drop_voters: Vec::new(), immunity: clients::Immunity::NotImmune, owner: Some(owner), invite: None };    
+    if let Some(signup_queue) = signup_queue {
+        if !clients_map.exists_by_mac(mac) && !signup_queue.is_approved(mac) {
+            if let Some(id) = signup_queue.request(mac, &client, now) {
+                log::info!("The client {} {} waits for an admin to approve its sign up {}", mac, client, id);
+                return format!("{{\"result\":\"Your sign up waits for an admin\",\"signup\":{}{}}}", id, ownership);
+            }
+            log::info!("The queue of sign ups is full, client {} was rejected", ip);
+            return format!("{}", ReplyErrCodes::ServerCapacityIsFull);
+        }
+    }
     if let Ok(capaciy) = usize::try_from(capaciy) {
         // If the client was logged we accept the request and update or replace the client, if not
         // we check if it's possible to save another client
-        if clients_map.exists_by_ipv4(ipparser::ipv4addr_to_u32(ip)) || clients_map.exists_by_mac(mac) {
+        if clients_map.exists_by_ip(ip) || clients_map.exists_by_mac(mac) {
             match clients_map.insert(mac, &client) {
                 clients::InsertionType::Insert => {
                     log::info!("New client {} {}", mac, client);
-                    return format!("{{\"result\": \"You have been registered\"}}");
+                    format!("{{\"result\": \"You have been registered\"{}}}", ownership)
                 },
                 clients::InsertionType::Update => {
                     log::info!("The client {} has been updated", mac);
-                    return format!("{{\"result\": \"Your data has been updated\"}}");
+                    format!("{{\"result\": \"Your data has been updated\"{}}}", ownership)
                 },
                 clients::InsertionType::Replace { client_mac_replaced } => {
                     log::info!("The client {} was replaced by {} {}", client_mac_replaced, mac, ip);
-                    return format!("{{\"result\": \"You have been registered\"}}");
+                    format!("{{\"result\": \"You have been registered\"{}}}", ownership)
                 }
             }
         } else if clients_map.len() < capaciy {
             match clients_map.insert(mac, &client) {
                 clients::InsertionType::Insert => {
                     log::info!("New client {} {}", mac, client);
-                    return format!("{{\"result\": \"You have been registered\"}}");
+                    format!("{{\"result\": \"You have been registered\"{}}}", ownership)
                 },
                 clients::InsertionType::Update => {
                     log::info!("The client {} has been updated", mac);
-                    return format!("{{\"result\": \"Your data has been updated\"}}");
+                    format!("{{\"result\": \"Your data has been updated\"{}}}", ownership)
                 },
                 clients::InsertionType::Replace { client_mac_replaced } => {
                     log::info!("The client {} was replaced by {} {}", client_mac_replaced, mac, ip);
-                    return format!("{{\"result\": \"You have been registered\"}}");
+                    format!("{{\"result\": \"You have been registered\"{}}}", ownership)
                 }
             }
         } else {
             log::info!("Server capacity ({} clients) if full, client {} was rejected", capaciy, ip);
-            return format!("{}", ReplyErrCodes::ServerCapacityIsFull);
+            format!("{}", ReplyErrCodes::ServerCapacityIsFull)
         }
     } else {
         log::error!("The client {} tried to sign in, but there was an internal error casting an u16 to an usize and was rejected", ip);
-        return format!("{}", ReplyErrCodes::ServerInternalError);
+        format!("{}", ReplyErrCodes::ServerInternalError)
+    }
+}
+
+// The clients whose sign up waits for an admin ask for it until it's approved or rejected
+pub fn reply_client_signupstatus(mac: &ipparser::MacAddress, clients_map: &clients::ClientsMap, signup_queue: &signups::SignupQueue, peer_addr: &net::SocketAddr) -> String {
+    if clients_map.exists_by_mac(mac) || signup_queue.is_approved(mac) {
+        log::info!("{} asked for the sign up of {}, it's approved", peer_addr, mac);
+        return String::from("{\"status\":\"approved\"}");
+    }
+    if let Some(signup) = signup_queue.get_by_mac(mac) {
+        log::info!("{} asked for the sign up {} of {}, it's {}", peer_addr, signup.id, mac, signup.status);
+        return format!("{{\"status\":\"{}\",\"signup\":{}}}", signup.status, signup.id);
+    }
+    log::info!("{} asked for the sign up of {}, but there is none", peer_addr, mac);
+    ReplyErrCodes::SignupDoesNotExist.to_string()
+}
+
+// A client can change or remove its own entry from the IP of the entry, or from anywhere with
+// the owner token of the MAC
+fn find_owned_client(clients_map: &clients::ClientsMap, mac: Option<&ipparser::MacAddress>, owner_token: Option<&str>, peer_addr: &net::SocketAddr) -> Result<(ipparser::MacAddress, clients::Client), String> {
+    let ip = ipparser::unmap_ipv4(&peer_addr.ip());
+    let mac = match mac {
+        Some(mac) => Some(mac.clone()),
+        None => clients_map.get_mac_by_ip(&ip)
+    };
+    if let Some(mac) = mac {
+        if let Some(client) = clients_map.get_by_mac(&mac) {
+            let has_token = match (&client.owner, owner_token) {
+                (Some(owner), Some(token)) => owner.is_owned_by(token),
+                _ => false
+            };
+            if client.get_ip_addr() == ip || has_token {
+                return Ok((mac, client));
+            }
+            log::warn!("{} tried to change the client {}, but it's not its IP nor has its owner token", peer_addr, mac);
+            return Err(ReplyErrCodes::NotClientOwner.to_string());
+        }
+    }
+    log::info!("{} tried to change its entry, but it's not signed up", peer_addr);
+    Err(ReplyErrCodes::ClientDoesNotExist.to_string())
+}
+
+pub fn reply_client_signout(clients_map: &mut clients::ClientsMap, mac: Option<&ipparser::MacAddress>, owner_token: Option<&str>, peer_addr: &net::SocketAddr) -> String {
+    match find_owned_client(clients_map, mac, owner_token, peer_addr) {
+        Ok((mac, client)) => {
+            clients_map.drop_by_mac(&mac);
+            log::info!("The client {} {} signed out ({})", mac, client, peer_addr);
+            String::from("{\"result\":\"You have been signed out\"}")
+        },
+        Err(reply) => reply
+    }
+}
+
+// The drop votes, the immunity and the lease are kept, only the given fields change
+pub fn reply_client_update(clients_map: &mut clients::ClientsMap, mac: Option<&ipparser::MacAddress>, owner_token: Option<&str>, username: Option<&str>, port: Option<u16>, get_only_by_mac: Option<bool>, peer_addr: &net::SocketAddr) -> String {
+    match find_owned_client(clients_map, mac, owner_token, peer_addr) {
+        Ok((mac, mut client)) => {
+            if let Some(username) = username {
+                client.username = String::from(username);
+            }
+            if let Some(port) = port {
+                client.port = port;
+            }
+            if let Some(get_only_by_mac) = get_only_by_mac {
+                client.get_only_by_mac = get_only_by_mac;
+            }
+            clients_map.insert(&mac, &client);
+            log::info!("The client {} has been updated ({}): {}", mac, peer_addr, client);
+            format!("{{\"result\":\"Your data has been updated\",\"client\":{}}}", client.to_json_string_with_mac_without_drop_votes(&mac))
+        },
+        Err(reply) => reply
+    }
+}
+
+// The token is only sent when it's new, the client keeps it to sign up again
+fn ownership_to_json_fields(owner: &clients::Ownership, token: &Option<String>) -> String {
+    match token {
+        Some(token) => format!(",\"owner_token\":\"{}\",\"lease_until\":{}", token, owner.lease_until),
+        None => format!(",\"lease_until\":{}", owner.lease_until)
     }
 }
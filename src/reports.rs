@@ -0,0 +1,193 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module keeps the abuse reports that the clients send instead of dropping each other out
+// when the report-queue is enabled, so an admin decides what happens with the reported client.
+
+extern crate serde_json;
+
+use std::fmt;
+use std::net;
+use crate::clients;
+use crate::ipparser;
+
+// The free text of a report is cut to this amount of characters
+pub const MAX_REPORT_TEXT_LEN: usize = 280;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReportReason {
+    Spam,
+    Impersonation,
+    Abuse,
+    Other
+}
+
+impl ReportReason {
+    pub fn new_from_str(reason: &str) -> Option<ReportReason> {
+        match reason.to_lowercase().as_str() {
+            "spam" => Some(ReportReason::Spam),
+            "impersonation" => Some(ReportReason::Impersonation),
+            "abuse" => Some(ReportReason::Abuse),
+            "other" => Some(ReportReason::Other),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for ReportReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportReason::Spam => write!(f, "spam"),
+            ReportReason::Impersonation => write!(f, "impersonation"),
+            ReportReason::Abuse => write!(f, "abuse"),
+            ReportReason::Other => write!(f, "other")
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReportStatus {
+    Pending,
+    Approved,
+    Dismissed
+}
+
+impl ReportStatus {
+    pub fn new_from_str(status: &str) -> Option<ReportStatus> {
+        match status {
+            "pending" => Some(ReportStatus::Pending),
+            "approved" => Some(ReportStatus::Approved),
+            "dismissed" => Some(ReportStatus::Dismissed),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for ReportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportStatus::Pending => write!(f, "pending"),
+            ReportStatus::Approved => write!(f, "approved"),
+            ReportStatus::Dismissed => write!(f, "dismissed")
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Report {
+    pub id: u64,
+    pub target_mac: ipparser::MacAddress,
+    pub target_ip: net::IpAddr,
+    pub target_username: String,
+    pub reporter: clients::DropVote,
+    pub reason: ReportReason,
+    pub text: String,
+    pub status: ReportStatus
+}
+
+impl Report {
+    pub fn to_json_string(&self) -> String {
+        // The text comes from the clients, serde_json escapes it
+        let text = serde_json::Value::String(self.text.clone());
+        format!("{{\"id\":{},\"target_mac\":\"{}\",\"target_ip\":\"{}\",\"target_username\":\"{}\",\"reporter\":{},\"reason\":\"{}\",\"text\":{},\"status\":\"{}\"}}",
+            self.id, self.target_mac, self.target_ip, self.target_username, self.reporter.to_json_string(), self.reason, text, self.status)
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<Report> {
+        let id = val.get("id")?.as_u64()?;
+        let target_mac = ipparser::MacAddress::new_from_str(val.get("target_mac")?.as_str()?)?;
+        let target_ip = ipparser::parse_ip(val.get("target_ip")?.as_str()?).ok()?;
+        let target_username = String::from(val.get("target_username")?.as_str()?);
+        let reporter = clients::DropVote::from_json_value(val.get("reporter")?)?;
+        let reason = ReportReason::new_from_str(val.get("reason")?.as_str()?)?;
+        let text = String::from(val.get("text")?.as_str()?);
+        let status = ReportStatus::new_from_str(val.get("status")?.as_str()?)?;
+        Some(Report { id, target_mac, target_ip, target_username, reporter, reason, text, status })
+    }
+}
+
+pub enum ReportResult {
+    Reported { id: u64 },
+    AlreadyReported
+}
+
+// Every report is kept, once reviewed it stays as part of the history of its target
+pub struct ReportsQueue {
+    next_id: u64,
+    reports: Vec<Report>
+}
+
+impl ReportsQueue {
+    pub fn new() -> ReportsQueue {
+        ReportsQueue { next_id: 1, reports: Vec::new() }
+    }
+
+    // A reporter can have only one pending report against the same client
+    pub fn report(&mut self, target_mac: &ipparser::MacAddress, target: &clients::Client, reporter: clients::DropVote, reason: ReportReason, text: &str) -> ReportResult {
+        if self.reports.iter().any(|r| r.status == ReportStatus::Pending && r.target_mac == *target_mac && r.reporter.is_from(&reporter)) {
+            return ReportResult::AlreadyReported;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let text: String = text.chars().take(MAX_REPORT_TEXT_LEN).collect();
+        self.reports.push(Report { id, target_mac: target_mac.clone(), target_ip: target.get_ip_addr(), target_username: target.username.clone(), reporter, reason, text, status: ReportStatus::Pending });
+        ReportResult::Reported { id }
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Report> {
+        self.reports.iter().find(|r| r.id == id)
+    }
+
+    pub fn pending(&self) -> Vec<&Report> {
+        self.reports.iter().filter(|r| r.status == ReportStatus::Pending).collect()
+    }
+
+    pub fn history(&self, target_mac: &ipparser::MacAddress) -> Vec<&Report> {
+        self.reports.iter().filter(|r| r.target_mac == *target_mac).collect()
+    }
+
+    // Approving a report settles every pending report against the same client, the reports that
+    // were already reviewed keep their status
+    pub fn approve(&mut self, id: u64) -> Option<ipparser::MacAddress> {
+        let target_mac = self.get(id).filter(|r| r.status == ReportStatus::Pending)?.target_mac.clone();
+        for report in self.reports.iter_mut() {
+            if report.target_mac == target_mac && report.status == ReportStatus::Pending {
+                report.status = ReportStatus::Approved;
+            }
+        }
+        Some(target_mac)
+    }
+
+    pub fn dismiss(&mut self, id: u64) -> bool {
+        if let Some(report) = self.reports.iter_mut().find(|r| r.id == id && r.status == ReportStatus::Pending) {
+            report.status = ReportStatus::Dismissed;
+            return true;
+        }
+        false
+    }
+
+    pub fn to_json_string(&self) -> String {
+        format!("[{}]", self.reports.iter().map(|r| r.to_json_string()).collect::<Vec<String>>().join(","))
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<ReportsQueue> {
+        let mut reports_queue = ReportsQueue::new();
+        for report in val.as_array()? {
+            let report = Report::from_json_value(report)?;
+            if report.id >= reports_queue.next_id {
+                reports_queue.next_id = report.id + 1;
+            }
+            reports_queue.reports.push(report);
+        }
+        Some(reports_queue)
+    }
+}
+
+impl Default for ReportsQueue {
+    fn default() -> Self {
+        ReportsQueue::new()
+    }
+}
+
+pub fn reports_to_json_array(reports: &[&Report]) -> String {
+    format!("[{}]", reports.iter().map(|r| r.to_json_string()).collect::<Vec<String>>().join(","))
+}
@@ -10,11 +10,18 @@ use std::fmt;
 use crate::ipparser;
 use crate::clients;
 use crate::server;
+use crate::reports;
+use crate::ratelimit;
+use crate::bans;
+use crate::auth;
+use crate::secrets;
+use crate::accounts;
 use std::convert::TryFrom;
 
 
 pub enum Request {
-    Admin(AdminRequest),
+    // The name of the admin account and its request
+    Admin(String, AdminRequest),
     Client(ClientRequest)
 }
 
@@ -23,10 +30,11 @@ impl Request {
         if let Ok(request) = serde_json::from_str::<serde_json::Value>(request_str) {
             let password;
             let user;
-            if let Some(pass) = request.get("password") {
+            // The admins that logged in send their session token instead of the key
+            if let Some(pass) = request.get("password").or_else(|| request.get("token")) {
                 if let Some(pass) = pass.as_str() {
                     password = String::from(pass);
-                    log::debug!("Request::from - password obtained ({})", password);
+                    log::debug!("Request::from - password obtained");
                     if let Some(usr) = request.get("user") {
                         if let Some(usr) = usr.as_str() {
                             match usr.to_lowercase().as_str() {
@@ -37,6 +45,19 @@ impl Request {
                                 }
                             }
                             log::debug!("Request::from - user obtained ({})", user);
+                            // The admins without an account use the key, the built-in account
+                            let account = match request.get("account") {
+                                Some(account) => {
+                                    match account.as_str() {
+                                        Some(account) if accounts::is_valid_account_name(account) => String::from(account),
+                                        _ => {
+                                            log::debug!("Request::from - incorrect account");
+                                            return None;
+                                        }
+                                    }
+                                },
+                                None => String::from(accounts::BUILTIN_ADMIN)
+                            };
                             if let Some(method) = request.get("method") {
                                 if let Some(method) = method.as_str() {
                                     match method {
@@ -54,7 +75,7 @@ impl Request {
                                                                         if let Some(mac) = ipparser::MacAddress::new_from_str(mac) {
                                                                             log::debug!("Request::from - parsing request: AdminRequest::GetByMac mac obtained ({})", mac);
                                                                             log::debug!("Request::from - parsed request: AdminRequest::GetByMac");
-                                                                            return Some(Request::Admin(AdminRequest::GetByMac { password, mac} ));
+                                                                            return Some(Request::Admin(account, AdminRequest::GetByMac { password, mac} ));
                                                                         } else { log::debug!("Request::from - parsing request: AdminRequest::GetByMac incorrect mac ({})", mac) };
                                                                     } else { log::debug!("Request::from - parsing request: AdminRequest::GetByMac mac not obtained") };
                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::GetByMac mac not obtained") };
@@ -72,7 +93,7 @@ impl Request {
                                                                                         log::debug!("Request::from - parsing request: AdminRequest::GetByUsername start_index obtained ({})", start_index);
                                                                                         let username = String::from(username);
                                                                                         log::debug!("Request::from - parsed request: AdminRequest::GetByUsername");
-                                                                                        return Some(Request::Admin(AdminRequest::GetByUsername { password, username, start_index } ))
+                                                                                        return Some(Request::Admin(account, AdminRequest::GetByUsername { password, username, start_index } ))
                                                                                     } else { log::debug!("Request::from - parsing request: AdminRequest::GetByUsername incorrect start_index ({})", start_index); }
                                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::GetByUsername start_index not obtained"); }
                                                                             } else { log::debug!("Request::from - parsing request: AdminRequest::GetByUsername start_index not obtained"); }
@@ -90,7 +111,7 @@ impl Request {
                                                                                 if let Some(end_index) = end_index.as_u64() {
                                                                                     if let Ok(end_index) = usize::try_from(end_index) {
                                                                                         log::debug!("Request::from - parsed request: AdminRequest::GetByIndex");
-                                                                                        return Some(Request::Admin(AdminRequest::GetByIndex { password, start_index, end_index } ))
+                                                                                        return Some(Request::Admin(account, AdminRequest::GetByIndex { password, start_index, end_index } ))
                                                                                     } else { log::debug!("Request::from - parsing request: AdminRequest::GetByIndex incorrect end_index ({})", end_index); }
                                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::GetByIndex end_index not obtained"); }
                                                                             } else { log::debug!("Request::from - parsing request: AdminRequest::GetByIndex end_index not obtained"); }
@@ -101,7 +122,49 @@ impl Request {
                                                             "running_configuration" => { // AdminRequest::GetActualConfiguration
                                                                 log::debug!("Request::from - parsing request: AdminRequest::GetByIndex how obtained (actual_configuration)");
                                                                 log::debug!("Request::from - parsed request: AdminRequest::GetActualConfiguration");
-                                                                return Some(Request::Admin(AdminRequest::GetRunningConfiguration { password } ))
+                                                                return Some(Request::Admin(account, AdminRequest::GetRunningConfiguration { password } ))
+                                                            },
+                                                            "bans" => { // AdminRequest::GetBans
+                                                                log::debug!("Request::from - parsed request: AdminRequest::GetBans");
+                                                                return Some(Request::Admin(account, AdminRequest::GetBans { password } ))
+                                                            },
+                                                            "signups" => { // AdminRequest::GetSignups
+                                                                log::debug!("Request::from - parsed request: AdminRequest::GetSignups");
+                                                                return Some(Request::Admin(account, AdminRequest::GetSignups { password } ))
+                                                            },
+                                                            "invites" => { // AdminRequest::GetInvites
+                                                                log::debug!("Request::from - parsed request: AdminRequest::GetInvites");
+                                                                return Some(Request::Admin(account, AdminRequest::GetInvites { password } ))
+                                                            },
+                                                            "accounts" => { // AdminRequest::GetAccounts
+                                                                log::debug!("Request::from - parsed request: AdminRequest::GetAccounts");
+                                                                return Some(Request::Admin(account, AdminRequest::GetAccounts { password } ))
+                                                            },
+                                                            "sessions" => { // AdminRequest::GetSessions
+                                                                log::debug!("Request::from - parsed request: AdminRequest::GetSessions");
+                                                                return Some(Request::Admin(account, AdminRequest::GetSessions { password } ))
+                                                            },
+                                                            "lockouts" => { // AdminRequest::GetLockouts
+                                                                log::debug!("Request::from - parsed request: AdminRequest::GetLockouts");
+                                                                return Some(Request::Admin(account, AdminRequest::GetLockouts { password } ))
+                                                            },
+                                                            "rate_limits" => { // AdminRequest::GetRateLimits
+                                                                log::debug!("Request::from - parsed request: AdminRequest::GetRateLimits");
+                                                                return Some(Request::Admin(account, AdminRequest::GetRateLimits { password } ))
+                                                            },
+                                                            "reports" => { // AdminRequest::GetReports
+                                                                log::debug!("Request::from - parsed request: AdminRequest::GetReports");
+                                                                return Some(Request::Admin(account, AdminRequest::GetReports { password } ))
+                                                            },
+                                                            "report_history" => { // AdminRequest::GetReportHistory
+                                                                if let Some(mac) = request.get("mac") {
+                                                                    if let Some(mac) = mac.as_str() {
+                                                                        if let Some(mac) = ipparser::MacAddress::new_from_str(mac) {
+                                                                            log::debug!("Request::from - parsed request: AdminRequest::GetReportHistory");
+                                                                            return Some(Request::Admin(account, AdminRequest::GetReportHistory { password, mac } ));
+                                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::GetReportHistory incorrect mac ({})", mac) };
+                                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::GetReportHistory mac not obtained") };
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::GetReportHistory mac not obtained") };
                                                             }
                                                             _ => {
                                                                 log::debug!("Request::from - parsing request: AdminRequest::Get incorrect how type ({})", how);
@@ -161,29 +224,321 @@ impl Request {
                                             if user == "admin" { // ======================================================= AdminDrop
                                                 if let Some(ip) = request.get("ip") {
                                                     if let Some(ip) = ip.as_str() {
-                                                        if let Some(ip) = ipparser::str_to_ipv4addr(ip) {
+                                                        if let Some((ip, _scope_id)) = ipparser::parse_scoped_ip(ip) {
                                                             log::debug!("Request::from - parsing request: AdminRequest::Drop ip obtained ({})", ip);
                                                             log::debug!("Request::from - parsed request: AdminRequest::Drop");
-                                                            return Some(Request::Admin(AdminRequest::Drop { password, ip } ));
+                                                            return Some(Request::Admin(account, AdminRequest::Drop { password, ip } ));
                                                         } else { log::debug!("Request::from - parsing request: AdminRequest::Drop incorrect ip ({})", ip); }
                                                     } else { log::debug!("Request::from - parsing request: AdminRequest::Drop ip not obtained"); }
                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::Drop ip not obtained"); }
                                             } else { // ======================================================= ClientDrop
                                                 if let Some(ip) = request.get("ip") {
                                                     if let Some(ip) = ip.as_str() {
-                                                        if let Some(ip) = ipparser::str_to_ipv4addr(ip) {
+                                                        if let Some((ip, _scope_id)) = ipparser::parse_scoped_ip(ip) {
                                                             log::debug!("Request::from - parsing request: ClientRequest::Drop ip obtained ({})", ip);
+                                                            // The reason and the text are only used by the report-queue and can be omitted
+                                                            let mut reason = reports::ReportReason::Other;
+                                                            if let Some(r) = request.get("reason") {
+                                                                if let Some(r) = r.as_str().and_then(reports::ReportReason::new_from_str) {
+                                                                    reason = r;
+                                                                } else { log::debug!("Request::from - parsing request: ClientRequest::Drop incorrect reason"); return None; }
+                                                            }
+                                                            let mut text = String::new();
+                                                            if let Some(t) = request.get("text") {
+                                                                if let Some(t) = t.as_str() {
+                                                                    text.push_str(t);
+                                                                } else { log::debug!("Request::from - parsing request: ClientRequest::Drop incorrect text"); return None; }
+                                                            }
                                                             log::debug!("Request::from - parsed request: ClientRequest::Drop");
-                                                            return Some(Request::Client(ClientRequest::Drop { password, ip } ));
+                                                            return Some(Request::Client(ClientRequest::Drop { password, ip, reason, text } ));
                                                         } else { log::debug!("Request::from - parsing request: ClientRequest::Drop incorrect ip ({})", ip); }
                                                     } else { log::debug!("Request::from - parsing request: ClientRequest::Drop ip not obtained"); }
                                                 } else { log::debug!("Request::from - parsing request: ClientRequest::Drop ip not obtained"); }
                                             }
                                         },
+                                        "login" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (login)");
+                                            if user != "admin" { return None; }
+                                            log::debug!("Request::from - parsed request: AdminRequest::Login");
+                                            return Some(Request::Admin(account, AdminRequest::Login { password } ));
+                                        },
+                                        "logout" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (logout)");
+                                            if user != "admin" { return None; }
+                                            log::debug!("Request::from - parsed request: AdminRequest::Logout");
+                                            return Some(Request::Admin(account, AdminRequest::Logout { password } ));
+                                        },
+                                        "session" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (session)");
+                                            if user != "admin" { return None; }
+                                            if let Some(action) = request.get("action") {
+                                                if let Some(action) = action.as_str() {
+                                                    if action.to_lowercase() != "revoke" {
+                                                        log::debug!("Request::from - parsing request: AdminRequest::Session incorrect action ({})", action);
+                                                        return None;
+                                                    }
+                                                    if let Some(id) = request.get("id") {
+                                                        if let Some(id) = id.as_u64() {
+                                                            log::debug!("Request::from - parsed request: AdminRequest::RevokeSession");
+                                                            return Some(Request::Admin(account, AdminRequest::RevokeSession { password, id } ));
+                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::RevokeSession incorrect id"); }
+                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::RevokeSession id not obtained"); }
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Session action not obtained"); }
+                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Session action not obtained"); }
+                                        },
+                                        "account" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (account)");
+                                            if user != "admin" { return None; }
+                                            if let Some(name) = request.get("name") {
+                                                if let Some(name) = name.as_str() {
+                                                    if accounts::is_valid_account_name(name) {
+                                                        let name = String::from(name);
+                                                        if let Some(action) = request.get("action") {
+                                                            if let Some(action) = action.as_str() {
+                                                                let role = request.get("role").and_then(|r| r.as_str()).and_then(accounts::AdminRole::new_from_str);
+                                                                match action.to_lowercase().as_str() {
+                                                                    "add" => {
+                                                                        if let Some(secret) = request.get("secret") {
+                                                                            if let Some(secret) = secret.as_str() {
+                                                                                if let (Some(role), true) = (role, secrets::is_valid_secret(secret)) {
+                                                                                    log::debug!("Request::from - parsed request: AdminRequest::AddAccount");
+                                                                                    return Some(Request::Admin(account, AdminRequest::AddAccount { password, name, role, secret: String::from(secret) } ));
+                                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::AddAccount incorrect role or secret"); }
+                                                                            } else { log::debug!("Request::from - parsing request: AdminRequest::AddAccount secret not obtained"); }
+                                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::AddAccount secret not obtained"); }
+                                                                    },
+                                                                    "remove" => {
+                                                                        log::debug!("Request::from - parsed request: AdminRequest::RemoveAccount");
+                                                                        return Some(Request::Admin(account, AdminRequest::RemoveAccount { password, name } ));
+                                                                    },
+                                                                    "set_role" => {
+                                                                        if let Some(role) = role {
+                                                                            log::debug!("Request::from - parsed request: AdminRequest::SetAccountRole");
+                                                                            return Some(Request::Admin(account, AdminRequest::SetAccountRole { password, name, role } ));
+                                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::SetAccountRole incorrect role"); }
+                                                                    },
+                                                                    _ => {
+                                                                        log::debug!("Request::from - parsing request: AdminRequest::Account incorrect action ({})", action);
+                                                                        return None;
+                                                                    }
+                                                                }
+                                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Account action not obtained"); }
+                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::Account action not obtained"); }
+                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Account incorrect name ({})", name); }
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Account name not obtained"); }
+                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Account name not obtained"); }
+                                        },
+                                        "votes" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (votes)");
+                                            if user != "admin" { return None; }
+                                            if let Some(action) = request.get("action") {
+                                                if let Some(action) = action.as_str() {
+                                                    if action.to_lowercase() != "reset" {
+                                                        log::debug!("Request::from - parsing request: AdminRequest::Votes incorrect action ({})", action);
+                                                        return None;
+                                                    }
+                                                    // Without ip the votes of every client are reset
+                                                    let mut ip: Option<net::IpAddr> = None;
+                                                    if let Some(i) = request.get("ip") {
+                                                        if let Some((i, _scope_id)) = i.as_str().and_then(ipparser::parse_scoped_ip) {
+                                                            ip = Some(i);
+                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::ResetVotes incorrect ip"); return None; }
+                                                    }
+                                                    log::debug!("Request::from - parsed request: AdminRequest::ResetVotes");
+                                                    return Some(Request::Admin(account, AdminRequest::ResetVotes { password, ip } ));
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Votes action not obtained"); }
+                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Votes action not obtained"); }
+                                        },
+                                        "immunity" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (immunity)");
+                                            if user != "admin" { return None; }
+                                            if let Some(ip) = request.get("ip") {
+                                                if let Some((ip, _scope_id)) = ip.as_str().and_then(ipparser::parse_scoped_ip) {
+                                                    if let Some(action) = request.get("action") {
+                                                        if let Some(action) = action.as_str() {
+                                                            match action.to_lowercase().as_str() {
+                                                                "grant" => {
+                                                                    // Without seconds the immunity is permanent
+                                                                    let mut seconds: Option<u64> = None;
+                                                                    if let Some(s) = request.get("seconds") {
+                                                                        if let Some(s) = s.as_u64() {
+                                                                            seconds = Some(s);
+                                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::GrantImmunity incorrect seconds"); return None; }
+                                                                    }
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::GrantImmunity");
+                                                                    return Some(Request::Admin(account, AdminRequest::GrantImmunity { password, ip, seconds } ));
+                                                                },
+                                                                "revoke" => {
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::RevokeImmunity");
+                                                                    return Some(Request::Admin(account, AdminRequest::RevokeImmunity { password, ip } ));
+                                                                },
+                                                                _ => {
+                                                                    log::debug!("Request::from - parsing request: AdminRequest::Immunity incorrect action ({})", action);
+                                                                    return None;
+                                                                }
+                                                            }
+                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::Immunity action not obtained"); }
+                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Immunity action not obtained"); }
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Immunity incorrect ip"); }
+                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Immunity ip not obtained"); }
+                                        },
+                                        "ban" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (ban)");
+                                            if user != "admin" { return None; }
+                                            if let Some(action) = request.get("action") {
+                                                if let Some(action) = action.as_str() {
+                                                    match action.to_lowercase().as_str() {
+                                                        "add" => {
+                                                            // One of ip, network or mac
+                                                            let target;
+                                                            if let Some(ip) = request.get("ip") {
+                                                                if let Some((ip, _scope_id)) = ip.as_str().and_then(ipparser::parse_scoped_ip) {
+                                                                    target = bans::BanTarget::Network(ipparser::Cidr::new_from_str(&ip.to_string())?);
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Ban incorrect ip"); return None; }
+                                                            } else if let Some(network) = request.get("network") {
+                                                                if let Some(network) = network.as_str().and_then(ipparser::Cidr::new_from_str) {
+                                                                    target = bans::BanTarget::Network(network);
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Ban incorrect network"); return None; }
+                                                            } else if let Some(mac) = request.get("mac") {
+                                                                if let Some(mac) = mac.as_str().and_then(ipparser::MacAddress::new_from_str) {
+                                                                    target = bans::BanTarget::Mac(mac);
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Ban incorrect mac"); return None; }
+                                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Ban ip, network or mac not obtained"); return None; }
+                                                            // Without seconds the ban is permanent
+                                                            let mut seconds: Option<u64> = None;
+                                                            if let Some(s) = request.get("seconds") {
+                                                                if let Some(s) = s.as_u64() {
+                                                                    seconds = Some(s);
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Ban incorrect seconds"); return None; }
+                                                            }
+                                                            let mut reason = String::new();
+                                                            if let Some(r) = request.get("reason") {
+                                                                if let Some(r) = r.as_str() {
+                                                                    reason.push_str(r);
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Ban incorrect reason"); return None; }
+                                                            }
+                                                            log::debug!("Request::from - parsed request: AdminRequest::Ban");
+                                                            return Some(Request::Admin(account, AdminRequest::Ban { password, target, seconds, reason } ));
+                                                        },
+                                                        "lift" => {
+                                                            if let Some(id) = request.get("id") {
+                                                                if let Some(id) = id.as_u64() {
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::LiftBan");
+                                                                    return Some(Request::Admin(account, AdminRequest::LiftBan { password, id } ));
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::LiftBan incorrect id"); }
+                                                            } else { log::debug!("Request::from - parsing request: AdminRequest::LiftBan id not obtained"); }
+                                                        },
+                                                        _ => {
+                                                            log::debug!("Request::from - parsing request: AdminRequest::Ban incorrect action ({})", action);
+                                                            return None;
+                                                        }
+                                                    }
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Ban action not obtained"); }
+                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Ban action not obtained"); }
+                                        },
+                                        "signup" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (signup)");
+                                            if user != "admin" { return None; }
+                                            if let Some(action) = request.get("action") {
+                                                if let Some(action) = action.as_str() {
+                                                    if let Some(id) = request.get("id") {
+                                                        if let Some(id) = id.as_u64() {
+                                                            match action.to_lowercase().as_str() {
+                                                                "approve" => {
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::ApproveSignup");
+                                                                    return Some(Request::Admin(account, AdminRequest::ApproveSignup { password, id } ));
+                                                                },
+                                                                "reject" => {
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::RejectSignup");
+                                                                    return Some(Request::Admin(account, AdminRequest::RejectSignup { password, id } ));
+                                                                },
+                                                                _ => {
+                                                                    log::debug!("Request::from - parsing request: AdminRequest::Signup incorrect action ({})", action);
+                                                                    return None;
+                                                                }
+                                                            }
+                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::Signup incorrect id"); }
+                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Signup id not obtained"); }
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Signup action not obtained"); }
+                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Signup action not obtained"); }
+                                        },
+                                        "invite" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (invite)");
+                                            if user != "admin" { return None; }
+                                            if let Some(action) = request.get("action") {
+                                                if let Some(action) = action.as_str() {
+                                                    match action.to_lowercase().as_str() {
+                                                        "mint" => {
+                                                            // Without uses the invite admits a single client
+                                                            let mut uses: u32 = 1;
+                                                            if let Some(u) = request.get("uses") {
+                                                                if let Some(u) = u.as_u64().and_then(|u| u32::try_from(u).ok()).filter(|u| *u > 0) {
+                                                                    uses = u;
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::MintInvite incorrect uses"); return None; }
+                                                            }
+                                                            // Without seconds the invite doesn't expire
+                                                            let mut seconds: Option<u64> = None;
+                                                            if let Some(s) = request.get("seconds") {
+                                                                if let Some(s) = s.as_u64() {
+                                                                    seconds = Some(s);
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::MintInvite incorrect seconds"); return None; }
+                                                            }
+                                                            log::debug!("Request::from - parsed request: AdminRequest::MintInvite");
+                                                            return Some(Request::Admin(account, AdminRequest::MintInvite { password, uses, seconds } ));
+                                                        },
+                                                        "revoke" => {
+                                                            if let Some(id) = request.get("id") {
+                                                                if let Some(id) = id.as_u64() {
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::RevokeInvite");
+                                                                    return Some(Request::Admin(account, AdminRequest::RevokeInvite { password, id } ));
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::RevokeInvite incorrect id"); }
+                                                            } else { log::debug!("Request::from - parsing request: AdminRequest::RevokeInvite id not obtained"); }
+                                                        },
+                                                        _ => {
+                                                            log::debug!("Request::from - parsing request: AdminRequest::Invite incorrect action ({})", action);
+                                                            return None;
+                                                        }
+                                                    }
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Invite action not obtained"); }
+                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Invite action not obtained"); }
+                                        },
+                                        "report" => { // ======================================================= Admin only
+                                            log::debug!("Request::from - method obtained (report)");
+                                            if user != "admin" { return None; }
+                                            if let Some(id) = request.get("id") {
+                                                if let Some(id) = id.as_u64() {
+                                                    if let Some(action) = request.get("action") {
+                                                        if let Some(action) = action.as_str() {
+                                                            match action.to_lowercase().as_str() {
+                                                                "approve" => {
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::ApproveReport");
+                                                                    return Some(Request::Admin(account, AdminRequest::ApproveReport { password, id } ));
+                                                                },
+                                                                "dismiss" => {
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::DismissReport");
+                                                                    return Some(Request::Admin(account, AdminRequest::DismissReport { password, id } ));
+                                                                },
+                                                                _ => {
+                                                                    log::debug!("Request::from - parsing request: AdminRequest::Report incorrect action ({})", action);
+                                                                    return None;
+                                                                }
+                                                            }
+                                                        } else { log::debug!("Request::from - parsing request: AdminRequest::Report action not obtained"); }
+                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Report action not obtained"); }
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Report incorrect id"); }
+                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Report id not obtained"); }
+                                        },
                                         "set" => { // ======================================================= Admin only
                                             log::debug!("Request::from - method obtained (set)");
                                             if user != "admin" { return None; }
                                             log::debug!("Request::from - parsed request: AdminRequest::Set");
+                                            // The key and the password can be given a grace period, without it the one of the config is used
+                                            let mut grace: Option<u64> = None;
+                                            if let Some(g) = request.get("grace") {
+                                                if let Some(g) = g.as_u64() {
+                                                    grace = Some(g);
+                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect grace"); return None; }
+                                            }
                                             if let Some(what) = request.get("what") {
                                                 if let Some(what) = what.as_str() {
                                                     match what.to_lowercase().as_str() {
@@ -191,10 +546,10 @@ impl Request {
                                                             if let Some(key) = request.get("key") {
                                                                 if let Some(key) = key.as_str(){
                                                                     if server::is_valid_key(key) {
-                                                                        log::debug!("Request::from - parsing request: AdminRequest::Set key obtained ({})", key);
+                                                                        log::debug!("Request::from - parsing request: AdminRequest::Set key obtained");
                                                                         log::debug!("Request::from - parsed request: AdminRequest::SetKey");
-                                                                        return Some(Request::Admin(AdminRequest::SetKey { password, key: String::from(key) } ));
-                                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect key ({})", key); }
+                                                                        return Some(Request::Admin(account, AdminRequest::SetKey { password, key: String::from(key), grace } ));
+                                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect key"); }
                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::Set key not obtained"); }
                                                             } else { log::debug!("Request::from - parsing request: AdminRequest::Set key not obtained"); }
                                                         },
@@ -202,10 +557,10 @@ impl Request {
                                                             if let Some(new_password) = request.get("new_password") {
                                                                 if let Some(new_password) = new_password.as_str(){
                                                                     if server::is_valid_key(new_password) {
-                                                                        log::debug!("Request::from - parsing request: AdminRequest::Set new_password obtained ({})", new_password);
+                                                                        log::debug!("Request::from - parsing request: AdminRequest::Set new_password obtained");
                                                                         log::debug!("Request::from - parsed request: AdminRequest::SetPassword");
-                                                                        return Some(Request::Admin(AdminRequest::SetPassword { password, new_password: String::from(new_password) } ));
-                                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect new_password ({})", new_password); }
+                                                                        return Some(Request::Admin(account, AdminRequest::SetPassword { password, new_password: String::from(new_password), grace } ));
+                                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect new_password"); }
                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::Set new_password not obtained"); }
                                                             } else { log::debug!("Request::from - parsing request: AdminRequest::Set new_password not obtained"); }
                                                         },
@@ -216,7 +571,7 @@ impl Request {
                                                                         // if capacity > 1 {
                                                                             log::debug!("Request::from - parsing request: AdminRequest::Set capacity obtained ({})", capacity);
                                                                             log::debug!("Request::from - parsed request: AdminRequest::SetCapacity");
-                                                                            return Some(Request::Admin(AdminRequest::SetCapacity { password, capacity } ));
+                                                                            return Some(Request::Admin(account, AdminRequest::SetCapacity { password, capacity } ));
                                                                         // } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect capacity ({})", capacity); }                                                                        
                                                                     } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect capacity ({})", capacity); }
                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::Set capacity not obtained"); }
@@ -228,7 +583,7 @@ impl Request {
                                                                     if let Ok(list_size) = u16::try_from(list_size) {
                                                                         log::debug!("Request::from - parsing request: AdminRequest::Set list_size obtained ({})", list_size);
                                                                         log::debug!("Request::from - parsed request: AdminRequest::SetListSize");
-                                                                        return Some(Request::Admin(AdminRequest::SetListSize { password, list_size } ));
+                                                                        return Some(Request::Admin(account, AdminRequest::SetListSize { password, list_size } ));
                                                                     } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect list_size ({})", list_size); }
                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::Set list_size not obtained"); }
                                                             } else { log::debug!("Request::from - parsing request: AdminRequest::Set list_size not obtained"); }
@@ -238,7 +593,7 @@ impl Request {
                                                                 if let Some(drop_verification) = drop_verification.as_bool(){                                                                    
                                                                     log::debug!("Request::from - parsing request: AdminRequest::Set drop_verification obtained ({})", drop_verification);
                                                                     log::debug!("Request::from - parsed request: AdminRequest::SetDropVerification");
-                                                                    return Some(Request::Admin(AdminRequest::SetDropVerification { password, drop_verification } ));
+                                                                    return Some(Request::Admin(account, AdminRequest::SetDropVerification { password, drop_verification } ));
                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::Set drop_verification not obtained"); }
                                                             } else { log::debug!("Request::from - parsing request: AdminRequest::Set drop_verification not obtained"); }
                                                         },
@@ -249,11 +604,43 @@ impl Request {
                                                                         // if drop_votes > 0 {
                                                                             log::debug!("Request::from - parsing request: AdminRequest::Set drop_votes obtained ({})", drop_votes);
                                                                             log::debug!("Request::from - parsed request: AdminRequest::SetDropVotes");
-                                                                            return Some(Request::Admin(AdminRequest::SetDropVotes { password, drop_votes } ));
+                                                                            return Some(Request::Admin(account, AdminRequest::SetDropVotes { password, drop_votes } ));
                                                                         // } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect drop_votes ({})", drop_votes); }
                                                                     } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect drop_votes ({})", drop_votes); }
                                                                 } else { log::debug!("Request::from - parsing request: AdminRequest::Set drop_votes not obtained"); }
                                                             } else { log::debug!("Request::from - parsing request: AdminRequest::Set drop_votes not obtained"); }
+                                                        },
+                                                        "report_queue" => {
+                                                            if let Some(report_queue) = request.get("report_queue") {
+                                                                if let Some(report_queue) = report_queue.as_bool() {
+                                                                    log::debug!("Request::from - parsing request: AdminRequest::Set report_queue obtained ({})", report_queue);
+                                                                    log::debug!("Request::from - parsed request: AdminRequest::SetReportQueue");
+                                                                    return Some(Request::Admin(account, AdminRequest::SetReportQueue { password, report_queue } ));
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Set report_queue not obtained"); }
+                                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Set report_queue not obtained"); }
+                                                        },
+                                                        "drop_quorum" => {
+                                                            if let Some(drop_quorum) = request.get("drop_quorum") {
+                                                                if let Some(drop_quorum) = drop_quorum.as_u64() {
+                                                                    if drop_quorum <= 100 {
+                                                                        let drop_quorum = drop_quorum as u8;
+                                                                        log::debug!("Request::from - parsing request: AdminRequest::Set drop_quorum obtained ({})", drop_quorum);
+                                                                        // Can be omitted, the server keeps the minimum it has
+                                                                        let mut min_votes: Option<u16> = None;
+                                                                        if let Some(mv) = request.get("min_votes") {
+                                                                            if let Some(mv) = mv.as_u64() {
+                                                                                if let Ok(mv) = u16::try_from(mv) {
+                                                                                    if mv > 0 {
+                                                                                        min_votes = Some(mv);
+                                                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect min_votes ({})", mv); return None; }
+                                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect min_votes ({})", mv); return None; }
+                                                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect min_votes"); return None; }
+                                                                        }
+                                                                        log::debug!("Request::from - parsed request: AdminRequest::SetDropQuorum");
+                                                                        return Some(Request::Admin(account, AdminRequest::SetDropQuorum { password, drop_quorum, min_votes } ));
+                                                                    } else { log::debug!("Request::from - parsing request: AdminRequest::Set incorrect drop_quorum ({})", drop_quorum); }
+                                                                } else { log::debug!("Request::from - parsing request: AdminRequest::Set drop_quorum not obtained"); }
+                                                            } else { log::debug!("Request::from - parsing request: AdminRequest::Set drop_quorum not obtained"); }
                                                         }
                                                         _ => {
                                                             log::debug!("Request::from - parsing request: AdminRequest::Set incorrect what type ({})", what);
@@ -282,8 +669,15 @@ impl Request {
                                                                                 if let Some(gobm) = request.get("get_only_by_mac") {
                                                                                     if let Some(get_only_by_mac) = gobm.as_bool() {
                                                                                         log::debug!("Request::from - parsing request: ClientRequest::SignUp get_only_by_mac obtained ({})", get_only_by_mac);
+                                                                                        // Only the clients that already own the MAC send it
+                                                                                        let mut owner_token = None;
+                                                                                        if let Some(t) = request.get("owner_token") {
+                                                                                            if let Some(t) = t.as_str() {
+                                                                                                owner_token = Some(String::from(t));
+                                                                                            } else { log::debug!("Request::from - parsing request: ClientRequest::SignUp incorrect owner_token"); return None; }
+                                                                                        }
                                                                                         log::debug!("Request::from - parsed request: ClientRequest::SignUp");
-                                                                                        return Some(Request::Client(ClientRequest::SignUp { password, username, mac, port, get_only_by_mac } ));
+                                                                                        return Some(Request::Client(ClientRequest::SignUp { password, username, mac, port, get_only_by_mac, owner_token } ));
                                                                                     } else { log::debug!("Request::from - parsing request: ClientRequest::SignUp get_only_by_mac not obtained"); }
                                                                                 } else { log::debug!("Request::from - parsing request: ClientRequest::SignUp get_only_by_mac not obtained"); }
                                                                             } else { log::debug!("Request::from - parsing request: ClientRequest::SignUp incorrect port ({})", port); }
@@ -296,6 +690,52 @@ impl Request {
                                                 } else { log::debug!("Request::from - parsing request: ClientRequest::SignUp username not obtained"); }
                                             } else { log::debug!("Request::from - parsing request: ClientRequest::SignUp username not obtained"); }
                                         },
+                                        "sign_up_status" => { // ======================================================= Client only
+                                            log::debug!("Request::from - method obtained (sign_up_status)");
+                                            if user != "client" { return None; }
+                                            if let Some(mac) = request.get("mac") {
+                                                if let Some(mac) = mac.as_str().and_then(ipparser::MacAddress::new_from_str) {
+                                                    log::debug!("Request::from - parsed request: ClientRequest::SignUpStatus");
+                                                    return Some(Request::Client(ClientRequest::SignUpStatus { password, mac } ));
+                                                } else { log::debug!("Request::from - parsing request: ClientRequest::SignUpStatus incorrect mac"); }
+                                            } else { log::debug!("Request::from - parsing request: ClientRequest::SignUpStatus mac not obtained"); }
+                                        },
+                                        "sign_out" => { // ======================================================= Client only
+                                            log::debug!("Request::from - method obtained (sign_out)");
+                                            if user != "client" { return None; }
+                                            let (mac, owner_token) = parse_client_owner(&request)?;
+                                            log::debug!("Request::from - parsed request: ClientRequest::SignOut");
+                                            return Some(Request::Client(ClientRequest::SignOut { password, mac, owner_token } ));
+                                        },
+                                        "update" => { // ======================================================= Client only
+                                            log::debug!("Request::from - method obtained (update)");
+                                            if user != "client" { return None; }
+                                            let (mac, owner_token) = parse_client_owner(&request)?;
+                                            let mut username = None;
+                                            if let Some(u) = request.get("username") {
+                                                if let Some(u) = u.as_str().filter(|u| clients::Client::is_valid_username(u)) {
+                                                    username = Some(String::from(u));
+                                                } else { log::debug!("Request::from - parsing request: ClientRequest::Update incorrect username"); return None; }
+                                            }
+                                            let mut port = None;
+                                            if let Some(p) = request.get("port") {
+                                                if let Some(p) = p.as_u64().and_then(|p| u16::try_from(p).ok()) {
+                                                    port = Some(p);
+                                                } else { log::debug!("Request::from - parsing request: ClientRequest::Update incorrect port"); return None; }
+                                            }
+                                            let mut get_only_by_mac = None;
+                                            if let Some(g) = request.get("get_only_by_mac") {
+                                                if let Some(g) = g.as_bool() {
+                                                    get_only_by_mac = Some(g);
+                                                } else { log::debug!("Request::from - parsing request: ClientRequest::Update incorrect get_only_by_mac"); return None; }
+                                            }
+                                            if username.is_none() && port.is_none() && get_only_by_mac.is_none() {
+                                                log::debug!("Request::from - parsing request: ClientRequest::Update nothing to update");
+                                                return None;
+                                            }
+                                            log::debug!("Request::from - parsed request: ClientRequest::Update");
+                                            return Some(Request::Client(ClientRequest::Update { password, mac, owner_token, username, port, get_only_by_mac } ));
+                                        },
                                         _ => {
                                             log::debug!("Request::from - incorrect method type ({})", method);
                                             return None;
@@ -314,10 +754,108 @@ impl Request {
     }
 }
 
+impl Request {
+    // Every category has its own rate limit
+    pub fn category(&self) -> ratelimit::RequestCategory {
+        match self {
+            Request::Admin(..) => ratelimit::RequestCategory::Admin,
+            Request::Client(ClientRequest::GetByMac { .. }) | Request::Client(ClientRequest::GetByUsername { .. }) => ratelimit::RequestCategory::Lookup,
+            Request::Client(ClientRequest::SignUpStatus { .. }) => ratelimit::RequestCategory::Lookup,
+            Request::Client(ClientRequest::Drop { .. }) | Request::Client(ClientRequest::SignUp { .. }) => ratelimit::RequestCategory::Mutation,
+            Request::Client(ClientRequest::SignOut { .. }) | Request::Client(ClientRequest::Update { .. }) => ratelimit::RequestCategory::Mutation
+        }
+    }
+
+    // The admin requests need the key, the client requests need the password
+    pub fn secret_kind(&self) -> auth::SecretKind {
+        match self {
+            Request::Admin(..) => auth::SecretKind::AdminKey,
+            Request::Client(_) => auth::SecretKind::ClientPassword
+        }
+    }
+
+    // The client requests have no account
+    pub fn admin_account(&self) -> Option<&str> {
+        match self {
+            Request::Admin(account, _) => Some(account),
+            Request::Client(_) => None
+        }
+    }
+}
+
+// The challenge-response authentication keeps the secrets off the wire. The client asks for a
+// nonce with {"user":"client","method":"challenge"}, then sends the request as a JSON string
+// signed with a proof over the nonce and the request, see secrets::Secret::verify_hmac:
+// {"nonce":"<nonce>","proof":"<client key XOR HMAC-SHA256 in hex>","request":"<the request>"}
+pub enum Envelope {
+    // The admins name the account whose secret signs the request
+    Challenge { kind: auth::SecretKind, account: String },
+    Signed { nonce: String, proof: Vec<u8>, body: String, request: Request }
+}
+
+impl Envelope {
+    pub fn from(request_str: &str) -> Option<Envelope> {
+        let envelope = serde_json::from_str::<serde_json::Value>(request_str).ok()?;
+        if let Some(body) = envelope.get("request") {
+            let body = String::from(body.as_str()?);
+            let nonce = String::from(envelope.get("nonce")?.as_str()?);
+            let proof = secrets::from_hex(envelope.get("proof")?.as_str()?)?;
+            // The signed requests don't carry the secret, an empty one is enough to parse them
+            let mut inner = serde_json::from_str::<serde_json::Value>(&body).ok()?;
+            inner.as_object_mut()?.insert(String::from("password"), serde_json::Value::String(String::new()));
+            let request = Request::from(&inner.to_string())?;
+            log::debug!("Envelope::from - parsed signed request: {}", request);
+            return Some(Envelope::Signed { nonce, proof, body, request });
+        }
+        if envelope.get("method")?.as_str()?.to_lowercase() != "challenge" {
+            return None;
+        }
+        let kind = match envelope.get("user")?.as_str()?.to_lowercase().as_str() {
+            "admin" => auth::SecretKind::AdminKey,
+            "client" => auth::SecretKind::ClientPassword,
+            _ => return None
+        };
+        let account = match envelope.get("account") {
+            Some(account) => String::from(account.as_str().filter(|a| accounts::is_valid_account_name(a))?),
+            None => String::from(accounts::BUILTIN_ADMIN)
+        };
+        log::debug!("Envelope::from - parsed challenge for the {}", kind);
+        Some(Envelope::Challenge { kind, account })
+    }
+
+    // Asking for a nonce costs the same as a lookup
+    pub fn category(&self) -> ratelimit::RequestCategory {
+        match self {
+            Envelope::Challenge { .. } => ratelimit::RequestCategory::Lookup,
+            Envelope::Signed { request, .. } => request.category()
+        }
+    }
+
+    // The proof is computed over the nonce followed by the request, exactly as they were sent
+    pub fn signed_message(nonce: &str, body: &str) -> Vec<u8> {
+        let mut message = Vec::with_capacity(nonce.len() + body.len());
+        message.extend_from_slice(nonce.as_bytes());
+        message.extend_from_slice(body.as_bytes());
+        message
+    }
+}
+
+impl fmt::Display for Envelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Envelope::Challenge { kind, .. } => write!(f, "Challenge for the {}", kind),
+            Envelope::Signed { request, .. } => write!(f, "Signed {}", request)
+        }
+    }
+}
+
 impl fmt::Display for Request {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Request::Admin(admin_request) => {
+            Request::Admin(account, admin_request) => {
+                if account != accounts::BUILTIN_ADMIN {
+                    write!(f, "[{}] ", account)?;
+                }
                 match admin_request {
                     AdminRequest::GetByIndex { password: _password, start_index, end_index } => {
                         write!(f, "Admin Get [{}, {})", start_index, end_index)
@@ -334,11 +872,17 @@ impl fmt::Display for Request {
                     AdminRequest::Drop { password: _password, ip } => {
                         write!(f, "Admin Drop {}", ip)
                     },
-                    AdminRequest::SetKey { password: _password, key } => {
-                        write!(f, "Admin Set Key {}", key)
+                    AdminRequest::SetKey { password: _password, key: _key, grace } => {
+                        match grace {
+                            Some(grace) => write!(f, "Admin Set Key with a grace of {} second(s)", grace),
+                            None => write!(f, "Admin Set Key")
+                        }
                     },
-                    AdminRequest::SetPassword { password: _password, new_password } => {
-                        write!(f, "Admin Set Password {}", new_password)
+                    AdminRequest::SetPassword { password: _password, new_password: _new_password, grace } => {
+                        match grace {
+                            Some(grace) => write!(f, "Admin Set Password with a grace of {} second(s)", grace),
+                            None => write!(f, "Admin Set Password")
+                        }
                     },
                     AdminRequest::SetCapacity { password: _password, capacity } => {
                         write!(f, "Admin Set Capacity {}", capacity)
@@ -351,6 +895,102 @@ impl fmt::Display for Request {
                     },
                     AdminRequest::SetDropVotes { password: _password, drop_votes } => {
                         write!(f, "Admin Set DropVotes {}", drop_votes)
+                    },
+                    AdminRequest::SetDropQuorum { password: _password, drop_quorum, min_votes } => {
+                        write!(f, "Admin Set DropQuorum {}% min votes {:?}", drop_quorum, min_votes)
+                    },
+                    AdminRequest::SetReportQueue { password: _password, report_queue } => {
+                        write!(f, "Admin Set ReportQueue {}", report_queue)
+                    },
+                    AdminRequest::GetReports { password: _password } => {
+                        write!(f, "Admin Get reports")
+                    },
+                    AdminRequest::GetRateLimits { password: _password } => {
+                        write!(f, "Admin Get rate limits")
+                    },
+                    AdminRequest::GetLockouts { password: _password } => {
+                        write!(f, "Admin Get lockouts")
+                    },
+                    AdminRequest::Login { password: _password } => {
+                        write!(f, "Admin Login")
+                    },
+                    AdminRequest::Logout { password: _password } => {
+                        write!(f, "Admin Logout")
+                    },
+                    AdminRequest::GetSessions { password: _password } => {
+                        write!(f, "Admin Get sessions")
+                    },
+                    AdminRequest::GetAccounts { password: _password } => {
+                        write!(f, "Admin Get accounts")
+                    },
+                    AdminRequest::AddAccount { password: _password, name, role, secret: _secret } => {
+                        write!(f, "Admin Add account {} ({})", name, role)
+                    },
+                    AdminRequest::RemoveAccount { password: _password, name } => {
+                        write!(f, "Admin Remove account {}", name)
+                    },
+                    AdminRequest::SetAccountRole { password: _password, name, role } => {
+                        write!(f, "Admin Set role of {} to {}", name, role)
+                    },
+                    AdminRequest::RevokeSession { password: _password, id } => {
+                        write!(f, "Admin Revoke session {}", id)
+                    },
+                    AdminRequest::GetBans { password: _password } => {
+                        write!(f, "Admin Get bans")
+                    },
+                    AdminRequest::Ban { password: _password, target, seconds, reason: _reason } => {
+                        match seconds {
+                            Some(seconds) => write!(f, "Admin Ban {} for {} second(s)", target, seconds),
+                            None => write!(f, "Admin Ban {} permanently", target)
+                        }
+                    },
+                    AdminRequest::LiftBan { password: _password, id } => {
+                        write!(f, "Admin Lift ban {}", id)
+                    },
+                    AdminRequest::GetInvites { password: _password } => {
+                        write!(f, "Admin Get invites")
+                    },
+                    AdminRequest::MintInvite { password: _password, uses, seconds } => {
+                        match seconds {
+                            Some(seconds) => write!(f, "Admin Mint invite for {} use(s) for {} second(s)", uses, seconds),
+                            None => write!(f, "Admin Mint invite for {} use(s)", uses)
+                        }
+                    },
+                    AdminRequest::RevokeInvite { password: _password, id } => {
+                        write!(f, "Admin Revoke invite {}", id)
+                    },
+                    AdminRequest::GetSignups { password: _password } => {
+                        write!(f, "Admin Get sign ups")
+                    },
+                    AdminRequest::ApproveSignup { password: _password, id } => {
+                        write!(f, "Admin Approve sign up {}", id)
+                    },
+                    AdminRequest::RejectSignup { password: _password, id } => {
+                        write!(f, "Admin Reject sign up {}", id)
+                    },
+                    AdminRequest::GetReportHistory { password: _password, mac } => {
+                        write!(f, "Admin Get report history of {}", mac)
+                    },
+                    AdminRequest::ApproveReport { password: _password, id } => {
+                        write!(f, "Admin Approve report {}", id)
+                    },
+                    AdminRequest::DismissReport { password: _password, id } => {
+                        write!(f, "Admin Dismiss report {}", id)
+                    },
+                    AdminRequest::ResetVotes { password: _password, ip } => {
+                        match ip {
+                            Some(ip) => write!(f, "Admin Reset votes of {}", ip),
+                            None => write!(f, "Admin Reset votes of everyone")
+                        }
+                    },
+                    AdminRequest::GrantImmunity { password: _password, ip, seconds } => {
+                        match seconds {
+                            Some(seconds) => write!(f, "Admin Grant immunity to {} for {} second(s)", ip, seconds),
+                            None => write!(f, "Admin Grant permanent immunity to {}", ip)
+                        }
+                    },
+                    AdminRequest::RevokeImmunity { password: _password, ip } => {
+                        write!(f, "Admin Revoke immunity of {}", ip)
                     }
                 }
             },
@@ -362,15 +1002,24 @@ impl fmt::Display for Request {
                     ClientRequest::GetByUsername { password: _password, username, start_index } => {
                         write!(f, "Client Get \"{}\" starting from {}", username, start_index)
                     },
-                    ClientRequest::Drop { password: _password, ip } => {
-                        write!(f, "Client Drop {}", ip)
+                    ClientRequest::Drop { password: _password, ip, reason, text: _text } => {
+                        write!(f, "Client Drop {} ({})", ip, reason)
                     },
-                    ClientRequest::SignUp { password: _password, username, mac, port, get_only_by_mac } => {
+                    ClientRequest::SignUp { password: _password, username, mac, port, get_only_by_mac, .. } => {
                         if *get_only_by_mac {
                             write!(f, "Client SignUp {} \"{}\" PORT: {} MAC-ONLY", mac, username, port)
                         } else {
                             write!(f, "Client SignUp {} \"{}\" PORT: {}", mac, username, port)
                         }
+                    },
+                    ClientRequest::SignUpStatus { mac, .. } => {
+                        write!(f, "Client SignUpStatus {}", mac)
+                    },
+                    ClientRequest::SignOut { mac, .. } => {
+                        write!(f, "Client SignOut {}", mac_or_own_ip(mac))
+                    },
+                    ClientRequest::Update { mac, username, port, get_only_by_mac, .. } => {
+                        write!(f, "Client Update {}{}", mac_or_own_ip(mac), update_fields_to_string(username, port, get_only_by_mac))
                     }
                 }
             }
@@ -399,15 +1048,19 @@ pub enum AdminRequest {
     },
     Drop {
         password: String,
-        ip: net::Ipv4Addr
+        ip: net::IpAddr
     },
     SetKey {
         password: String,
-        key: String
+        key: String,
+        // How long the old key is still accepted
+        grace: Option<u64>
     },
     SetPassword {
         password: String,
-        new_password: String
+        new_password: String,
+        // How long the old password is still accepted
+        grace: Option<u64>
     },
     SetCapacity {
         password: String,
@@ -424,6 +1077,209 @@ pub enum AdminRequest {
     SetDropVotes {
         password: String,
         drop_votes: u8
+    },
+    // 0 goes back to the fixed drop-votes
+    SetDropQuorum {
+        password: String,
+        drop_quorum: u8,
+        min_votes: Option<u16>
+    },
+    SetReportQueue {
+        password: String,
+        report_queue: bool
+    },
+    GetReports {
+        password: String
+    },
+    GetRateLimits {
+        password: String
+    },
+    GetLockouts {
+        password: String
+    },
+    Login {
+        password: String
+    },
+    Logout {
+        password: String
+    },
+    GetSessions {
+        password: String
+    },
+    RevokeSession {
+        password: String,
+        id: u64
+    },
+    GetAccounts {
+        password: String
+    },
+    AddAccount {
+        password: String,
+        name: String,
+        role: accounts::AdminRole,
+        secret: String
+    },
+    RemoveAccount {
+        password: String,
+        name: String
+    },
+    SetAccountRole {
+        password: String,
+        name: String,
+        role: accounts::AdminRole
+    },
+    GetBans {
+        password: String
+    },
+    Ban {
+        password: String,
+        target: bans::BanTarget,
+        seconds: Option<u64>,
+        reason: String
+    },
+    LiftBan {
+        password: String,
+        id: u64
+    },
+    GetReportHistory {
+        password: String,
+        mac: ipparser::MacAddress
+    },
+    ApproveReport {
+        password: String,
+        id: u64
+    },
+    DismissReport {
+        password: String,
+        id: u64
+    },
+    ResetVotes {
+        password: String,
+        ip: Option<net::IpAddr>
+    },
+    GrantImmunity {
+        password: String,
+        ip: net::IpAddr,
+        seconds: Option<u64>
+    },
+    RevokeImmunity {
+        password: String,
+        ip: net::IpAddr
+    },
+    GetInvites {
+        password: String
+    },
+    MintInvite {
+        password: String,
+        uses: u32,
+        seconds: Option<u64>
+    },
+    RevokeInvite {
+        password: String,
+        id: u64
+    },
+    GetSignups {
+        password: String
+    },
+    ApproveSignup {
+        password: String,
+        id: u64
+    },
+    RejectSignup {
+        password: String,
+        id: u64
+    }
+}
+
+impl AdminRequest {
+    // The key, the secret of the account or the token of a session
+    pub fn password(&self) -> &str {
+        match self {
+            AdminRequest::GetByIndex { password, .. } |
+            AdminRequest::GetByMac { password, .. } |
+            AdminRequest::GetByUsername { password, .. } |
+            AdminRequest::GetRunningConfiguration { password, .. } |
+            AdminRequest::Drop { password, .. } |
+            AdminRequest::SetKey { password, .. } |
+            AdminRequest::SetPassword { password, .. } |
+            AdminRequest::SetCapacity { password, .. } |
+            AdminRequest::SetListSize { password, .. } |
+            AdminRequest::SetDropVerification { password, .. } |
+            AdminRequest::SetDropVotes { password, .. } |
+            AdminRequest::SetDropQuorum { password, .. } |
+            AdminRequest::SetReportQueue { password, .. } |
+            AdminRequest::GetReports { password, .. } |
+            AdminRequest::GetRateLimits { password, .. } |
+            AdminRequest::GetLockouts { password, .. } |
+            AdminRequest::Login { password, .. } |
+            AdminRequest::Logout { password, .. } |
+            AdminRequest::GetSessions { password, .. } |
+            AdminRequest::RevokeSession { password, .. } |
+            AdminRequest::GetAccounts { password, .. } |
+            AdminRequest::AddAccount { password, .. } |
+            AdminRequest::RemoveAccount { password, .. } |
+            AdminRequest::SetAccountRole { password, .. } |
+            AdminRequest::GetBans { password, .. } |
+            AdminRequest::Ban { password, .. } |
+            AdminRequest::LiftBan { password, .. } |
+            AdminRequest::GetReportHistory { password, .. } |
+            AdminRequest::ApproveReport { password, .. } |
+            AdminRequest::DismissReport { password, .. } |
+            AdminRequest::ResetVotes { password, .. } |
+            AdminRequest::GrantImmunity { password, .. } |
+            AdminRequest::RevokeImmunity { password, .. } |
+            AdminRequest::GetInvites { password, .. } |
+            AdminRequest::MintInvite { password, .. } |
+            AdminRequest::RevokeInvite { password, .. } |
+            AdminRequest::GetSignups { password, .. } |
+            AdminRequest::ApproveSignup { password, .. } |
+            AdminRequest::RejectSignup { password, .. } => password
+        }
+    }
+
+    // The least role that can make the request
+    pub fn required_role(&self) -> accounts::AdminRole {
+        match self {
+            AdminRequest::GetByIndex { .. } |
+            AdminRequest::GetByMac { .. } |
+            AdminRequest::GetByUsername { .. } |
+            AdminRequest::GetRunningConfiguration { .. } |
+            AdminRequest::GetReports { .. } |
+            AdminRequest::GetReportHistory { .. } |
+            AdminRequest::GetBans { .. } |
+            AdminRequest::GetInvites { .. } |
+            AdminRequest::GetSignups { .. } |
+            AdminRequest::GetRateLimits { .. } |
+            AdminRequest::GetLockouts { .. } |
+            AdminRequest::Login { .. } |
+            AdminRequest::Logout { .. } => accounts::AdminRole::ReadOnly,
+            AdminRequest::Drop { .. } |
+            AdminRequest::ResetVotes { .. } |
+            AdminRequest::GrantImmunity { .. } |
+            AdminRequest::RevokeImmunity { .. } |
+            AdminRequest::ApproveReport { .. } |
+            AdminRequest::DismissReport { .. } |
+            AdminRequest::Ban { .. } |
+            AdminRequest::LiftBan { .. } |
+            AdminRequest::MintInvite { .. } |
+            AdminRequest::RevokeInvite { .. } |
+            AdminRequest::ApproveSignup { .. } |
+            AdminRequest::RejectSignup { .. } => accounts::AdminRole::Operator,
+            AdminRequest::SetKey { .. } |
+            AdminRequest::SetPassword { .. } |
+            AdminRequest::SetCapacity { .. } |
+            AdminRequest::SetListSize { .. } |
+            AdminRequest::SetDropVerification { .. } |
+            AdminRequest::SetDropVotes { .. } |
+            AdminRequest::SetDropQuorum { .. } |
+            AdminRequest::SetReportQueue { .. } |
+            AdminRequest::GetSessions { .. } |
+            AdminRequest::RevokeSession { .. } |
+            AdminRequest::GetAccounts { .. } |
+            AdminRequest::AddAccount { .. } |
+            AdminRequest::RemoveAccount { .. } |
+            AdminRequest::SetAccountRole { .. } => accounts::AdminRole::Superuser
+        }
     }
 }
 
@@ -445,11 +1301,17 @@ impl fmt::Display for AdminRequest {
             AdminRequest::Drop { password: _password, ip } => {
                 write!(f, "AdminRequest::Drop {}", ip)
             },
-            AdminRequest::SetKey { password: _password, key } => {
-                write!(f, "AdminRequest::SetKey {}", key)
+            AdminRequest::SetKey { password: _password, key: _key, grace } => {
+                match grace {
+                    Some(grace) => write!(f, "AdminRequest::SetKey grace {} second(s)", grace),
+                    None => write!(f, "AdminRequest::SetKey")
+                }
             },
-            AdminRequest::SetPassword { password: _password, new_password } => {
-                write!(f, "AdminRequest::SetPassword {}", new_password)
+            AdminRequest::SetPassword { password: _password, new_password: _new_password, grace } => {
+                match grace {
+                    Some(grace) => write!(f, "AdminRequest::SetPassword grace {} second(s)", grace),
+                    None => write!(f, "AdminRequest::SetPassword")
+                }
             },
             AdminRequest::SetCapacity { password: _password, capacity } => {
                 write!(f, "AdminRequest::SetCapacity {}", capacity)
@@ -462,6 +1324,93 @@ impl fmt::Display for AdminRequest {
             },
             AdminRequest::SetDropVotes { password: _password, drop_votes } => {
                 write!(f, "AdminRequest::SetDropVotes {}", drop_votes)
+            },
+            AdminRequest::SetDropQuorum { password: _password, drop_quorum, min_votes } => {
+                write!(f, "AdminRequest::SetDropQuorum {}% min votes {:?}", drop_quorum, min_votes)
+            },
+            AdminRequest::SetReportQueue { password: _password, report_queue } => {
+                write!(f, "AdminRequest::SetReportQueue {}", report_queue)
+            },
+            AdminRequest::GetReports { password: _password } => {
+                write!(f, "AdminRequest::GetReports")
+            },
+            AdminRequest::GetRateLimits { password: _password } => {
+                write!(f, "AdminRequest::GetRateLimits")
+            },
+            AdminRequest::GetLockouts { password: _password } => {
+                write!(f, "AdminRequest::GetLockouts")
+            },
+            AdminRequest::Login { password: _password } => {
+                write!(f, "AdminRequest::Login")
+            },
+            AdminRequest::Logout { password: _password } => {
+                write!(f, "AdminRequest::Logout")
+            },
+            AdminRequest::GetSessions { password: _password } => {
+                write!(f, "AdminRequest::GetSessions")
+            },
+            AdminRequest::GetAccounts { password: _password } => {
+                write!(f, "AdminRequest::GetAccounts")
+            },
+            AdminRequest::AddAccount { password: _password, name, role, secret: _secret } => {
+                write!(f, "AdminRequest::AddAccount {} {}", name, role)
+            },
+            AdminRequest::RemoveAccount { password: _password, name } => {
+                write!(f, "AdminRequest::RemoveAccount {}", name)
+            },
+            AdminRequest::SetAccountRole { password: _password, name, role } => {
+                write!(f, "AdminRequest::SetAccountRole {} {}", name, role)
+            },
+            AdminRequest::RevokeSession { password: _password, id } => {
+                write!(f, "AdminRequest::RevokeSession {}", id)
+            },
+            AdminRequest::GetBans { password: _password } => {
+                write!(f, "AdminRequest::GetBans")
+            },
+            AdminRequest::Ban { password: _password, target, seconds, reason: _reason } => {
+                write!(f, "AdminRequest::Ban {} {:?}", target, seconds)
+            },
+            AdminRequest::LiftBan { password: _password, id } => {
+                write!(f, "AdminRequest::LiftBan {}", id)
+            },
+            AdminRequest::GetInvites { password: _password } => {
+                write!(f, "AdminRequest::GetInvites")
+            },
+            AdminRequest::MintInvite { password: _password, uses, seconds } => {
+                match seconds {
+                    Some(seconds) => write!(f, "AdminRequest::MintInvite {} use(s) for {} second(s)", uses, seconds),
+                    None => write!(f, "AdminRequest::MintInvite {} use(s)", uses)
+                }
+            },
+            AdminRequest::RevokeInvite { password: _password, id } => {
+                write!(f, "AdminRequest::RevokeInvite {}", id)
+            },
+            AdminRequest::GetSignups { password: _password } => {
+                write!(f, "AdminRequest::GetSignups")
+            },
+            AdminRequest::ApproveSignup { password: _password, id } => {
+                write!(f, "AdminRequest::ApproveSignup {}", id)
+            },
+            AdminRequest::RejectSignup { password: _password, id } => {
+                write!(f, "AdminRequest::RejectSignup {}", id)
+            },
+            AdminRequest::GetReportHistory { password: _password, mac } => {
+                write!(f, "AdminRequest::GetReportHistory {}", mac)
+            },
+            AdminRequest::ApproveReport { password: _password, id } => {
+                write!(f, "AdminRequest::ApproveReport {}", id)
+            },
+            AdminRequest::DismissReport { password: _password, id } => {
+                write!(f, "AdminRequest::DismissReport {}", id)
+            },
+            AdminRequest::ResetVotes { password: _password, ip } => {
+                write!(f, "AdminRequest::ResetVotes {:?}", ip)
+            },
+            AdminRequest::GrantImmunity { password: _password, ip, seconds } => {
+                write!(f, "AdminRequest::GrantImmunity {} {:?}", ip, seconds)
+            },
+            AdminRequest::RevokeImmunity { password: _password, ip } => {
+                write!(f, "AdminRequest::RevokeImmunity {}", ip)
             }            
         }
     }
@@ -480,14 +1429,48 @@ pub enum ClientRequest {
     },
     Drop {
         password: String,
-        ip: net::Ipv4Addr
+        ip: net::IpAddr,
+        reason: reports::ReportReason,
+        text: String
     },
     SignUp {
         password: String,
         username: String,
         mac: ipparser::MacAddress,
         port: u16,
-        get_only_by_mac: bool        
+        get_only_by_mac: bool,
+        // Proves that the client owns the MAC, it's given by the first sign up
+        owner_token: Option<String>
+    },
+    SignUpStatus {
+        password: String,
+        mac: ipparser::MacAddress
+    },
+    // Without the MAC the client is found by its IP
+    SignOut {
+        password: String,
+        mac: Option<ipparser::MacAddress>,
+        owner_token: Option<String>
+    },
+    // Only the fields that were sent are changed
+    Update {
+        password: String,
+        mac: Option<ipparser::MacAddress>,
+        owner_token: Option<String>,
+        username: Option<String>,
+        port: Option<u16>,
+        get_only_by_mac: Option<bool>
+    }
+}
+
+impl ClientRequest {
+    // With --force-signup only the signed up clients can make these requests
+    pub fn requires_signup(&self) -> bool {
+        match self {
+            ClientRequest::GetByMac { .. } | ClientRequest::GetByUsername { .. } | ClientRequest::Drop { .. } => true,
+            // The owner token lets the clients whose IP changed find their entry
+            ClientRequest::SignUp { .. } | ClientRequest::SignUpStatus { .. } | ClientRequest::SignOut { .. } | ClientRequest::Update { .. } => false
+        }
     }
 }
 
@@ -500,16 +1483,63 @@ impl fmt::Display for ClientRequest {
             ClientRequest::GetByUsername { password: _password, username, start_index } => {
                 write!(f, "ClientRequest::GetByUsername \"{}\" starting from {}", username, start_index)
             },
-            ClientRequest::Drop { password: _password, ip } => {
-                write!(f, "ClientRequest::Drop {}", ip)
+            ClientRequest::Drop { password: _password, ip, reason, text: _text } => {
+                write!(f, "ClientRequest::Drop {} ({})", ip, reason)
             },
-            ClientRequest::SignUp { password: _password, username, mac, port, get_only_by_mac } => {
+            ClientRequest::SignUp { password: _password, username, mac, port, get_only_by_mac, .. } => {
                 if *get_only_by_mac {
                     write!(f, "ClientRequest::SignUp {} \"{}\" PORT: {} MAC-ONLY", mac, username, port)
                 } else {
                     write!(f, "ClientRequest::SignUp {} \"{}\" PORT: {}", mac, username, port)
                 }
+            },
+            ClientRequest::SignUpStatus { mac, .. } => {
+                write!(f, "ClientRequest::SignUpStatus {}", mac)
+            },
+            ClientRequest::SignOut { mac, .. } => {
+                write!(f, "ClientRequest::SignOut {}", mac_or_own_ip(mac))
+            },
+            ClientRequest::Update { mac, username, port, get_only_by_mac, .. } => {
+                write!(f, "ClientRequest::Update {}{}", mac_or_own_ip(mac), update_fields_to_string(username, port, get_only_by_mac))
             }
         }
     }
 }
+
+// The optional mac and owner_token of sign_out and update, None if any of them is incorrect
+fn parse_client_owner(request: &serde_json::Value) -> Option<(Option<ipparser::MacAddress>, Option<String>)> {
+    let mut mac = None;
+    if let Some(m) = request.get("mac") {
+        if let Some(m) = m.as_str().and_then(ipparser::MacAddress::new_from_str) {
+            mac = Some(m);
+        } else { log::debug!("Request::from - parsing request: incorrect mac"); return None; }
+    }
+    let mut owner_token = None;
+    if let Some(t) = request.get("owner_token") {
+        if let Some(t) = t.as_str() {
+            owner_token = Some(String::from(t));
+        } else { log::debug!("Request::from - parsing request: incorrect owner_token"); return None; }
+    }
+    Some((mac, owner_token))
+}
+
+fn mac_or_own_ip(mac: &Option<ipparser::MacAddress>) -> String {
+    match mac {
+        Some(mac) => mac.to_string(),
+        None => String::from("(own IP)")
+    }
+}
+
+fn update_fields_to_string(username: &Option<String>, port: &Option<u16>, get_only_by_mac: &Option<bool>) -> String {
+    let mut fields = String::new();
+    if let Some(username) = username {
+        fields.push_str(&format!(" \"{}\"", username));
+    }
+    if let Some(port) = port {
+        fields.push_str(&format!(" PORT: {}", port));
+    }
+    if let Some(get_only_by_mac) = get_only_by_mac {
+        fields.push_str(if *get_only_by_mac { " MAC-ONLY" } else { " NOT MAC-ONLY" });
+    }
+    fields
+}
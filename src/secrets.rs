@@ -0,0 +1,254 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module keeps the admin key and the client password as salted PBKDF2 hashes, so the
+// plaintext secrets are never stored, logged nor sent back to anybody. Like SCRAM, only a digest
+// of the key that signs the requests is stored, so a stored hash can't be used to sign them.
+
+extern crate ring;
+
+use std::cell;
+use std::fmt;
+use std::num;
+use ring::digest;
+use ring::hmac;
+use ring::pbkdf2;
+use ring::rand;
+use ring::rand::SecureRandom;
+use crate::auth;
+
+// The hashed secrets look like scram-sha256$<iterations>$<salt in hex>$<stored key in hex>
+pub const HASH_PREFIX: &str = "scram-sha256$";
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+pub const SALT_LEN: usize = 16;
+const HASH_LEN: usize = digest::SHA256_OUTPUT_LEN;
+static ALGORITHM: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+// client key = HMAC(PBKDF2(secret), CLIENT_KEY_LABEL), stored key = SHA-256(client key)
+const CLIENT_KEY_LABEL: &[u8] = b"Client Key";
+
+pub struct Secret {
+    iterations: num::NonZeroU32,
+    salt: Vec<u8>,
+    stored_key: Vec<u8>,
+    // PBKDF2 is slow on purpose and the server is single threaded, once a candidate is
+    // verified only a SHA-256 of it (salted) is kept to check it again quickly
+    verified: cell::Cell<Option<[u8; HASH_LEN]>>
+}
+
+impl Secret {
+    pub fn hash(plaintext: &str) -> Option<Secret> {
+        let mut salt = vec![0u8; SALT_LEN];
+        rand::SystemRandom::new().fill(&mut salt).ok()?;
+        let iterations = num::NonZeroU32::new(DEFAULT_ITERATIONS)?;
+        let stored_key = stored_key_of(iterations, &salt, plaintext).to_vec();
+        Some(Secret { iterations, salt, stored_key, verified: cell::Cell::new(None) })
+    }
+
+    pub fn from_hash_str(hash_str: &str) -> Option<Secret> {
+        let mut parts = hash_str.strip_prefix(HASH_PREFIX)?.split('$');
+        let iterations = num::NonZeroU32::new(parts.next()?.parse::<u32>().ok()?)?;
+        let salt = from_hex(parts.next()?)?;
+        let stored_key = from_hex(parts.next()?)?;
+        if parts.next().is_some() || salt.is_empty() || stored_key.len() != HASH_LEN {
+            return None;
+        }
+        Some(Secret { iterations, salt, stored_key, verified: cell::Cell::new(None) })
+    }
+
+    // The config can have the secret already hashed or in plaintext, the plaintext is hashed
+    pub fn new_from_str(secret: &str) -> Option<Secret> {
+        if is_hash_str(secret) {
+            return Secret::from_hash_str(secret);
+        }
+        Secret::hash(secret)
+    }
+
+    // The candidates that can't be a secret are refused before the PBKDF2
+    pub fn verify(&self, candidate: &str) -> bool {
+        if !is_plaintext_secret(candidate) {
+            return false;
+        }
+        let quick = self.quick_digest(candidate);
+        self.is_verified(&quick) || self.accept(&self.derive(candidate), quick)
+    }
+
+    fn is_verified(&self, quick: &[u8; HASH_LEN]) -> bool {
+        match self.verified.get() {
+            Some(verified) => auth::constant_time_eq_bytes(&verified, quick),
+            None => false
+        }
+    }
+
+    fn derive(&self, candidate: &str) -> [u8; HASH_LEN] {
+        stored_key_of(self.iterations, &self.salt, candidate)
+    }
+
+    fn accept(&self, derived: &[u8; HASH_LEN], quick: [u8; HASH_LEN]) -> bool {
+        if auth::constant_time_eq_bytes(derived, &self.stored_key) {
+            self.verified.set(Some(quick));
+            return true;
+        }
+        false
+    }
+
+    // The challenge-response clients derive the client key from the salt and the iterations, and
+    // send it masked with the signature of the message: proof = client key XOR HMAC(stored key,
+    // message). The server unmasks it and compares its digest with the stored key, whoever only
+    // knows the stored key can compute the signature but not the client key
+    pub fn verify_hmac(&self, message: &[u8], proof: &[u8]) -> bool {
+        if proof.len() != HASH_LEN {
+            return false;
+        }
+        let signature = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &self.stored_key), message);
+        let client_key: Vec<u8> = proof.iter().zip(signature.as_ref().iter()).map(|(p, s)| p ^ s).collect();
+        auth::constant_time_eq_bytes(digest::digest(&digest::SHA256, &client_key).as_ref(), &self.stored_key)
+    }
+
+    pub fn salt_to_string(&self) -> String {
+        to_hex(&self.salt)
+    }
+
+    pub fn iterations(&self) -> u32 {
+        self.iterations.get()
+    }
+
+    fn quick_digest(&self, candidate: &str) -> [u8; HASH_LEN] {
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        ctx.update(&self.salt);
+        ctx.update(candidate.as_bytes());
+        let mut quick = [0u8; HASH_LEN];
+        quick.copy_from_slice(ctx.finish().as_ref());
+        quick
+    }
+
+    pub fn to_hash_string(&self) -> String {
+        format!("{}{}${}${}", HASH_PREFIX, self.iterations, to_hex(&self.salt), to_hex(&self.stored_key))
+    }
+}
+
+impl Clone for Secret {
+    fn clone(&self) -> Self {
+        Secret { iterations: self.iterations, salt: self.salt.clone(), stored_key: self.stored_key.clone(), verified: cell::Cell::new(self.verified.get()) }
+    }
+}
+
+// Whatever prints a secret prints this instead
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[redacted]")
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret([redacted])")
+    }
+}
+
+// A replaced secret that is still accepted until its grace period ends, so the clients have time
+// to change it
+#[derive(Clone)]
+pub struct RetiredSecret {
+    pub secret: Secret,
+    // Seconds since the UNIX epoch
+    pub until: u64
+}
+
+impl RetiredSecret {
+    // None if there is no grace period
+    pub fn new(secret: Secret, now: u64, grace: u64) -> Option<RetiredSecret> {
+        if grace == 0 {
+            return None;
+        }
+        Some(RetiredSecret { secret, until: now.saturating_add(grace) })
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        now < self.until
+    }
+}
+
+// Which secret of a rotation a candidate is
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RotationMatch {
+    Current,
+    Retired,
+    Neither
+}
+
+// The retired secret is only accepted while its grace period lasts, it has its own salt so a wrong
+// candidate costs a second PBKDF2 until the grace period ends
+pub fn verify_rotated(current: &Secret, retired: Option<&RetiredSecret>, now: u64, candidate: &str) -> RotationMatch {
+    if !is_plaintext_secret(candidate) {
+        return RotationMatch::Neither;
+    }
+    let retired = retired.filter(|r| r.is_active(now)).map(|r| &r.secret);
+    let quick = current.quick_digest(candidate);
+    if current.is_verified(&quick) {
+        return RotationMatch::Current;
+    }
+    let retired_quick = retired.map(|r| r.quick_digest(candidate));
+    if let (Some(retired), Some(retired_quick)) = (retired, retired_quick) {
+        if retired.is_verified(&retired_quick) {
+            return RotationMatch::Retired;
+        }
+    }
+    if current.accept(&current.derive(candidate), quick) {
+        return RotationMatch::Current;
+    }
+    if let (Some(retired), Some(retired_quick)) = (retired, retired_quick) {
+        if retired.accept(&retired.derive(candidate), retired_quick) {
+            return RotationMatch::Retired;
+        }
+    }
+    RotationMatch::Neither
+}
+
+pub fn verify_hmac_rotated(current: &Secret, retired: Option<&RetiredSecret>, now: u64, message: &[u8], proof: &[u8]) -> RotationMatch {
+    if current.verify_hmac(message, proof) {
+        return RotationMatch::Current;
+    }
+    // The clients that still have the old secret derive their key with its salt, the challenge
+    // gives it while the grace period lasts
+    let retired = retired.filter(|r| r.is_active(now));
+    if retired.map(|r| r.secret.verify_hmac(message, proof)).unwrap_or(false) {
+        return RotationMatch::Retired;
+    }
+    RotationMatch::Neither
+}
+
+fn stored_key_of(iterations: num::NonZeroU32, salt: &[u8], secret: &str) -> [u8; HASH_LEN] {
+    let mut salted = [0u8; HASH_LEN];
+    pbkdf2::derive(ALGORITHM, iterations, salt, secret.as_bytes(), &mut salted);
+    let client_key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &salted), CLIENT_KEY_LABEL);
+    let mut stored_key = [0u8; HASH_LEN];
+    stored_key.copy_from_slice(digest::digest(&digest::SHA256, client_key.as_ref()).as_ref());
+    stored_key
+}
+
+pub fn is_hash_str(secret: &str) -> bool {
+    secret.starts_with(HASH_PREFIX)
+}
+
+// The plaintext secrets must contain only ascii characters and less than 33 characters, the
+// hashed ones must be well formed
+pub fn is_valid_secret(secret: &str) -> bool {
+    if is_hash_str(secret) {
+        return Secret::from_hash_str(secret).is_some();
+    }
+    is_plaintext_secret(secret)
+}
+
+pub fn is_plaintext_secret(secret: &str) -> bool {
+    secret.is_ascii() && secret.len() < 33
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
@@ -3,245 +3,1022 @@
 // This module starts the server and hanldes the incomming connections.
 
 extern crate log;
+extern crate ctrlc;
+extern crate rustls;
 
 use std::io::{
+    self,
     Read,
     Write
 };
 use std::net;
 use std::process;
+use std::thread;
+use std::time;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use crate::clients;
 use crate::config;
+use crate::ipparser;
 use crate::requests;
 use crate::replies;
+use crate::snapshot;
+use crate::reports;
+use crate::ratelimit;
+use crate::auth;
+use crate::bans;
+use crate::secrets;
+use crate::tls;
+use crate::sessions;
+use crate::accounts;
+use crate::audit;
+use crate::invites;
+use crate::signups;
 use std::fmt;
 
+// How long the accept loop sleeps when there are no incoming connections
+const ACCEPT_POLL_INTERVAL: time::Duration = time::Duration::from_millis(50);
+// How long the server waits for each client when it notifies the shutdown
+const NOTIFY_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+// How long a client can take to finish the TLS handshake, the accept loop waits for it
+const TLS_HANDSHAKE_TIMEOUT: time::Duration = time::Duration::from_secs(3);
+
 pub struct Server {
     pub clients: clients::ClientsMap,
-    pub address: net::SocketAddrV4,
-    pub key: String,
-    pub password: String,
+    pub listeners: Vec<config::Listener>,
+    pub key: secrets::Secret,
+    pub password: secrets::Secret,
+    // The replaced key and password while their grace period lasts
+    pub old_key: Option<secrets::RetiredSecret>,
+    pub old_password: Option<secrets::RetiredSecret>,
     pub drop_votes: u8,
     pub capacity: u16,
     pub list_size: u16,
-    pub drop_verification: bool
+    pub drop_verification: bool,
+    pub snapshot: Option<String>,
+    pub audit: Option<audit::AuditLog>,
+    pub shutdown_timeout: u64,
+    // How long a connection can take to send its request and get its reply
+    pub io_timeout: u64,
+    pub notify_on_shutdown: bool,
+    pub remote_admin: Vec<ipparser::Cidr>,
+    pub force_signup: bool,
+    pub vote_expiry: u64,
+    pub lease: u64,
+    pub rotation_grace: u64,
+    pub drop_quorum: Option<config::DropQuorum>,
+    pub report_queue: bool,
+    pub signup_approval: bool,
+    pub reports: reports::ReportsQueue,
+    pub rate_limiter: ratelimit::RateLimiter,
+    pub auth_guard: auth::AuthGuard,
+    pub auth_mode: config::AuthMode,
+    pub challenges: auth::ChallengeStore,
+    pub sessions: sessions::SessionStore,
+    pub accounts: accounts::AccountStore,
+    // Only when there are TLS listeners
+    pub tls_config: Option<Arc<rustls::ServerConfig>>,
+    pub bans: bans::BanList,
+    pub invites: invites::InviteList,
+    pub signups: signups::SignupQueue,
+    // Empty means that the clients can be anywhere
+    pub client_subnets: Vec<ipparser::Cidr>,
+    pub auto_subnet: bool
 }
 
 impl fmt::Display for Server {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.drop_verification {
-            write!(f,"MINT server listening on {}\\n=> key:        {}\\n=> password:   {}\\n=> drop-votes: {}\\n=> list-size:  {}\\n=> capacity:   up to {} user(s)\\n=> drop-verification is enabled\\n=> {} user(s) are signed up", self.address, self.key, self.password, self.drop_votes, self.list_size, self.capacity, self.clients.len())
+            write!(f,"MINT server listening on {}\\n=> key:        {}\\n=> password:   {}\\n=> rotation:   {}\\n=> drop-votes: {}\\n=> list-size:  {}\\n=> capacity:   up to {} user(s)\\n=> drop-verification is enabled\\n=> snapshot:   {}\\n=> audit-log:  {}\\n=> remote-admin: {}\\n=> force-signup: {}\\n=> vote-expiry:  {}\\n=> lease:        {}\\n=> drop-quorum:  {}\\n=> {} vote(s) are needed to drop out a client\\n=> report-queue: {}\\n=> {} pending report(s)\\n=> signup-approval: {}\\n=> {} pending sign up(s)\\n=> lookup-rate:   {}\\n=> mutation-rate: {}\\n=> admin-rate:    {}\\n=> lockout:       {}\\n=> auth-mode:     {}\\n=> session-ttl:   {} second(s), {} admin session(s)\\n=> {} admin account(s)\\n=> {} ban(s)\\n=> {} invite(s)\\n=> client-subnets: {}\\n=> {} user(s) are signed up", self.listeners_to_string(), self.key, self.password, self.rotation_to_string(), self.drop_votes, self.list_size, self.capacity, self.snapshot.as_deref().unwrap_or("disabled"), self.audit_log_to_string(), config::cidrs_to_string(&self.remote_admin, "loopback only"), self.force_signup, config::vote_expiry_to_string(self.vote_expiry), config::lease_to_string(self.lease), config::drop_quorum_to_string(&self.drop_quorum), self.drop_votes_needed(), self.report_queue, self.reports.pending().len(), self.signup_approval, self.signups.pending().len(), config::rate_limit_to_string(&self.rate_limiter.limit_of(ratelimit::RequestCategory::Lookup)), config::rate_limit_to_string(&self.rate_limiter.limit_of(ratelimit::RequestCategory::Mutation)), config::rate_limit_to_string(&self.rate_limiter.limit_of(ratelimit::RequestCategory::Admin)), config::lockout_to_string(self.auth_guard.max_failures(), self.auth_guard.base_lockout().as_secs()), self.auth_mode, self.sessions.ttl(), self.sessions.len(), self.accounts.len() + 1, self.bans.len(), self.invites.len(), config::cidrs_to_string(&self.client_subnets, if self.auto_subnet { "loopback only" } else { "any" }), self.clients.len())
         } else {
-            write!(f,"MINT server listening on {}\\n=> key:        {}\\n=> password:   {}\\n=> drop-votes: {}\\n=> list-size:  {}\\n=> capacity:   up to {} user(s)\\n=> drop-verification is disabled\\n=> {} user(s) are signed up", self.address, self.key, self.password, self.drop_votes, self.list_size, self.capacity, self.clients.len())
+            write!(f,"MINT server listening on {}\\n=> key:        {}\\n=> password:   {}\\n=> rotation:   {}\\n=> drop-votes: {}\\n=> list-size:  {}\\n=> capacity:   up to {} user(s)\\n=> drop-verification is disabled\\n=> snapshot:   {}\\n=> audit-log:  {}\\n=> remote-admin: {}\\n=> force-signup: {}\\n=> vote-expiry:  {}\\n=> lease:        {}\\n=> drop-quorum:  {}\\n=> {} vote(s) are needed to drop out a client\\n=> report-queue: {}\\n=> {} pending report(s)\\n=> signup-approval: {}\\n=> {} pending sign up(s)\\n=> lookup-rate:   {}\\n=> mutation-rate: {}\\n=> admin-rate:    {}\\n=> lockout:       {}\\n=> auth-mode:     {}\\n=> session-ttl:   {} second(s), {} admin session(s)\\n=> {} admin account(s)\\n=> {} ban(s)\\n=> {} invite(s)\\n=> client-subnets: {}\\n=> {} user(s) are signed up", self.listeners_to_string(), self.key, self.password, self.rotation_to_string(), self.drop_votes, self.list_size, self.capacity, self.snapshot.as_deref().unwrap_or("disabled"), self.audit_log_to_string(), config::cidrs_to_string(&self.remote_admin, "loopback only"), self.force_signup, config::vote_expiry_to_string(self.vote_expiry), config::lease_to_string(self.lease), config::drop_quorum_to_string(&self.drop_quorum), self.drop_votes_needed(), self.report_queue, self.reports.pending().len(), self.signup_approval, self.signups.pending().len(), config::rate_limit_to_string(&self.rate_limiter.limit_of(ratelimit::RequestCategory::Lookup)), config::rate_limit_to_string(&self.rate_limiter.limit_of(ratelimit::RequestCategory::Mutation)), config::rate_limit_to_string(&self.rate_limiter.limit_of(ratelimit::RequestCategory::Admin)), config::lockout_to_string(self.auth_guard.max_failures(), self.auth_guard.base_lockout().as_secs()), self.auth_mode, self.sessions.ttl(), self.sessions.len(), self.accounts.len() + 1, self.bans.len(), self.invites.len(), config::cidrs_to_string(&self.client_subnets, if self.auto_subnet { "loopback only" } else { "any" }), self.clients.len())
         }        
     }
 }
 
 impl Server {
     pub fn from_start_config(start_config: &config::StartConfig) -> Server {
+        let mut clients = clients::ClientsMap::new();
+        let mut reports = reports::ReportsQueue::new();
+        let mut bans = bans::BanList::new();
+        let mut invites = invites::InviteList::new();
+        let mut signups = signups::SignupQueue::new();
+        let mut accounts = accounts::AccountStore::new();
+        if let Some(path) = &start_config.snapshot {
+            match snapshot::read_snapshot(path) {
+                Ok(snapshot) => {
+                    log::info!("{} client(s), {} pending report(s), {} ban(s), {} invite(s), {} pending sign up(s) and {} admin account(s) were restored from {}", snapshot.clients.len(), snapshot.reports.pending().len(), snapshot.bans.len(), snapshot.invites.len(), snapshot.signups.pending().len(), snapshot.accounts.len(), path);
+                    clients = snapshot.clients;
+                    reports = snapshot.reports;
+                    bans = snapshot.bans;
+                    invites = snapshot.invites;
+                    signups = snapshot.signups;
+                    accounts = snapshot.accounts;
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => log::info!("There is no snapshot in {} yet, starting with no clients", path),
+                Err(e) => {
+                    log::error!("I couldn't restore the clients from {}: {}", path, e);
+                    process::exit(1);
+                }
+            }
+        }
+        let mut audit = None;
+        if let (Some(path), Some(key_path)) = (&start_config.audit_log, &start_config.audit_key) {
+            let key = match audit::read_key(key_path) {
+                Ok(key) => key,
+                Err(e) => {
+                    log::error!("{}", e);
+                    process::exit(1);
+                }
+            };
+            match audit::AuditLog::open(path, key) {
+                Ok(audit_log) => {
+                    log::info!("The admin actions are appended to {}, it has {} entries", path, audit_log.entries());
+                    audit = Some(audit_log);
+                },
+                Err(e) => {
+                    log::error!("I couldn't open the audit log {}: {}", path, e);
+                    process::exit(1);
+                }
+            }
+        }
+        let mut tls_config = None;
+        if start_config.listeners.iter().any(|l| l.tls) {
+            let certificate = match (&start_config.tls_cert, &start_config.tls_key) {
+                (Some(cert_path), Some(key_path)) => tls::load_certificate(cert_path, key_path),
+                _ => tls::generate_self_signed(&start_config.listeners)
+            };
+            match certificate {
+                Ok((certs, key)) => {
+                    // The clients compare it with the certificate they get, specially a self-signed one
+                    log::info!("The TLS certificate fingerprint (SHA-256) is {}", tls::fingerprint(certs[0].as_ref()));
+                    match tls::server_config(certs, key, &start_config.tls_admin_certs) {
+                        Ok(config) => tls_config = Some(config),
+                        Err(e) => {
+                            log::error!("{}", e);
+                            process::exit(1);
+                        }
+                    }
+                },
+                Err(e) => {
+                    log::error!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        for account in start_config.admin_accounts.iter() {
+            accounts.replace(account.clone());
+        }
+        let mut client_subnets = start_config.client_subnets.clone();
+        if start_config.auto_subnet {
+            match config::detect_client_subnets(&start_config.listeners) {
+                Ok(subnets) => {
+                    if subnets.is_empty() {
+                        log::warn!("I couldn't detect the networks of the client addresses, only the loopback and the --client-subnet networks are allowed");
+                    }
+                    for cidr in subnets {
+                        log::info!("The clients of {} are allowed", cidr);
+                        if !client_subnets.contains(&cidr) {
+                            client_subnets.push(cidr);
+                        }
+                    }
+                },
+                Err(e) => {
+                    log::error!("I couldn't detect the networks of the interfaces: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
         Server {
-            clients: clients::ClientsMap::new(),
-            address: start_config.address.clone(),
+            clients,
+            listeners: start_config.listeners.clone(),
             key: start_config.key.clone(),
             password: start_config.password.clone(),
+            old_key: None,
+            old_password: None,
             drop_votes: start_config.drop_votes,
             capacity: start_config.capacity,
             list_size: start_config.list_size,
-            drop_verification: start_config.drop_verification
+            drop_verification: start_config.drop_verification,
+            snapshot: start_config.snapshot.clone(),
+            audit,
+            shutdown_timeout: start_config.shutdown_timeout,
+            io_timeout: start_config.io_timeout,
+            notify_on_shutdown: start_config.notify_on_shutdown,
+            remote_admin: start_config.remote_admin.clone(),
+            force_signup: start_config.force_signup,
+            vote_expiry: start_config.vote_expiry,
+            lease: start_config.lease,
+            rotation_grace: start_config.rotation_grace,
+            drop_quorum: start_config.drop_quorum,
+            report_queue: start_config.report_queue,
+            signup_approval: start_config.signup_approval,
+            reports,
+            rate_limiter: ratelimit::RateLimiter::new(start_config.lookup_rate, start_config.mutation_rate, start_config.admin_rate),
+            auth_guard: auth::AuthGuard::new(start_config.max_auth_failures, time::Duration::from_secs(start_config.lockout)),
+            auth_mode: start_config.auth_mode,
+            challenges: auth::ChallengeStore::new(),
+            sessions: sessions::SessionStore::new(start_config.session_ttl),
+            accounts,
+            tls_config,
+            bans,
+            invites,
+            signups,
+            client_subnets,
+            auto_subnet: start_config.auto_subnet
         }
     }
 
+    fn listeners_to_string(&self) -> String {
+        self.listeners.iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")
+    }
+
+    // Tells which old secrets are still accepted and until when
+    fn rotation_to_string(&self) -> String {
+        let now = clients::unix_now();
+        let mut rotations = Vec::new();
+        if let Some(old_key) = self.old_key.as_ref().filter(|k| k.is_active(now)) {
+            rotations.push(format!("the old key is accepted until {}", old_key.until));
+        }
+        if let Some(old_password) = self.old_password.as_ref().filter(|p| p.is_active(now)) {
+            rotations.push(format!("the old password is accepted until {}", old_password.until));
+        }
+        if rotations.is_empty() {
+            return String::from("none pending");
+        }
+        rotations.join(", ")
+    }
+
+    fn expire_old_secrets(&mut self) {
+        let now = clients::unix_now();
+        if self.old_key.as_ref().map(|k| !k.is_active(now)).unwrap_or(false) {
+            self.old_key = None;
+            log::info!("The grace period of the old key ended, only the new one is accepted");
+        }
+        if self.old_password.as_ref().map(|p| !p.is_active(now)).unwrap_or(false) {
+            self.old_password = None;
+            log::info!("The grace period of the old password ended, only the new one is accepted");
+        }
+    }
+
+    fn rotation_of(&self, kind: auth::SecretKind) -> (&secrets::Secret, Option<&secrets::RetiredSecret>) {
+        match kind {
+            auth::SecretKind::AdminKey => (&self.key, self.old_key.as_ref()),
+            auth::SecretKind::ClientPassword => (&self.password, self.old_password.as_ref())
+        }
+    }
+
+    // The old secret of a grace period is checked with its own salt only if the candidate isn't the
+    // secret
+    fn verify_secret(&mut self, candidate: &str, kind: auth::SecretKind, peer_addr: &net::SocketAddr) -> bool {
+        self.expire_old_secrets();
+        let (secret, old_secret) = self.rotation_of(kind);
+        let rotation_match = secrets::verify_rotated(secret, old_secret, clients::unix_now(), candidate);
+        self.is_accepted(rotation_match, kind, peer_addr)
+    }
+
+    fn verify_signed_secret(&mut self, message: &[u8], proof: &[u8], kind: auth::SecretKind, peer_addr: &net::SocketAddr) -> bool {
+        self.expire_old_secrets();
+        let (secret, old_secret) = self.rotation_of(kind);
+        let rotation_match = secrets::verify_hmac_rotated(secret, old_secret, clients::unix_now(), message, proof);
+        self.is_accepted(rotation_match, kind, peer_addr)
+    }
+
+    // Whoever still uses an old secret is warned about in the logs, so the admins know who hasn't
+    // changed it yet
+    fn is_accepted(&self, rotation_match: secrets::RotationMatch, kind: auth::SecretKind, peer_addr: &net::SocketAddr) -> bool {
+        match rotation_match {
+            secrets::RotationMatch::Current => true,
+            secrets::RotationMatch::Retired => {
+                let until = self.rotation_of(kind).1.map(|s| s.until).unwrap_or(0);
+                log::warn!("{} still uses the old {}, it's accepted until {}", peer_addr, kind, until);
+                true
+            },
+            secrets::RotationMatch::Neither => false
+        }
+    }
+
+    fn audit_log_to_string(&self) -> String {
+        match &self.audit {
+            Some(audit) => format!("{} ({} entries)", audit.path(), audit.entries()),
+            None => String::from("disabled")
+        }
+    }
+
+    // The actions of the admins that proved who they are, the ones refused for their role too
+    fn audit(&mut self, admin: &accounts::ActingAdmin, action: &str, reply: &str) {
+        if let Some(audit) = &mut self.audit {
+            if let Err(e) = audit.record(clients::unix_now(), admin, action, reply) {
+                log::error!("I couldn't append the {} of the admin {} to the audit log {}: {}", action, admin, audit.path(), e);
+            }
+        }
+    }
+
+    // The admin requests refused before the admin proved who it is
+    fn audit_refused(&mut self, account: &str, peer_addr: &net::SocketAddr, action: &str, reply: &str) {
+        if let Some(audit) = &mut self.audit {
+            if let Err(e) = audit.record_refused(clients::unix_now(), account, peer_addr, action, reply) {
+                log::error!("I couldn't append the refused {} of {} to the audit log {}: {}", action, peer_addr, audit.path(), e);
+            }
+        }
+    }
+
+    // The loopback is always allowed, any other address must be in one of the --remote-admin networks
+    fn is_remote_admin_allowed(&self, ip: &net::IpAddr) -> bool {
+        ipparser::unmap_ipv4(ip).is_loopback() || self.remote_admin.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    // With --auto-subnet and nothing detected only the loopback is allowed
+    fn is_client_allowed(&self, ip: &net::IpAddr) -> bool {
+        let ip = ipparser::unmap_ipv4(ip);
+        let restricted = !self.client_subnets.is_empty() || self.auto_subnet;
+        !restricted || ip.is_loopback() || self.client_subnets.iter().any(|cidr| cidr.contains(&ip))
+    }
+
     pub fn run(&mut self) {
-        if let Ok(listener) = net::TcpListener::bind(self.address) {
-            log::info!("I'm listening on {}", self.address);
-            let mut reply;
-            let mut request_type: String = "UnparsedRequest".to_string();
-            for stream in listener.incoming() {
-                match stream {
-                    Ok(mut stream) => {                        
-                        if let Ok(peer_addr) = stream.peer_addr() {
-                            if let net::SocketAddr::V4(peer_addr) = peer_addr {
-                                let mut buffer = [0; 1024];
-                                if let Ok(bytes_read) = stream.read(&mut buffer) {
-                                    let request = String::from_utf8_lossy(&buffer[..bytes_read]);
-                                    if let Some(request) = requests::Request::from(&request) {
-                                        request_type = request.to_string();
-                                        match request {
-                                            requests::Request::Admin(a_request) => {
-                                                // if peer_addr.ip() == self.address.ip() {
-                                                    match a_request {
-                                                        requests::AdminRequest::Drop { password, ip } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_drop(&ip, &mut self.clients, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::GetByIndex { password, start_index, end_index } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_getbyindex(start_index, end_index, &self.clients, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::GetByMac { password, mac } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_getbymac(&mac, &self.clients, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::GetByUsername { password, username, start_index } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_getbyusername(&username, &self.clients, self.list_size, start_index, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::GetRunningConfiguration { password } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_getrunningconfiguration(&self, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::SetCapacity { password, capacity } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_setcapacity(capacity, &mut self.capacity, self.clients.len(), &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::SetDropVerification { password, drop_verification } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_setdropverification(drop_verification, &mut self.drop_verification, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::SetDropVotes { password, drop_votes } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_setdropvotes(drop_votes, &mut self.drop_votes, &mut self.clients, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::SetKey { password, key } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_setkey(&key, &mut self.key, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::SetListSize { password, list_size } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_setlistsize(list_size, &mut self.list_size, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        },
-                                                        requests::AdminRequest::SetPassword { password, new_password } => {
-                                                            if self.key == password {
-                                                                reply = replies::reply_admin_setpassword(&new_password, &mut self.password, &peer_addr);
-                                                            } else { 
-                                                                log::info!("The admin {} forgot the password", peer_addr);
-                                                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                            }
-                                                        }
-                                                    }
-                                                // } else {
-                                                //     log::warn!("{} tried to administrate the server", peer_addr);
-                                                //     reply = replies::ReplyErrCodes::RemoteAdminIsNotAllowed.to_string();
-                                                // }
-                                            },
-                                            requests::Request::Client(c_request) => {
-                                                match c_request {
-                                                    requests::ClientRequest::GetByMac { password: client_password, mac } => {
-                                                        if self.password == client_password {
-                                                            reply = replies::reply_client_getbymac(&mac, &self.clients, &peer_addr);
-                                                        } else { 
-                                                            log::info!("The client {} doesn't know the password", peer_addr);
-                                                            reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                        }
-                                                    },
-                                                    requests::ClientRequest::GetByUsername { password: client_password, username, start_index } => {
-                                                        if self.password == client_password {
-                                                            reply = replies::reply_client_getbyusername(&username, &self.clients, self.list_size, start_index, &peer_addr);
-                                                        } else { 
-                                                            log::info!("The client {} doesn't know the password", peer_addr);
-                                                            reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                        }
-                                                    },
-                                                    requests::ClientRequest::Drop { password: client_password, ip } => {
-                                                        if self.password == client_password {
-                                                            reply = replies::reply_client_drop(&ip, &mut self.clients, self.drop_votes, &peer_addr);
-                                                        } else { 
-                                                            log::info!("The client {} doesn't know the password", peer_addr);
-                                                            reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                        }
-                                                        log::debug!("Client's DB:\n{}", self.clients);
-                                                    },
-                                                    requests::ClientRequest::SignUp { password: client_password, username, mac, port, get_only_by_mac } => {
-                                                        if self.password == client_password {
-                                                            reply = replies::reply_client_signup(&mut self.clients, &username, &mac, &peer_addr.ip(), port, get_only_by_mac, self.capacity);
-                                                        } else { 
-                                                            log::info!("The client {} doesn't know the password", peer_addr);
-                                                            reply = replies::ReplyErrCodes::WrongPassword.to_string();
-                                                        }
-                                                        log::debug!("Client's DB:\n{}", self.clients);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    } else {
-                                        log::info!("I couldn't parse the request of {}", peer_addr);
-                                        reply = replies::ReplyErrCodes::UnparsableRequest.to_string();
-                                    }
-                                } else {
-                                    log::error!("I couldn't read the request of {}", peer_addr);
-                                    reply = replies::ReplyErrCodes::ServerInternalError.to_string();
-                                }
-                            } else {
-                                log::info!("Host {} tried to use IPv6, but it's not supported", peer_addr);
-                                reply = replies::ReplyErrCodes::OnlyIpv4Supported.to_string();
-                            }
+        let running = Arc::new(AtomicBool::new(true));
+        let handler_running = running.clone();
+        if let Err(e) = ctrlc::set_handler(move || handler_running.store(false, Ordering::SeqCst)) {
+            log::error!("I couldn't set the SIGINT/SIGTERM handler: {}", e);
+        }
 
-                            if let Ok(bytes_written) = stream.write(reply.as_bytes()) {
-                                if bytes_written == reply.len() {
-                                    log::debug!("{} from {} Ok!", request_type, peer_addr);
-                                } else {
-                                    log::error!("{} Err! I sent {} of {} bytes to {}", request_type, bytes_written, reply.as_bytes().len(), peer_addr);
-                                }
-                            } else {
-                                log::error!("{} Err! I couldn't sent the reply to {}", request_type, peer_addr);
-                            }
-                        } else {
-                            log::error!("I couldn't get to peer address of a client");
-                        }                        
+        let mut listeners: Vec<(net::TcpListener, config::Listener)> = Vec::new();
+        for listener_config in self.listeners.iter() {
+            if let Ok(listener) = net::TcpListener::bind(listener_config.address) {
+                if let Err(e) = listener.set_nonblocking(true) {
+                    log::error!("I couldn't make {} non-blocking: {}", listener_config.address, e);
+                    process::exit(1);
+                }
+                log::info!("I'm listening on {}", listener_config);
+                listeners.push((listener, listener_config.clone()));
+            } else {
+                log::error!("I couldn't bind to {} :/", listener_config.address);
+                process::exit(1);
+            }
+        }
+
+        let io_timeout = time::Duration::from_secs(self.io_timeout);
+        while running.load(Ordering::SeqCst) {
+            let mut accepted_any = false;
+            for (listener, listener_config) in listeners.iter() {
+                match listener.accept() {
+                    Ok((stream, _peer_addr)) => {
+                        accepted_any = true;
+                        self.handle_connection(stream, listener_config, io_timeout);
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                    Err(e) => {
+                        log::error!("{}", e);
+                    }
+                }
+            }
+            if !accepted_any {
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+        }
+        log::info!("I was asked to stop, finishing the pending requests in at most {} second(s)", self.shutdown_timeout);
+        let deadline = time::Instant::now() + time::Duration::from_secs(self.shutdown_timeout);
+        self.drain(&listeners, deadline);
+        drop(listeners);
+        self.shutdown(deadline);
+    }
+
+    // Serves the connections that were already waiting to be accepted, until there are
+    // no more of them or the shutdown timeout expires
+    fn drain(&mut self, listeners: &[(net::TcpListener, config::Listener)], deadline: time::Instant) {
+        let io_timeout = time::Duration::from_secs(self.io_timeout);
+        let mut drained = 0;
+        'listeners: for (listener, listener_config) in listeners.iter() {
+            loop {
+                let now = time::Instant::now();
+                if now >= deadline {
+                    log::warn!("The shutdown timeout expired, pending connections will be dropped");
+                    break 'listeners;
+                }
+                match listener.accept() {
+                    Ok((stream, _peer_addr)) => {
+                        // A connection can't take longer than what is left of the shutdown timeout
+                        self.handle_connection(stream, listener_config, io_timeout.min(deadline - now));
+                        drained += 1;
                     },
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
                     Err(e) => {
                         log::error!("{}", e);
+                        break;
+                    }
+                }
+            }
+        }
+        log::info!("{} pending request(s) were served before stopping", drained);
+    }
+
+    // The snapshot is saved even if the shutdown timeout expired
+    fn shutdown(&mut self, deadline: time::Instant) {
+        if self.notify_on_shutdown {
+            self.notify_clients(deadline);
+        }
+        if let Some(path) = &self.snapshot {
+            match self.save_snapshot() {
+                Ok(()) => log::info!("The clients were saved into {}", path),
+                Err(e) => log::error!("I couldn't save the clients into {}: {}", path, e)
+            }
+        }
+        log::info!("MINT stopped with {} client(s) signed up\n{}", self.clients.len(), self.clients);
+    }
+
+    fn save_snapshot(&self) -> io::Result<()> {
+        match &self.snapshot {
+            Some(path) => snapshot::write_snapshot(path, &self.clients, &self.reports, &self.bans, &self.invites, &self.signups, &self.accounts),
+            None => Ok(())
+        }
+    }
+
+    // The bans, the accounts and the approved sign ups must survive a crash, so they are saved as
+    // soon as they change instead of waiting for the shutdown
+    fn persist(&self, what: &str) {
+        if let Err(e) = self.save_snapshot() {
+            log::error!("I couldn't save the {} into {}: {}", what, self.snapshot.as_deref().unwrap_or(""), e);
+        }
+    }
+
+    // The clients are notified one by one within what is left of the shutdown timeout, the ones
+    // that are left when it expires are not notified
+    fn notify_clients(&self, deadline: time::Instant) {
+        let notice = "{\"event\":\"shutdown\"}";
+        let clients = self.clients.range(0, self.clients.len());
+        for (index, (mac, client)) in clients.iter().enumerate() {
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            if remaining.as_millis() == 0 {
+                log::warn!("The shutdown timeout expired, {} client(s) were not notified", clients.len() - index);
+                return;
+            }
+            let client_addr = client.socket_addr();
+            match net::TcpStream::connect_timeout(&client_addr, NOTIFY_TIMEOUT.min(remaining)) {
+                Ok(mut stream) => {
+                    let remaining = deadline.saturating_duration_since(time::Instant::now()).max(time::Duration::from_millis(1));
+                    if let Err(e) = stream.set_write_timeout(Some(NOTIFY_TIMEOUT.min(remaining))).and_then(|_| stream.write_all(notice.as_bytes())) {
+                        log::debug!("I couldn't notify {} {} about the shutdown: {}", mac, client_addr, e);
+                    }
+                },
+                Err(e) => log::debug!("I couldn't notify {} {} about the shutdown: {}", mac, client_addr, e)
+            }
+        }
+    }
+
+    fn handle_connection(&mut self, mut stream: net::TcpStream, listener: &config::Listener, io_timeout: time::Duration) {
+        // Some platforms make the accepted streams inherit the non-blocking mode of the listener
+        if stream.set_nonblocking(false).is_err() || stream.set_read_timeout(Some(io_timeout)).is_err() || stream.set_write_timeout(Some(io_timeout)).is_err() {
+            log::error!("I couldn't configure the connection of a client");
+            return;
+        }
+
+        if let Ok(peer_addr) = stream.peer_addr() {
+            if !listener.tls {
+                let request = read_request(&mut stream);
+                self.serve(request, &mut stream, &peer_addr, listener.role, false);
+                return;
+            }
+            let tls_connection = match &self.tls_config {
+                Some(tls_config) => rustls::ServerConnection::new(tls_config.clone()),
+                None => {
+                    log::error!("{} connected to {}, but TLS is not set up", peer_addr, listener);
+                    return;
+                }
+            };
+            match tls_connection {
+                Ok(mut tls_connection) => {
+                    if let Err(e) = tls_handshake(&mut tls_connection, &mut stream, time::Instant::now() + TLS_HANDSHAKE_TIMEOUT) {
+                        log::warn!("The TLS handshake with {} failed: {}", peer_addr, e);
+                        return;
+                    }
+                    if stream.set_read_timeout(Some(io_timeout)).is_err() || stream.set_write_timeout(Some(io_timeout)).is_err() {
+                        log::error!("I couldn't configure the connection of {}", peer_addr);
+                        return;
+                    }
+                    let mut tls_stream = rustls::StreamOwned::new(tls_connection, stream);
+                    // The client certificate is known after the handshake
+                    let request = read_request(&mut tls_stream);
+                    let admin_certificate = tls::has_admin_certificate(&tls_stream.conn);
+                    self.serve(request, &mut tls_stream, &peer_addr, listener.role, admin_certificate);
+                    tls_stream.conn.send_close_notify();
+                    if tls_stream.flush().is_err() {
+                        log::debug!("I couldn't close the TLS connection of {}", peer_addr);
+                    }
+                },
+                Err(e) => log::error!("I couldn't start a TLS connection with {}: {}", peer_addr, e)
+            }
+        } else {
+            log::error!("I couldn't get to peer address of a client");
+        }
+    }
+
+    // An admin certificate proves the admin requests of the built-in account like the key does.
+    // The reply is written into the stream, whatever it is
+    pub fn serve<S: Write>(&mut self, request: io::Result<String>, stream: &mut S, peer_addr: &net::SocketAddr, role: config::ListenerRole, admin_certificate: bool) {
+        let reply;
+        let mut request_type: String = "UnparsedRequest".to_string();
+        if let Ok(request) = request {
+            let peer_ip = ipparser::unmap_ipv4(&peer_addr.ip());
+            let expired_bans = self.bans.expire();
+            if expired_bans > 0 {
+                log::info!("{} ban(s) expired", expired_bans);
+            }
+            self.expire_old_secrets();
+            // The loopback can't be banned, so an admin can always lift the bans
+            let ban = if peer_ip.is_loopback() { None } else { self.bans.banned_ip(&peer_ip) };
+            if let Some(ban) = ban {
+                log::info!("{} is banned by the ban {}, its request was refused", peer_addr, ban.id);
+                reply = replies::ReplyErrCodes::Banned { until: ban.until }.to_string();
+            } else if let Some(envelope) = requests::Envelope::from(&request) {
+                request_type = envelope.to_string();
+                match self.rate_limiter.check(&peer_ip, envelope.category(), time::Instant::now()) {
+                    Ok(()) => reply = self.reply_envelope(envelope, peer_addr, role),
+                    Err(retry_after) => {
+                        log::warn!("{} from {} was rate limited, it can retry in {} second(s)", request_type, peer_addr, retry_after);
+                        reply = replies::ReplyErrCodes::RateLimited { retry_after }.to_string();
                     }
                 }
+            } else if let Some(request) = requests::Request::from(&request) {
+                request_type = request.to_string();
+                let proven = admin_certificate && request.admin_account() == Some(accounts::BUILTIN_ADMIN);
+                if proven {
+                    log::debug!("{} is proven by the admin certificate of {}", request_type, peer_addr);
+                }
+                match self.rate_limiter.check(&peer_ip, request.category(), time::Instant::now()) {
+                    Ok(()) if !proven && !self.auth_mode.allows_plaintext() => {
+                        log::info!("{} sent its {} with the secret in plaintext, but the auth-mode is {}", peer_addr, request_type, self.auth_mode);
+                        reply = replies::ReplyErrCodes::AuthMethodNotAllowed.to_string();
+                    },
+                    Ok(()) => reply = self.reply(request, peer_addr, role, proven),
+                    Err(retry_after) => {
+                        log::warn!("{} from {} was rate limited, it can retry in {} second(s)", request_type, peer_addr, retry_after);
+                        reply = replies::ReplyErrCodes::RateLimited { retry_after }.to_string();
+                    }
+                }
+            } else if let Err(retry_after) = self.rate_limiter.check(&peer_ip, ratelimit::RequestCategory::Lookup, time::Instant::now()) {
+                // The garbage costs the same as a lookup
+                log::warn!("The unparsable requests of {} were rate limited, it can retry in {} second(s)", peer_addr, retry_after);
+                reply = replies::ReplyErrCodes::RateLimited { retry_after }.to_string();
+            } else {
+                log::info!("I couldn't parse the request of {}", peer_addr);
+                reply = replies::ReplyErrCodes::UnparsableRequest.to_string();
             }
         } else {
-            log::error!("I couldn't bind to {} :/", self.address);
-            process::exit(1);
+            log::error!("I couldn't read the request of {}", peer_addr);
+            reply = replies::ReplyErrCodes::ServerInternalError.to_string();
+        }
+
+        if let Ok(()) = stream.write_all(reply.as_bytes()) {
+            log::debug!("{} from {} Ok!", request_type, peer_addr);
+        } else {
+            log::error!("{} Err! I couldn't sent the reply to {}", request_type, peer_addr);
+        }
+    }
+
+    // With a drop-quorum it depends on how many clients are signed up right now
+    pub fn drop_votes_needed(&self) -> u16 {
+        match &self.drop_quorum {
+            Some(drop_quorum) => drop_quorum.drop_votes(self.clients.len()),
+            None => u16::from(self.drop_votes)
         }
     }
 
+    // The built-in account is the key
+    fn admin_secret(&self, account: &str) -> Option<&secrets::Secret> {
+        if account == accounts::BUILTIN_ADMIN {
+            return Some(&self.key);
+        }
+        self.accounts.get(account).map(|a| a.secret())
+    }
+
+    fn admin_role(&self, account: &str) -> Option<accounts::AdminRole> {
+        if account == accounts::BUILTIN_ADMIN {
+            return Some(accounts::AdminRole::Superuser);
+        }
+        self.accounts.get(account).map(|a| a.role)
+    }
+
+    // A proven request was already signed with the secret of the account, see reply_envelope. The
+    // token of an admin session stands in for the secret and acts as the account that logged in
+    fn authenticate_admin(&mut self, account: &str, password: &str, peer_addr: &net::SocketAddr, proven: bool) -> Option<accounts::ActingAdmin> {
+        let mut name = String::from(account);
+        let mut session = None;
+        let succeeded = if proven {
+            true
+        } else if let Some((id, session_account)) = self.sessions.validate(password, &ipparser::unmap_ipv4(&peer_addr.ip()), clients::unix_now()) {
+            name = session_account;
+            session = Some(id);
+            true
+        } else {
+            match account {
+                accounts::BUILTIN_ADMIN => self.verify_secret(password, auth::SecretKind::AdminKey, peer_addr),
+                account => self.accounts.get(account).map(|a| a.secret().verify(password)).unwrap_or(false)
+            }
+        };
+        // The account could have been removed after the request was signed
+        let role = self.admin_role(&name).filter(|_| succeeded);
+        if self.record_auth(role.is_some(), auth::SecretKind::AdminKey, peer_addr) {
+            return Some(accounts::ActingAdmin { name, role: role?, peer_addr: *peer_addr, session });
+        }
+        None
+    }
+
+    // The owner token of an invited client is checked first, it costs a SHA-256 instead of a PBKDF2
+    fn is_client_password(&mut self, password: &str, peer_addr: &net::SocketAddr, proven: bool) -> bool {
+        let is_password = proven || self.is_invited_client(password, peer_addr) || self.verify_secret(password, auth::SecretKind::ClientPassword, peer_addr);
+        self.record_auth(is_password, auth::SecretKind::ClientPassword, peer_addr)
+    }
+
+    // The clients admitted by an invite send the owner token of their entry as their password,
+    // until the invite is revoked. The code of the invite only signs up
+    fn is_invited_client(&self, token: &str, peer_addr: &net::SocketAddr) -> bool {
+        if let Some(mac) = self.clients.get_mac_by_ip(&ipparser::unmap_ipv4(&peer_addr.ip())) {
+            if let Some(client) = self.clients.get_by_mac(&mac) {
+                if let (Some(invite), Some(owner)) = (client.invite, &client.owner) {
+                    return self.invites.get(invite).is_some() && owner.is_owned_by(token);
+                }
+            }
+        }
+        false
+    }
+
+    // The client of the MAC, in the directory or waiting for an admin
+    fn signup_record(&self, mac: &ipparser::MacAddress) -> Option<clients::Client> {
+        self.clients.get_by_mac(mac).or_else(|| self.signups.get_by_mac(mac).map(|s| s.client.clone()))
+    }
+
+    // Some(None) for the password, Some(invite) for a code that admits the MAC. A MAC that the
+    // invite already admitted can sign up again even if the invite has no uses left. The codes are
+    // checked first, they cost a SHA-256 instead of a PBKDF2
+    fn sign_up_credential(&mut self, password: &str, mac: &ipparser::MacAddress, peer_addr: &net::SocketAddr, proven: bool) -> Option<Option<u64>> {
+        let mut credential = None;
+        if proven {
+            credential = Some(None);
+        } else if let Some(invite) = self.invites.get_by_code(password) {
+            let admitted = self.signup_record(mac).map(|c| c.invite == Some(invite.id)).unwrap_or(false);
+            if admitted || self.invites.admits(password, clients::unix_now()).is_some() {
+                credential = Some(Some(invite.id));
+            } else {
+                log::info!("{} tried to sign up with the invite {}, but it expired or has no uses left", peer_addr, invite.id);
+            }
+        } else if self.verify_secret(password, auth::SecretKind::ClientPassword, peer_addr) {
+            credential = Some(None);
+        }
+        if self.record_auth(credential.is_some(), auth::SecretKind::ClientPassword, peer_addr) {
+            return credential;
+        }
+        None
+    }
+
+    fn record_auth(&mut self, succeeded: bool, kind: auth::SecretKind, peer_addr: &net::SocketAddr) -> bool {
+        let ip = ipparser::unmap_ipv4(&peer_addr.ip());
+        if succeeded {
+            self.auth_guard.record_success(&ip, kind);
+        } else if let Some(lockout) = self.auth_guard.record_failure(&ip, kind, time::Instant::now()) {
+            log::warn!("{} failed the {} too many times, it's locked out for {} second(s)", peer_addr, kind, lockout);
+        }
+        succeeded
+    }
+
+    fn reply_envelope(&mut self, envelope: requests::Envelope, peer_addr: &net::SocketAddr, role: config::ListenerRole) -> String {
+        if !self.auth_mode.allows_challenges() {
+            log::info!("{} sent a {}, but the auth-mode is {}", peer_addr, envelope, self.auth_mode);
+            return replies::ReplyErrCodes::AuthMethodNotAllowed.to_string();
+        }
+        let peer_ip = ipparser::unmap_ipv4(&peer_addr.ip());
+        let now = time::Instant::now();
+        match envelope {
+            requests::Envelope::Challenge { kind, account } => {
+                let secret = match kind {
+                    auth::SecretKind::AdminKey => self.admin_secret(&account),
+                    auth::SecretKind::ClientPassword => Some(&self.password)
+                };
+                // The unknown accounts get a made up salt, their requests never verify
+                let salt = match secret {
+                    Some(secret) => Some((secret.salt_to_string(), secret.iterations())),
+                    None => self.challenges.unknown_account_salt(&account).map(|salt| (salt, secrets::DEFAULT_ITERATIONS))
+                };
+                // Only the builtin admin and the clients have an old secret while it rotates
+                self.expire_old_secrets();
+                let old_secret = match kind {
+                    auth::SecretKind::AdminKey if account == accounts::BUILTIN_ADMIN => self.old_key.as_ref(),
+                    auth::SecretKind::AdminKey => None,
+                    auth::SecretKind::ClientPassword => self.old_password.as_ref()
+                };
+                let old_salt = old_secret.map(|old| (old.secret.salt_to_string(), old.secret.iterations()));
+                if let (Some((salt, iterations)), Some(nonce)) = (salt, self.challenges.issue(&peer_ip, kind, now)) {
+                    return replies::reply_challenge(&nonce, &salt, iterations, old_salt, kind, peer_addr);
+                }
+                log::error!("I couldn't generate a nonce for {}", peer_addr);
+                replies::ReplyErrCodes::ServerInternalError.to_string()
+            },
+            requests::Envelope::Signed { nonce, proof, body, request } => {
+                let kind = request.secret_kind();
+                if let Some(retry_after) = self.auth_guard.locked_out(&peer_ip, kind, now) {
+                    log::info!("{} is locked out for {} more second(s), its signed {} was ignored", peer_addr, retry_after, request);
+                    return self.refuse_signed(&request, peer_addr, replies::ReplyErrCodes::LockedOut { retry_after }.to_string());
+                }
+                if !self.challenges.take(&nonce, &peer_ip, kind, now) {
+                    log::info!("{} signed its {} with a nonce that it wasn't given or that expired", peer_addr, request);
+                    return self.refuse_signed(&request, peer_addr, replies::ReplyErrCodes::InvalidNonce.to_string());
+                }
+                let message = requests::Envelope::signed_message(&nonce, &body);
+                let signed = match request.admin_account() {
+                    Some(accounts::BUILTIN_ADMIN) | None => self.verify_signed_secret(&message, &proof, kind, peer_addr),
+                    Some(account) => self.accounts.get(account).map(|a| a.secret().verify_hmac(&message, &proof)).unwrap_or(false)
+                };
+                if self.record_auth(signed, kind, peer_addr) {
+                    return self.reply(request, peer_addr, role, true);
+                }
+                log::info!("{} signed its {} with the wrong {}", peer_addr, request, kind);
+                self.refuse_signed(&request, peer_addr, replies::ReplyErrCodes::WrongPassword.to_string())
+            }
+        }
+    }
+
+    // Only the refused admin requests are audited
+    fn refuse_signed(&mut self, request: &requests::Request, peer_addr: &net::SocketAddr, reply: String) -> String {
+        if let requests::Request::Admin(account, a_request) = request {
+            self.audit_refused(account, peer_addr, &format!("Signed {}", a_request), &reply);
+        }
+        reply
+    }
+
+    // The admin is already authenticated and its role allows the request
+    fn reply_admin(&mut self, request: requests::AdminRequest, admin: &accounts::ActingAdmin) -> String {
+        match request {
+            requests::AdminRequest::Drop { ip, .. } => {
+                let reply = replies::reply_admin_drop(&ip, &mut self.clients, &mut self.signups, admin);
+                self.persist("approved sign ups");
+                reply
+            },
+            requests::AdminRequest::GetByIndex { start_index, end_index, .. } => replies::reply_admin_getbyindex(start_index, end_index, &self.clients, admin),
+            requests::AdminRequest::GetByMac { mac, .. } => replies::reply_admin_getbymac(&mac, &self.clients, admin),
+            requests::AdminRequest::GetByUsername { username, start_index, .. } => replies::reply_admin_getbyusername(&username, &self.clients, self.list_size, start_index, admin),
+            requests::AdminRequest::GetRunningConfiguration { .. } => replies::reply_admin_getrunningconfiguration(self, admin),
+            requests::AdminRequest::SetCapacity { capacity, .. } => replies::reply_admin_setcapacity(capacity, &mut self.capacity, self.clients.len(), admin),
+            requests::AdminRequest::SetDropVerification { drop_verification, .. } => replies::reply_admin_setdropverification(drop_verification, &mut self.drop_verification, admin),
+            requests::AdminRequest::SetDropVotes { drop_votes, .. } => replies::reply_admin_setdropvotes(drop_votes, &mut self.drop_votes, &mut self.drop_quorum, &mut self.clients, admin),
+            requests::AdminRequest::ResetVotes { ip, .. } => replies::reply_admin_resetvotes(&ip, &mut self.clients, admin),
+            requests::AdminRequest::GrantImmunity { ip, seconds, .. } => {
+                let immunity = match seconds {
+                    Some(seconds) => clients::Immunity::Until(clients::unix_now().saturating_add(seconds)),
+                    None => clients::Immunity::Permanent
+                };
+                replies::reply_admin_setimmunity(&ip, immunity, &mut self.clients, admin)
+            },
+            requests::AdminRequest::RevokeImmunity { ip, .. } => replies::reply_admin_setimmunity(&ip, clients::Immunity::NotImmune, &mut self.clients, admin),
+            requests::AdminRequest::SetReportQueue { report_queue, .. } => replies::reply_admin_setreportqueue(report_queue, &mut self.report_queue, admin),
+            requests::AdminRequest::GetBans { .. } => replies::reply_admin_getbans(&self.bans, admin),
+            requests::AdminRequest::Ban { target, seconds, reason, .. } => {
+                let until = seconds.map(|seconds| clients::unix_now().saturating_add(seconds));
+                let reply = replies::reply_admin_ban(target, until, &reason, &mut self.bans, &mut self.clients, &mut self.signups, admin);
+                self.persist("bans");
+                reply
+            },
+            requests::AdminRequest::LiftBan { id, .. } => {
+                let reply = replies::reply_admin_liftban(id, &mut self.bans, admin);
+                self.persist("bans");
+                reply
+            },
+            requests::AdminRequest::GetInvites { .. } => replies::reply_admin_getinvites(&self.invites, admin),
+            requests::AdminRequest::MintInvite { uses, seconds, .. } => {
+                let expires_at = seconds.map(|seconds| clients::unix_now().saturating_add(seconds));
+                replies::reply_admin_mintinvite(uses, expires_at, &mut self.invites, admin)
+            },
+            requests::AdminRequest::RevokeInvite { id, .. } => replies::reply_admin_revokeinvite(id, &mut self.invites, admin),
+            requests::AdminRequest::GetSignups { .. } => replies::reply_admin_getsignups(&self.signups, admin),
+            requests::AdminRequest::ApproveSignup { id, .. } => {
+                let reply = replies::reply_admin_approvesignup(id, &mut self.signups, &mut self.clients, &self.bans, self.capacity, self.lease, admin);
+                self.persist("approved sign ups");
+                reply
+            },
+            requests::AdminRequest::RejectSignup { id, .. } => replies::reply_admin_rejectsignup(id, &mut self.signups, admin),
+            requests::AdminRequest::Login { .. } => {
+                // Logging in with the token of a session would make it last forever
+                if admin.session.is_some() {
+                    log::info!("The admin {} tried to log in with the token of a session", admin);
+                    return replies::ReplyErrCodes::WrongPassword.to_string();
+                }
+                replies::reply_admin_login(&mut self.sessions, admin)
+            },
+            requests::AdminRequest::Logout { .. } => replies::reply_admin_logout(&mut self.sessions, admin),
+            requests::AdminRequest::GetSessions { .. } => replies::reply_admin_getsessions(&mut self.sessions, admin),
+            requests::AdminRequest::RevokeSession { id, .. } => replies::reply_admin_revokesession(id, &mut self.sessions, admin),
+            requests::AdminRequest::GetAccounts { .. } => replies::reply_admin_getaccounts(&self.accounts, admin),
+            requests::AdminRequest::AddAccount { name, role, secret, .. } => {
+                let reply = replies::reply_admin_addaccount(&name, role, &secret, &mut self.accounts, admin);
+                self.persist("accounts");
+                reply
+            },
+            requests::AdminRequest::RemoveAccount { name, .. } => {
+                let reply = replies::reply_admin_removeaccount(&name, &mut self.accounts, &mut self.sessions, admin);
+                self.persist("accounts");
+                reply
+            },
+            requests::AdminRequest::SetAccountRole { name, role, .. } => {
+                let reply = replies::reply_admin_setaccountrole(&name, role, &mut self.accounts, admin);
+                self.persist("accounts");
+                reply
+            },
+            requests::AdminRequest::GetLockouts { .. } => replies::reply_admin_getlockouts(&self.auth_guard, admin),
+            requests::AdminRequest::GetRateLimits { .. } => replies::reply_admin_getratelimits(&self.rate_limiter, admin),
+            requests::AdminRequest::GetReports { .. } => replies::reply_admin_getreports(&self.reports, admin),
+            requests::AdminRequest::GetReportHistory { mac, .. } => replies::reply_admin_getreporthistory(&mac, &self.reports, admin),
+            requests::AdminRequest::ApproveReport { id, .. } => {
+                let reply = replies::reply_admin_approvereport(id, &mut self.reports, &mut self.clients, &mut self.signups, admin);
+                self.persist("approved sign ups");
+                reply
+            },
+            requests::AdminRequest::DismissReport { id, .. } => replies::reply_admin_dismissreport(id, &mut self.reports, admin),
+            requests::AdminRequest::SetDropQuorum { drop_quorum, min_votes, .. } => replies::reply_admin_setdropquorum(drop_quorum, min_votes, &mut self.drop_quorum, &mut self.clients, admin),
+            requests::AdminRequest::SetKey { key, grace, .. } => replies::reply_admin_setkey(&key, grace.unwrap_or(self.rotation_grace), &mut self.key, &mut self.old_key, &mut self.sessions, admin),
+            requests::AdminRequest::SetListSize { list_size, .. } => replies::reply_admin_setlistsize(list_size, &mut self.list_size, admin),
+            requests::AdminRequest::SetPassword { new_password, grace, .. } => replies::reply_admin_setpassword(&new_password, grace.unwrap_or(self.rotation_grace), &mut self.password, &mut self.old_password, admin)
+        }
+    }
+
+    fn reply(&mut self, request: requests::Request, peer_addr: &net::SocketAddr, role: config::ListenerRole, proven: bool) -> String {
+        // Every reply must see only the votes that still count
+        let expired_votes = self.clients.expire_drop_votes(self.vote_expiry);
+        if expired_votes > 0 {
+            log::info!("{} drop vote(s) expired", expired_votes);
+        }
+        // An IP that guessed the secret too many times is ignored until its lockout ends
+        if let Some(retry_after) = self.auth_guard.locked_out(&ipparser::unmap_ipv4(&peer_addr.ip()), request.secret_kind(), time::Instant::now()) {
+            log::info!("{} is locked out for {} more second(s), its {} was ignored", peer_addr, retry_after, request);
+            let reply = replies::ReplyErrCodes::LockedOut { retry_after }.to_string();
+            if let requests::Request::Admin(account, a_request) = &request {
+                self.audit_refused(account, peer_addr, &a_request.to_string(), &reply);
+            }
+            return reply;
+        }
+
+        let reply;
+        match request {
+            requests::Request::Admin(account, a_request) => {
+                if role.serves_admins() && self.is_remote_admin_allowed(&peer_addr.ip()) {
+                    let action = a_request.to_string();
+                    match self.authenticate_admin(&account, a_request.password(), peer_addr, proven) {
+                        Some(admin) if admin.role.permits(a_request.required_role()) => {
+                            reply = self.reply_admin(a_request, &admin);
+                            self.audit(&admin, &action, &reply);
+                        },
+                        Some(admin) => {
+                            log::warn!("The admin {} has the {} role, it can't make an {}", admin, admin.role, a_request);
+                            reply = replies::ReplyErrCodes::InsufficientRole.to_string();
+                            self.audit(&admin, &action, &reply);
+                        },
+                        None => {
+                            log::info!("The admin {} ({}) forgot the password", account, peer_addr);
+                            reply = replies::ReplyErrCodes::WrongPassword.to_string();
+                            self.audit_refused(&account, peer_addr, &action, &reply);
+                        }
+                    }
+                } else {
+                    if role.serves_admins() {
+                        log::warn!("{} tried to administrate the server, but it's not in the --remote-admin networks", peer_addr);
+                    } else {
+                        log::warn!("{} tried to administrate the server through a client-only address", peer_addr);
+                    }
+                    reply = replies::ReplyErrCodes::RemoteAdminIsNotAllowed.to_string();
+                    self.audit_refused(&account, peer_addr, &a_request.to_string(), &reply);
+                }
+            },
+            requests::Request::Client(c_request) => {
+                if !role.serves_clients() {
+                    log::warn!("{} sent a client request to an admin-only address", peer_addr);
+                    reply = replies::ReplyErrCodes::ClientRequestNotAllowed.to_string();
+                } else if !self.is_client_allowed(&peer_addr.ip()) {
+                    log::warn!("{} is outside the client networks, its {} was refused", peer_addr, c_request);
+                    reply = replies::ReplyErrCodes::OutsideClientSubnet.to_string();
+                } else if self.force_signup && c_request.requires_signup() && !self.clients.exists_by_ip(&ipparser::unmap_ipv4(&peer_addr.ip())) {
+                    log::info!("{} is not signed up, but it sent a {}", peer_addr, c_request);
+                    reply = replies::ReplyErrCodes::ClientIsNotSignedUp.to_string();
+                } else {
+                    match c_request {
+                        requests::ClientRequest::GetByMac { password: client_password, mac } => {
+                            if self.is_client_password(&client_password, peer_addr, proven) {
+                                reply = replies::reply_client_getbymac(&mac, &self.clients, peer_addr);
+                            } else { 
+                                log::info!("The client {} doesn't know the password", peer_addr);
+                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
+                            }
+                        },
+                        requests::ClientRequest::GetByUsername { password: client_password, username, start_index } => {
+                            if self.is_client_password(&client_password, peer_addr, proven) {
+                                reply = replies::reply_client_getbyusername(&username, &self.clients, self.list_size, start_index, peer_addr);
+                            } else { 
+                                log::info!("The client {} doesn't know the password", peer_addr);
+                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
+                            }
+                        },
+                        requests::ClientRequest::Drop { password: client_password, ip, reason, text } => {
+                            if self.is_client_password(&client_password, peer_addr, proven) {
+                                if self.report_queue {
+                                    reply = replies::reply_client_report(&ip, &self.clients, &mut self.reports, reason, &text, peer_addr);
+                                } else {
+                                    let drop_votes_needed = self.drop_votes_needed();
+                                    reply = replies::reply_client_drop(&ip, &mut self.clients, drop_votes_needed, peer_addr);
+                                }
+                            } else { 
+                                log::info!("The client {} doesn't know the password", peer_addr);
+                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
+                            }
+                            log::debug!("Client's DB:\n{}", self.clients);
+                        },
+                        requests::ClientRequest::SignUp { password: client_password, username, mac, port, get_only_by_mac, owner_token } => {
+                            if let Some(invite) = self.sign_up_credential(&client_password, &mac, peer_addr, proven) {
+                                if let Some(ban) = self.bans.banned_client(&mac, &ipparser::unmap_ipv4(&peer_addr.ip())) {
+                                    log::info!("{} {} is banned by the ban {}, it can't sign up", mac, peer_addr, ban.id);
+                                    reply = replies::ReplyErrCodes::Banned { until: ban.until }.to_string();
+                                } else {
+                                    let before = self.signup_record(&mac);
+                                    let signup_queue = if self.signup_approval { Some(&mut self.signups) } else { None };
+                                    reply = replies::reply_client_signup(&mut self.clients, signup_queue, &username, &mac, peer_addr, port, get_only_by_mac, self.capacity, owner_token.as_deref(), self.lease);
+                                    // Every accepted sign up issues or renews the ownership, only a MAC that
+                                    // wasn't admitted by the invite uses it up
+                                    if let Some(invite) = invite {
+                                        let signed_up = self.signup_record(&mac).map(|c| c.owner) != before.as_ref().map(|c| c.owner.clone());
+                                        let admitted = before.map(|c| c.invite == Some(invite)).unwrap_or(false);
+                                        if signed_up && !admitted {
+                                            if !self.clients.set_invite(&mac, invite) {
+                                                self.signups.set_invite(&mac, invite);
+                                            }
+                                            self.invites.redeem(invite);
+                                            log::info!("{} {} was admitted by the invite {}", mac, peer_addr, invite);
+                                        }
+                                    }
+                                }
+                            } else { 
+                                log::info!("The client {} doesn't know the password", peer_addr);
+                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
+                            }
+                            log::debug!("Client's DB:\n{}", self.clients);
+                        },
+                        requests::ClientRequest::SignUpStatus { password: client_password, mac } => {
+                            if self.sign_up_credential(&client_password, &mac, peer_addr, proven).is_some() {
+                                reply = replies::reply_client_signupstatus(&mac, &self.clients, &self.signups, peer_addr);
+                            } else { 
+                                log::info!("The client {} doesn't know the password", peer_addr);
+                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
+                            }
+                        },
+                        requests::ClientRequest::SignOut { password: client_password, mac, owner_token } => {
+                            if self.is_client_password(&client_password, peer_addr, proven) {
+                                reply = replies::reply_client_signout(&mut self.clients, mac.as_ref(), owner_token.as_deref(), peer_addr);
+                            } else { 
+                                log::info!("The client {} doesn't know the password", peer_addr);
+                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
+                            }
+                            log::debug!("Client's DB:\n{}", self.clients);
+                        },
+                        requests::ClientRequest::Update { password: client_password, mac, owner_token, username, port, get_only_by_mac } => {
+                            if self.is_client_password(&client_password, peer_addr, proven) {
+                                reply = replies::reply_client_update(&mut self.clients, mac.as_ref(), owner_token.as_deref(), username.as_deref(), port, get_only_by_mac, peer_addr);
+                            } else { 
+                                log::info!("The client {} doesn't know the password", peer_addr);
+                                reply = replies::ReplyErrCodes::WrongPassword.to_string();
+                            }
+                            log::debug!("Client's DB:\n{}", self.clients);
+                        }
+                    }
+                }
+            }
+        }
+        reply
+    }
 }
 
 pub fn is_valid_key(key: &str) -> bool {
-    key.is_ascii() && key.len() < 33
+    secrets::is_valid_secret(key)
+}
+
+// The handshake has a deadline of its own, so a client that sends it slowly can't hold the
+// accept loop for the whole I/O timeout on each read
+fn tls_handshake(connection: &mut rustls::ServerConnection, stream: &mut net::TcpStream, deadline: time::Instant) -> io::Result<()> {
+    while connection.is_handshaking() {
+        let remaining = deadline.saturating_duration_since(time::Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "the handshake took too long"));
+        }
+        stream.set_read_timeout(Some(remaining))?;
+        stream.set_write_timeout(Some(remaining))?;
+        if connection.wants_write() {
+            connection.write_tls(stream)?;
+        } else if connection.read_tls(stream)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the client closed the connection"));
+        } else if let Err(e) = connection.process_new_packets() {
+            // The alert tells the client why
+            let _ = connection.write_tls(stream);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    }
+    Ok(())
+}
+
+fn read_request<S: Read>(stream: &mut S) -> io::Result<String> {
+    let mut buffer = [0; 1024];
+    let bytes_read = stream.read(&mut buffer)?;
+    Ok(String::from_utf8_lossy(&buffer[..bytes_read]).into_owned())
 }
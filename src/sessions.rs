@@ -0,0 +1,117 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module keeps the admin sessions, an admin logs in with the key once and then sends a
+// short-lived token instead of the key until the session expires or is revoked.
+
+extern crate ring;
+
+use std::net;
+use ring::digest;
+use ring::rand;
+use ring::rand::SecureRandom;
+use crate::secrets;
+
+const TOKEN_LEN: usize = 32;
+// When there are more sessions than this, the oldest ones are logged out
+const MAX_SESSIONS: usize = 256;
+
+pub struct Session {
+    pub id: u64,
+    pub ip_addr: net::IpAddr,
+    // The admin account that logged in
+    pub account: String,
+    // Seconds since the UNIX epoch
+    pub created_at: u64,
+    pub expires_at: u64,
+    // Only the SHA-256 of the token is kept, the admin is the only one that knows the token
+    token_digest: Vec<u8>
+}
+
+impl Session {
+    pub fn to_json_string(&self) -> String {
+        format!("{{\"id\":{},\"ip_addr\":\"{}\",\"account\":\"{}\",\"created_at\":{},\"expires_at\":{}}}", self.id, self.ip_addr, self.account, self.created_at, self.expires_at)
+    }
+}
+
+pub struct SessionStore {
+    ttl: u64,
+    next_id: u64,
+    sessions: Vec<Session>,
+    rng: rand::SystemRandom
+}
+
+impl SessionStore {
+    pub fn new(ttl: u64) -> SessionStore {
+        SessionStore { ttl, next_id: 1, sessions: Vec::new(), rng: rand::SystemRandom::new() }
+    }
+
+    pub fn ttl(&self) -> u64 {
+        self.ttl
+    }
+
+    // Returns the id of the session and its token
+    pub fn login(&mut self, ip: &net::IpAddr, account: &str, now: u64) -> Option<(u64, String)> {
+        let mut token = [0u8; TOKEN_LEN];
+        self.rng.fill(&mut token).ok()?;
+        let token = secrets::to_hex(&token);
+        self.expire(now);
+        if self.sessions.len() >= MAX_SESSIONS {
+            self.sessions.remove(0);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.sessions.push(Session { id, ip_addr: *ip, account: String::from(account), created_at: now, expires_at: now.saturating_add(self.ttl), token_digest: token_digest(&token) });
+        Some((id, token))
+    }
+
+    // A token only works from the IP that logged in, returns the id of the session and its account
+    pub fn validate(&mut self, token: &str, ip: &net::IpAddr, now: u64) -> Option<(u64, String)> {
+        self.expire(now);
+        let digest = token_digest(token);
+        self.sessions.iter().find(|s| s.token_digest == digest && s.ip_addr == *ip).map(|s| (s.id, s.account.clone()))
+    }
+
+    pub fn revoke(&mut self, id: u64) -> bool {
+        let sessions_before = self.sessions.len();
+        self.sessions.retain(|s| s.id != id);
+        sessions_before != self.sessions.len()
+    }
+
+    // Returns how many sessions of the account were logged out
+    pub fn revoke_account(&mut self, account: &str) -> usize {
+        let sessions_before = self.sessions.len();
+        self.sessions.retain(|s| s.account != account);
+        sessions_before - self.sessions.len()
+    }
+
+    // Returns how many sessions were logged out
+    pub fn revoke_all(&mut self) -> usize {
+        let sessions = self.sessions.len();
+        self.sessions.clear();
+        sessions
+    }
+
+    pub fn expire(&mut self, now: u64) -> usize {
+        let sessions_before = self.sessions.len();
+        self.sessions.retain(|s| now < s.expires_at);
+        sessions_before - self.sessions.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    pub fn to_json_string(&self) -> String {
+        format!("[{}]", self.sessions.iter().map(|s| s.to_json_string()).collect::<Vec<String>>().join(","))
+    }
+}
+
+// The tokens are looked up by their digest, so the time it takes doesn't tell how much of a
+// token was guessed
+fn token_digest(token: &str) -> Vec<u8> {
+    digest::digest(&digest::SHA256, token.as_bytes()).as_ref().to_vec()
+}
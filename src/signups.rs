@@ -0,0 +1,188 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module keeps the sign ups that wait for an admin when the signup-approval is enabled, the
+// waiting clients are not in the directory, so nobody can look them up until they are approved.
+
+extern crate serde_json;
+
+use std::fmt;
+use crate::clients;
+use crate::ipparser;
+
+// The rejected sign ups are forgotten first when the queue is full
+pub const MAX_SIGNUPS: usize = 1024;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SignupStatus {
+    Pending,
+    Rejected
+}
+
+impl SignupStatus {
+    pub fn new_from_str(status: &str) -> Option<SignupStatus> {
+        match status {
+            "pending" => Some(SignupStatus::Pending),
+            "rejected" => Some(SignupStatus::Rejected),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for SignupStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignupStatus::Pending => write!(f, "pending"),
+            SignupStatus::Rejected => write!(f, "rejected")
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Signup {
+    pub id: u64,
+    pub mac: ipparser::MacAddress,
+    // The client as it will be signed up
+    pub client: clients::Client,
+    pub requested_at: u64,
+    pub status: SignupStatus
+}
+
+impl Signup {
+    pub fn to_json_string(&self) -> String {
+        format!("{{\"id\":{},\"requested_at\":{},\"status\":\"{}\",\"client\":{}}}", self.id, self.requested_at, self.status, self.client.to_json_string_with_mac(&self.mac))
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<Signup> {
+        let id = val.get("id")?.as_u64()?;
+        let requested_at = val.get("requested_at")?.as_u64()?;
+        let status = SignupStatus::new_from_str(val.get("status")?.as_str()?)?;
+        let client = val.get("client")?;
+        let mac = ipparser::MacAddress::new_from_str(client.get("mac")?.as_str()?)?;
+        let client = clients::Client::from_json_value(client)?;
+        Some(Signup { id, mac, client, requested_at, status })
+    }
+}
+
+// The approved sign ups leave the queue for the directory, the rejected ones stay so their
+// clients can see it, until they sign up again. The approved MACs are remembered, they don't
+// wait again after they sign out or are dropped out by the votes, only an admin revokes them
+pub struct SignupQueue {
+    next_id: u64,
+    signups: Vec<Signup>,
+    approved: Vec<ipparser::MacAddress>
+}
+
+impl SignupQueue {
+    pub fn new() -> SignupQueue {
+        SignupQueue { next_id: 1, signups: Vec::new(), approved: Vec::new() }
+    }
+
+    // Signing up again replaces the previous sign up of the MAC, None if the queue is full
+    pub fn request(&mut self, mac: &ipparser::MacAddress, client: &clients::Client, now: u64) -> Option<u64> {
+        self.signups.retain(|s| s.mac != *mac);
+        if self.signups.len() >= MAX_SIGNUPS {
+            let index = self.signups.iter().position(|s| s.status == SignupStatus::Rejected)?;
+            self.signups.remove(index);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.signups.push(Signup { id, mac: mac.clone(), client: client.clone(), requested_at: now, status: SignupStatus::Pending });
+        Some(id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Signup> {
+        self.signups.iter().find(|s| s.id == id)
+    }
+
+    pub fn get_by_mac(&self, mac: &ipparser::MacAddress) -> Option<&Signup> {
+        self.signups.iter().find(|s| s.mac == *mac)
+    }
+
+    pub fn set_invite(&mut self, mac: &ipparser::MacAddress, invite: u64) -> bool {
+        if let Some(signup) = self.signups.iter_mut().find(|s| s.mac == *mac) {
+            signup.client.invite = Some(invite);
+            return true;
+        }
+        false
+    }
+
+    pub fn pending(&self) -> Vec<&Signup> {
+        self.signups.iter().filter(|s| s.status == SignupStatus::Pending).collect()
+    }
+
+    // Takes the sign up out of the queue, only the pending ones can be approved
+    pub fn approve(&mut self, id: u64) -> Option<Signup> {
+        let index = self.signups.iter().position(|s| s.id == id && s.status == SignupStatus::Pending)?;
+        let signup = self.signups.remove(index);
+        if !self.is_approved(&signup.mac) {
+            self.approved.push(signup.mac.clone());
+        }
+        Some(signup)
+    }
+
+    pub fn is_approved(&self, mac: &ipparser::MacAddress) -> bool {
+        self.approved.contains(mac)
+    }
+
+    // The MACs dropped out or banned by an admin lose their approval and wait again
+    pub fn revoke(&mut self, mac: &ipparser::MacAddress) -> bool {
+        let approved_before = self.approved.len();
+        self.approved.retain(|m| m != mac);
+        self.approved.len() < approved_before
+    }
+
+    pub fn reject(&mut self, id: u64) -> Option<&Signup> {
+        let signup = self.signups.iter_mut().find(|s| s.id == id && s.status == SignupStatus::Pending)?;
+        signup.status = SignupStatus::Rejected;
+        Some(signup)
+    }
+
+    pub fn len(&self) -> usize {
+        self.signups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signups.is_empty()
+    }
+
+    pub fn to_json_string(&self) -> String {
+        format!("[{}]", self.signups.iter().map(|s| s.to_json_string()).collect::<Vec<String>>().join(","))
+    }
+
+    pub fn approved_to_json_string(&self) -> String {
+        format!("[{}]", self.approved.iter().map(|m| format!("\"{}\"", m)).collect::<Vec<String>>().join(","))
+    }
+
+    // The approved MACs are saved apart from the queue
+    pub fn restore_approved(&mut self, val: &serde_json::Value) -> Option<()> {
+        for mac in val.as_array()? {
+            let mac = ipparser::MacAddress::new_from_str(mac.as_str()?)?;
+            if !self.is_approved(&mac) {
+                self.approved.push(mac);
+            }
+        }
+        Some(())
+    }
+
+    pub fn from_json_value(val: &serde_json::Value) -> Option<SignupQueue> {
+        let mut signup_queue = SignupQueue::new();
+        for signup in val.as_array()? {
+            let signup = Signup::from_json_value(signup)?;
+            if signup.id >= signup_queue.next_id {
+                signup_queue.next_id = signup.id + 1;
+            }
+            signup_queue.signups.push(signup);
+        }
+        Some(signup_queue)
+    }
+}
+
+impl Default for SignupQueue {
+    fn default() -> Self {
+        SignupQueue::new()
+    }
+}
+
+pub fn signups_to_json_array(signups: &[&Signup]) -> String {
+    format!("[{}]", signups.iter().map(|s| s.to_json_string()).collect::<Vec<String>>().join(","))
+}
@@ -0,0 +1,72 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module saves the clients directory to disk and restores it when the server starts.
+
+extern crate serde_json;
+
+use std::fs;
+use std::io;
+use crate::clients;
+use crate::reports;
+use crate::bans;
+use crate::invites;
+use crate::signups;
+use crate::accounts;
+
+pub struct Snapshot {
+    pub clients: clients::ClientsMap,
+    pub reports: reports::ReportsQueue,
+    pub bans: bans::BanList,
+    pub invites: invites::InviteList,
+    pub signups: signups::SignupQueue,
+    // The accounts added by the admins, with their hashed secrets
+    pub accounts: accounts::AccountStore
+}
+
+pub fn write_snapshot(path: &str, clients_map: &clients::ClientsMap, reports_queue: &reports::ReportsQueue, ban_list: &bans::BanList, invite_list: &invites::InviteList, signup_queue: &signups::SignupQueue, account_store: &accounts::AccountStore) -> io::Result<()> {
+    let snapshot = format!("{{\"clients\":{},\"reports\":{},\"bans\":{},\"invites\":{},\"signups\":{},\"approved_signups\":{},\"accounts\":{}}}", clients_map.to_json_string(), reports_queue.to_json_string(), ban_list.to_json_string(), invite_list.to_json_string(), signup_queue.to_json_string(), signup_queue.approved_to_json_string(), account_store.to_snapshot_json_string());
+    // Write to a temporary file first, so a crash never leaves a half written snapshot
+    let tmp_path = format!("{}.tmp", path);
+    fs::write(&tmp_path, snapshot)?;
+    fs::rename(&tmp_path, path)
+}
+
+pub fn read_snapshot(path: &str) -> io::Result<Snapshot> {
+    let snapshot = fs::read_to_string(path)?;
+    if let Ok(snapshot) = serde_json::from_str::<serde_json::Value>(&snapshot) {
+        if let Some(clients) = snapshot.get("clients") {
+            if let Some(clients) = clients::ClientsMap::from_json_value(clients) {
+                // Older snapshots don't have reports, bans, invites, sign ups, approved sign ups nor accounts
+                let reports = match snapshot.get("reports") {
+                    Some(reports) => reports::ReportsQueue::from_json_value(reports),
+                    None => Some(reports::ReportsQueue::new())
+                };
+                let bans = match snapshot.get("bans") {
+                    Some(bans) => bans::BanList::from_json_value(bans),
+                    None => Some(bans::BanList::new())
+                };
+                let invites = match snapshot.get("invites") {
+                    Some(invites) => invites::InviteList::from_json_value(invites),
+                    None => Some(invites::InviteList::new())
+                };
+                let mut signups = match snapshot.get("signups") {
+                    Some(signups) => signups::SignupQueue::from_json_value(signups),
+                    None => Some(signups::SignupQueue::new())
+                };
+                if let (Some(signup_queue), Some(approved)) = (signups.as_mut(), snapshot.get("approved_signups")) {
+                    if signup_queue.restore_approved(approved).is_none() {
+                        signups = None;
+                    }
+                }
+                let accounts = match snapshot.get("accounts") {
+                    Some(accounts) => accounts::AccountStore::from_json_value(accounts),
+                    None => Some(accounts::AccountStore::new())
+                };
+                if let (Some(reports), Some(bans), Some(invites), Some(signups), Some(accounts)) = (reports, bans, invites, signups, accounts) {
+                    return Ok(Snapshot { clients, reports, bans, invites, signups, accounts });
+                }
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a valid snapshot", path)))
+}
@@ -0,0 +1,83 @@
+use crate::accounts;
+use crate::requests;
+use crate::secrets;
+
+#[test]
+fn parse_accounts_and_roles() {
+    let account = accounts::Account::new_from_str("alice:operator:alice_secret").unwrap();
+    assert_eq!(account.name, "alice");
+    assert_eq!(account.role, accounts::AdminRole::Operator);
+    assert!(account.secret().verify("alice_secret"));
+    // The secret can have colons
+    assert!(accounts::Account::new_from_str("bob:read-only:a:b").unwrap().secret().verify("a:b"));
+    assert!(accounts::Account::new_from_str("admin:superuser:secret").is_none());
+    assert!(accounts::Account::new_from_str("alice:root:secret").is_none());
+    assert!(accounts::Account::new_from_str("al ice:operator:secret").is_none());
+    assert!(accounts::Account::new_from_str("alice:operator").is_none());
+    // The secret is never listed
+    assert_eq!(account.to_json_string(), "{\"name\":\"alice\",\"role\":\"operator\"}");
+
+    assert!(accounts::AdminRole::Superuser.permits(accounts::AdminRole::Operator));
+    assert!(accounts::AdminRole::Operator.permits(accounts::AdminRole::Operator));
+    assert!(!accounts::AdminRole::ReadOnly.permits(accounts::AdminRole::Operator));
+}
+
+#[test]
+fn account_store() {
+    let mut store = accounts::AccountStore::new();
+    assert!(store.add(accounts::Account::new_from_str("alice:operator:secret").unwrap()));
+    assert!(!store.add(accounts::Account::new_from_str("alice:superuser:secret").unwrap()));
+    assert!(store.set_role("alice", accounts::AdminRole::ReadOnly));
+    assert!(!store.set_role("bob", accounts::AdminRole::ReadOnly));
+    assert_eq!(store.get("alice").unwrap().role, accounts::AdminRole::ReadOnly);
+    assert_eq!(store.to_json_string(), "[{\"name\":\"alice\",\"role\":\"read-only\"}]");
+    assert!(store.remove("alice").is_some());
+    assert!(store.remove("alice").is_none());
+    assert!(store.is_empty());
+}
+
+#[test]
+fn accounts_survive_the_snapshots() {
+    let mut store = accounts::AccountStore::new();
+    store.add(accounts::Account::new_from_str("alice:operator:alice_secret").unwrap());
+    let snapshot = store.to_snapshot_json_string();
+    // Only the hash of the secret is saved
+    assert!(!snapshot.contains("alice_secret"));
+    match accounts::AccountStore::from_json_value(&serde_json::from_str(&snapshot).unwrap()) {
+        Some(mut restored) => {
+            assert_eq!(restored.get("alice").unwrap().role, accounts::AdminRole::Operator);
+            assert!(restored.get("alice").unwrap().secret().verify("alice_secret"));
+            // The accounts of the config replace the restored ones
+            assert!(restored.replace(accounts::Account::new_from_str("alice:read-only:new_secret").unwrap()));
+            assert_eq!(restored.len(), 1);
+            assert!(restored.get("alice").unwrap().secret().verify("new_secret"));
+            assert!(!restored.replace(accounts::Account::new(accounts::BUILTIN_ADMIN, accounts::AdminRole::Superuser, secrets::Secret::hash("secret").unwrap())));
+        },
+        None => assert!(false)
+    }
+    assert!(accounts::AccountStore::from_json_value(&serde_json::from_str("[{\"name\":\"admin\",\"role\":\"superuser\",\"secret\":\"x\"}]").unwrap()).is_none());
+}
+
+#[test]
+fn admin_requests_name_their_account() {
+    match requests::Request::from("{\"user\":\"admin\",\"account\":\"alice\",\"password\":\"secret\",\"method\":\"drop\",\"ip\":\"10.0.0.1\"}") {
+        Some(requests::Request::Admin(account, request)) => {
+            assert_eq!(account, "alice");
+            assert_eq!(request.required_role(), accounts::AdminRole::Operator);
+        },
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"secret\",\"method\":\"get\",\"how\":\"running_configuration\"}") {
+        Some(requests::Request::Admin(account, request)) => {
+            assert_eq!(account, accounts::BUILTIN_ADMIN);
+            assert_eq!(request.required_role(), accounts::AdminRole::ReadOnly);
+        },
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"secret\",\"method\":\"account\",\"action\":\"add\",\"name\":\"bob\",\"role\":\"read-only\",\"secret\":\"bob_secret\"}") {
+        Some(requests::Request::Admin(_, request)) => assert_eq!(request.required_role(), accounts::AdminRole::Superuser),
+        _ => assert!(false)
+    }
+    assert!(requests::Request::from("{\"user\":\"admin\",\"account\":\"\",\"password\":\"secret\",\"method\":\"get\",\"how\":\"bans\"}").is_none());
+    assert!(requests::Request::from("{\"user\":\"admin\",\"password\":\"secret\",\"method\":\"account\",\"action\":\"set_role\",\"name\":\"bob\",\"role\":\"root\"}").is_none());
+}
@@ -0,0 +1,47 @@
+use std::env;
+use std::fs;
+use std::process;
+use ring::hmac;
+use crate::accounts;
+use crate::audit;
+
+#[test]
+fn audit_log_chain() {
+    let path = env::temp_dir().join(format!("cinnamon_audit_{}.log", process::id()));
+    let path = path.to_str().unwrap();
+    let _ = fs::remove_file(path);
+    let admin = accounts::ActingAdmin { name: String::from("alice"), role: accounts::AdminRole::Operator, peer_addr: "127.0.0.1:5000".parse().unwrap(), session: Some(3) };
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, b"the audit key of the tests");
+    let mut audit_log = audit::AuditLog::open(path, key.clone()).unwrap();
+    audit_log.record(1000, &admin, "AdminRequest::Drop 10.0.0.1", "{\"result\":\"Client was dropped out\",\"client\":{\"mac\":\"00:11:22:33:44:55\"}}").unwrap();
+    audit_log.record(1001, &admin, "AdminRequest::Login", "{\"result\":\"logged in\",\"session\":4,\"token\":\"abcdef\",\"expires_in\":900}").unwrap();
+    // The chain goes on after the log is opened again
+    let mut audit_log = audit::AuditLog::open(path, key.clone()).unwrap();
+    assert_eq!(audit_log.entries(), 2);
+    audit_log.record(1002, &admin, "AdminRequest::GetBans", "{\"bans\":[]}").unwrap();
+    // The requests refused before the admin proved who it is are recorded too
+    audit_log.record_refused(1003, "mallory", &"10.0.0.9:6000".parse().unwrap(), "AdminRequest::GetBans", "{\"error\":5,\"name\":\"WrongPassword\"}").unwrap();
+    assert_eq!(audit::verify(path, &key), Ok(4));
+    // Without the key the chain can't be checked nor made again
+    assert!(audit::verify(path, &hmac::Key::new(hmac::HMAC_SHA256, b"another audit key")).is_err());
+
+    let contents = fs::read_to_string(path).unwrap();
+    // The session tokens are never written
+    assert!(!contents.contains("abcdef"));
+    assert!(contents.contains("\"admin\":\"alice\",\"role\":\"operator\",\"session\":3,\"peer_addr\":\"127.0.0.1:5000\""));
+    assert!(contents.contains("\"admin\":\"mallory\",\"role\":null,\"session\":null,\"peer_addr\":\"10.0.0.9:6000\""));
+
+    let lines: Vec<&str> = contents.lines().collect();
+    let changed = contents.replace("10.0.0.1", "10.0.0.2");
+    assert!(audit::verify_str(&changed, &key).is_err());
+    let removed = format!("{}\n{}\n", lines[0], lines[2]);
+    assert!(audit::verify_str(&removed, &key).is_err());
+    let reordered = format!("{}\n{}\n{}\n", lines[1], lines[0], lines[2]);
+    assert!(audit::verify_str(&reordered, &key).is_err());
+
+    // A tampered log is never extended
+    fs::write(path, changed).unwrap();
+    assert!(audit::AuditLog::open(path, key).is_err());
+    let _ = fs::remove_file(path);
+}
@@ -0,0 +1,62 @@
+use std::net;
+use std::time;
+use crate::auth;
+
+#[test]
+fn constant_time_eq() {
+    assert!(auth::constant_time_eq("my-secret-key", "my-secret-key"));
+    assert!(!auth::constant_time_eq("my-secret-key", "my-secret-kez"));
+    assert!(!auth::constant_time_eq("my-secret-key", "my-secret"));
+    assert!(!auth::constant_time_eq("", "a"));
+    assert!(auth::constant_time_eq("", ""));
+}
+
+#[test]
+fn auth_guard_exponential_lockout() {
+    let mut auth_guard = auth::AuthGuard::new(3, time::Duration::from_secs(10));
+    let ip: net::IpAddr = "192.168.1.50".parse().unwrap();
+    let now = time::Instant::now();
+
+    assert_eq!(auth_guard.record_failure(&ip, auth::SecretKind::AdminKey, now), None);
+    assert_eq!(auth_guard.record_failure(&ip, auth::SecretKind::AdminKey, now), None);
+    assert_eq!(auth_guard.record_failure(&ip, auth::SecretKind::AdminKey, now), Some(10));
+    assert_eq!(auth_guard.locked_out(&ip, auth::SecretKind::AdminKey, now), Some(10));
+    // The client password is guessed separately
+    assert_eq!(auth_guard.locked_out(&ip, auth::SecretKind::ClientPassword, now), None);
+
+    let later = now + time::Duration::from_secs(11);
+    assert_eq!(auth_guard.locked_out(&ip, auth::SecretKind::AdminKey, later), None);
+    assert_eq!(auth_guard.record_failure(&ip, auth::SecretKind::AdminKey, later), Some(20));
+
+    auth_guard.record_success(&ip, auth::SecretKind::AdminKey);
+    assert_eq!(auth_guard.locked_out(&ip, auth::SecretKind::AdminKey, later), None);
+}
+
+#[test]
+fn challenge_nonces_are_single_use() {
+    let mut challenges = auth::ChallengeStore::new();
+    let ip: net::IpAddr = "192.168.1.50".parse().unwrap();
+    let other_ip: net::IpAddr = "192.168.1.51".parse().unwrap();
+    let now = time::Instant::now();
+
+    let nonce = challenges.issue(&ip, auth::SecretKind::ClientPassword, now).unwrap();
+    assert_eq!(nonce.len(), 32);
+    assert!(challenges.take(&nonce, &ip, auth::SecretKind::ClientPassword, now));
+    assert!(!challenges.take(&nonce, &ip, auth::SecretKind::ClientPassword, now));
+
+    // Bound to the IP and the secret, and used up by a wrong answer
+    let nonce = challenges.issue(&ip, auth::SecretKind::ClientPassword, now).unwrap();
+    assert!(!challenges.take(&nonce, &other_ip, auth::SecretKind::ClientPassword, now));
+    assert!(!challenges.take(&nonce, &ip, auth::SecretKind::ClientPassword, now));
+    let nonce = challenges.issue(&ip, auth::SecretKind::ClientPassword, now).unwrap();
+    assert!(!challenges.take(&nonce, &ip, auth::SecretKind::AdminKey, now));
+
+    let nonce = challenges.issue(&ip, auth::SecretKind::AdminKey, now).unwrap();
+    assert!(!challenges.take(&nonce, &ip, auth::SecretKind::AdminKey, now + auth::NONCE_TTL));
+
+    // The accounts that don't exist always get the same made up salt
+    let salt = challenges.unknown_account_salt("nobody").unwrap();
+    assert_eq!(salt.len(), crate::secrets::SALT_LEN * 2);
+    assert_eq!(challenges.unknown_account_salt("nobody").unwrap(), salt);
+    assert_ne!(challenges.unknown_account_salt("somebody").unwrap(), salt);
+}
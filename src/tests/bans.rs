@@ -0,0 +1,27 @@
+use std::net;
+use crate::bans;
+use crate::ipparser;
+
+#[test]
+fn ban_list_ban_and_lift() {
+    let mut ban_list = bans::BanList::new();
+    let mac = ipparser::MacAddress::new_from_str("aaaa.bbbb.cccc").unwrap();
+    let inside: net::IpAddr = "10.0.3.7".parse().unwrap();
+    let outside: net::IpAddr = "10.1.0.1".parse().unwrap();
+
+    let network_ban = ban_list.ban(bans::BanTarget::Network(ipparser::Cidr::new_from_str("10.0.0.0/16").unwrap()), None, "lab");
+    ban_list.ban(bans::BanTarget::Mac(mac.clone()), None, "");
+    // An expired ban doesn't ban anyone
+    ban_list.ban(bans::BanTarget::Network(ipparser::Cidr::new_from_str("10.1.0.1").unwrap()), Some(1), "");
+
+    assert!(ban_list.banned_ip(&inside).is_some());
+    assert!(ban_list.banned_ip(&outside).is_none());
+    assert!(ban_list.banned_client(&mac, &outside).is_some());
+    assert_eq!(ban_list.expire(), 1);
+
+    let json = serde_json::from_str::<serde_json::Value>(&ban_list.to_json_string()).unwrap();
+    let mut restored = bans::BanList::from_json_value(&json).unwrap();
+    assert_eq!(restored.len(), 2);
+    assert_eq!(restored.lift(network_ban).unwrap().reason, "lab");
+    assert!(restored.banned_ip(&inside).is_none());
+}
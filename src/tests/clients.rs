@@ -7,8 +7,8 @@ fn client_new() {
     let port: u16 = 8000;
     let username = "jorge_alarcon";
     let get_only_by_mac = false;
-    let drop_votes: u8 = 12;
-    if let Some(_client) = clients::Client::new(ipv4_addr, port, username, get_only_by_mac, drop_votes) {
+    let drop_votes: u16 = 12;
+    if let Some(_client) = clients::Client::new(ipparser::u32_to_ipv4(ipv4_addr).into(), port, username, get_only_by_mac, drop_votes) {
         assert!(true);
     } else {
         assert!(false);
@@ -17,15 +17,15 @@ fn client_new() {
 
 #[test]
 fn clients_map_insert() {
-    let mut jorge = clients::Client::new(3232235826, 8000, "jorge_alarcon", false, 1).unwrap();
+    let mut jorge = clients::Client::new(ipparser::u32_to_ipv4(3232235826).into(), 8000, "jorge_alarcon", false, 1).unwrap();
     let mac_jorge = ipparser::MacAddress::new_from_str("aaaa.bbbb.cccc").unwrap();
-    let gil = clients::Client::new(2352233826, 9000, "gil_vazquez", true, 1).unwrap();
+    let gil = clients::Client::new(ipparser::u32_to_ipv4(2352233826).into(), 9000, "gil_vazquez", true, 1).unwrap();
     let mac_gil = ipparser::MacAddress::new_from_str("eeee.1234.fabc").unwrap();
-    let tania = clients::Client::new(3232236000, 7000, "tania_m", false, 0).unwrap();
+    let tania = clients::Client::new(ipparser::u32_to_ipv4(3232236000).into(), 7000, "tania_m", false, 0).unwrap();
     let mac_tania = ipparser::MacAddress::new_from_str("89ab.9999.ffff").unwrap();
-    let fredy = clients::Client::new(3232235788, 9000, "fredy_dela_cruz", false, 0).unwrap();
+    let fredy = clients::Client::new(ipparser::u32_to_ipv4(3232235788).into(), 9000, "fredy_dela_cruz", false, 0).unwrap();
     let mac_fredy = ipparser::MacAddress::new_from_str("100a.7890.ae45").unwrap();
-    let coro = clients::Client::new(3232235799, 9000, "alexis_coro", true, 0).unwrap();
+    let coro = clients::Client::new(ipparser::u32_to_ipv4(3232235799).into(), 9000, "alexis_coro", true, 0).unwrap();
     let mac_coro = ipparser::MacAddress::new_from_str("abcf.2312.9898").unwrap();
     let mut clients_map = clients::ClientsMap::new();
 
@@ -67,7 +67,7 @@ fn clients_map_insert() {
     }
 
     // Same mac and different ip should result in an update
-    jorge.ipv4_addr = 3232235825;
+    jorge.ip_addr = ipparser::u32_to_ipv4(3232235825).into();
     match clients_map.insert(&mac_jorge, &jorge) {
         clients::InsertionType::Insert => assert!(false),
         clients::InsertionType::Replace { client_mac_replaced: _ } => assert!(false),
@@ -85,15 +85,15 @@ fn clients_map_insert() {
 
 #[test]
 fn clients_map_len() {
-    let jorge = clients::Client::new(3232235826, 8000, "jorge_alarcon", false, 1).unwrap();
+    let jorge = clients::Client::new(ipparser::u32_to_ipv4(3232235826).into(), 8000, "jorge_alarcon", false, 1).unwrap();
     let mac_jorge = ipparser::MacAddress::new_from_str("aaaa.bbbb.cccc").unwrap();
-    let gil = clients::Client::new(2352233826, 9000, "gil_vazquez", true, 1).unwrap();
+    let gil = clients::Client::new(ipparser::u32_to_ipv4(2352233826).into(), 9000, "gil_vazquez", true, 1).unwrap();
     let mac_gil = ipparser::MacAddress::new_from_str("eeee.1234.fabc").unwrap();
-    let tania = clients::Client::new(3232236000, 7000, "tania_m", false, 0).unwrap();
+    let tania = clients::Client::new(ipparser::u32_to_ipv4(3232236000).into(), 7000, "tania_m", false, 0).unwrap();
     let mac_tania = ipparser::MacAddress::new_from_str("89ab.9999.ffff").unwrap();
-    let fredy = clients::Client::new(3232235788, 9000, "fredy_dela_cruz", false, 0).unwrap();
+    let fredy = clients::Client::new(ipparser::u32_to_ipv4(3232235788).into(), 9000, "fredy_dela_cruz", false, 0).unwrap();
     let mac_fredy = ipparser::MacAddress::new_from_str("100a.7890.ae45").unwrap();
-    let coro = clients::Client::new(3232235799, 9000, "alexis_coro", true, 0).unwrap();
+    let coro = clients::Client::new(ipparser::u32_to_ipv4(3232235799).into(), 9000, "alexis_coro", true, 0).unwrap();
     let mac_coro = ipparser::MacAddress::new_from_str("abcf.2312.9898").unwrap();
     let mut clients_map = clients::ClientsMap::new();
     clients_map.insert(&mac_jorge, &jorge);
@@ -106,19 +106,19 @@ fn clients_map_len() {
 
 #[test]
 fn clients_map_usernames_that_contain() {
-    let jorge = clients::Client::new(3232235826, 8000, "jorge_alarcon", false, 1).unwrap();
+    let jorge = clients::Client::new(ipparser::u32_to_ipv4(3232235826).into(), 8000, "jorge_alarcon", false, 1).unwrap();
     let mac_jorge = ipparser::MacAddress::new_from_str("0000.0000.0000").unwrap();
-    let gil = clients::Client::new(2352233826, 9000, "gil_vazquez", true, 1).unwrap();
+    let gil = clients::Client::new(ipparser::u32_to_ipv4(2352233826).into(), 9000, "gil_vazquez", true, 1).unwrap();
     let mac_gil = ipparser::MacAddress::new_from_str("0000.0000.0001").unwrap();
-    let tania = clients::Client::new(3232236000, 7000, "tania_vazquez", false, 0).unwrap();
+    let tania = clients::Client::new(ipparser::u32_to_ipv4(3232236000).into(), 7000, "tania_vazquez", false, 0).unwrap();
     let mac_tania = ipparser::MacAddress::new_from_str("0000.0000.0002").unwrap();
-    let fredy = clients::Client::new(3232235788, 9000, "fredy_dela_cruz", false, 0).unwrap();
+    let fredy = clients::Client::new(ipparser::u32_to_ipv4(3232235788).into(), 9000, "fredy_dela_cruz", false, 0).unwrap();
     let mac_fredy = ipparser::MacAddress::new_from_str("0000.0000.0003").unwrap();
-    let coro = clients::Client::new(3232235799, 9000, "alexis_coro", true, 0).unwrap();
+    let coro = clients::Client::new(ipparser::u32_to_ipv4(3232235799).into(), 9000, "alexis_coro", true, 0).unwrap();
     let mac_coro = ipparser::MacAddress::new_from_str("0000.0000.0004").unwrap();
-    let sabino = clients::Client::new(3212224788, 9000, "sabino_vazquez", true, 0).unwrap();
+    let sabino = clients::Client::new(ipparser::u32_to_ipv4(3212224788).into(), 9000, "sabino_vazquez", true, 0).unwrap();
     let mac_sabino = ipparser::MacAddress::new_from_str("0000.0000.0005").unwrap();
-    let martin = clients::Client::new(3221123981, 9000, "martin_vazquez", true, 0).unwrap();
+    let martin = clients::Client::new(ipparser::u32_to_ipv4(3221123981).into(), 9000, "martin_vazquez", true, 0).unwrap();
     let mac_martin = ipparser::MacAddress::new_from_str("0000.0000.0006").unwrap();
 
     let mut clients_map = clients::ClientsMap::new();
@@ -161,3 +161,354 @@ fn clients_map_usernames_that_contain() {
     assert_eq!(martin, *iter.next().unwrap());
     assert_eq!(end_index, 6);
 }
+
+#[test]
+fn clients_map_json_round_trip() {
+    let jorge = clients::Client::new(ipparser::u32_to_ipv4(3232235826).into(), 8000, "jorge_alarcon", false, 1).unwrap();
+    let mac_jorge = ipparser::MacAddress::new_from_str("aaaa.bbbb.cccc").unwrap();
+    let gil = clients::Client::new(ipparser::u32_to_ipv4(2352233826).into(), 9000, "gil_vazquez", true, 0).unwrap();
+    let mac_gil = ipparser::MacAddress::new_from_str("eeee.1234.fabc").unwrap();
+    let mut clients_map = clients::ClientsMap::new();
+    clients_map.insert(&mac_jorge, &jorge);
+    clients_map.insert(&mac_gil, &gil);
+
+    let json = serde_json::from_str::<serde_json::Value>(&clients_map.to_json_string()).unwrap();
+    let restored = clients::ClientsMap::from_json_value(&json).unwrap();
+    assert_eq!(restored.len(), 2);
+    let restored_jorge = restored.get_by_mac(&mac_jorge).unwrap();
+    assert_eq!(restored_jorge, jorge);
+    assert_eq!(restored_jorge.port, 8000);
+    assert_eq!(restored_jorge.drop_votes, 1);
+    assert!(restored.get_by_mac(&mac_gil).unwrap().get_only_by_mac);
+}
+
+#[test]
+fn clients_map_ipv6_clients() {
+    let (ip_addr, scope_id) = ipparser::parse_scoped_ip("fe80::1c2a:3bff:fe4d:5e6f%3").unwrap();
+    let mut link_local = clients::Client::new(ip_addr, 8000, "link_local", false, 0).unwrap();
+    link_local.scope_id = scope_id;
+    let mac_link_local = ipparser::MacAddress::new_from_str("aaaa.bbbb.cccc").unwrap();
+    let global = clients::Client::new("2001:db8::10".parse().unwrap(), 9000, "global", false, 0).unwrap();
+    let mac_global = ipparser::MacAddress::new_from_str("eeee.1234.fabc").unwrap();
+    let mut clients_map = clients::ClientsMap::new();
+    clients_map.insert(&mac_link_local, &link_local);
+    clients_map.insert(&mac_global, &global);
+
+    assert!(clients_map.exists_by_ip(&ip_addr));
+    assert_eq!(link_local.socket_addr().to_string(), "[fe80::1c2a:3bff:fe4d:5e6f%3]:8000");
+
+    let json = serde_json::from_str::<serde_json::Value>(&clients_map.to_json_string()).unwrap();
+    let restored = clients::ClientsMap::from_json_value(&json).unwrap();
+    assert_eq!(restored.get_by_mac(&mac_link_local).unwrap().scope_id, 3);
+
+    assert!(clients_map.drop_by_ip(&"2001:db8::10".parse().unwrap()));
+    assert_eq!(clients_map.len(), 1);
+}
+
+#[test]
+fn clients_map_one_drop_vote_per_voter() {
+    let jorge = clients::Client::new(ipparser::u32_to_ipv4(3232235826).into(), 8000, "jorge_alarcon", false, 0).unwrap();
+    let mac_jorge = ipparser::MacAddress::new_from_str("aaaa.bbbb.cccc").unwrap();
+    let gil = clients::Client::new(ipparser::u32_to_ipv4(3232235827).into(), 9000, "gil_vazquez", false, 0).unwrap();
+    let mac_gil = ipparser::MacAddress::new_from_str("eeee.1234.fabc").unwrap();
+    let mut clients_map = clients::ClientsMap::new();
+    clients_map.insert(&mac_jorge, &jorge);
+    clients_map.insert(&mac_gil, &gil);
+
+    let stranger: std::net::IpAddr = ipparser::u32_to_ipv4(3232235900).into();
+    match clients_map.drop_vote_by_ip(&gil.get_ip_addr(), &jorge.get_ip_addr(), 3) {
+        clients::DropVoteResult::Voted => assert!(true),
+        _ => assert!(false)
+    }
+    match clients_map.drop_vote_by_ip(&gil.get_ip_addr(), &jorge.get_ip_addr(), 3) {
+        clients::DropVoteResult::AlreadyVoted => assert!(true),
+        _ => assert!(false)
+    }
+    match clients_map.drop_vote_by_ip(&gil.get_ip_addr(), &stranger, 3) {
+        clients::DropVoteResult::Voted => assert!(true),
+        _ => assert!(false)
+    }
+    assert_eq!(clients_map.get_by_mac(&mac_gil).unwrap().drop_votes, 2);
+    assert_eq!(clients_map.get_by_mac(&mac_gil).unwrap().drop_voters.len(), 2);
+
+    // Inserting the MAC again doesn't clear its votes
+    clients_map.insert(&mac_gil, &gil);
+    assert_eq!(clients_map.get_by_mac(&mac_gil).unwrap().drop_votes, 2);
+    assert_eq!(clients_map.get_by_mac(&mac_gil).unwrap().drop_voters.len(), 2);
+
+    // When the voter leaves its vote is forgotten
+    assert!(clients_map.drop_by_ip(&jorge.get_ip_addr()));
+    assert_eq!(clients_map.get_by_mac(&mac_gil).unwrap().drop_votes, 1);
+    assert_eq!(clients_map.get_by_mac(&mac_gil).unwrap().drop_voters.len(), 1);
+}
+
+#[test]
+fn client_expire_drop_votes() {
+    let mut gil = clients::Client::new(ipparser::u32_to_ipv4(3232235827).into(), 9000, "gil_vazquez", false, 0).unwrap();
+    let old_vote = clients::DropVote { voter_ip: ipparser::u32_to_ipv4(3232235826).into(), voter_mac: None, cast_at: 1000 };
+    let new_vote = clients::DropVote { voter_ip: ipparser::u32_to_ipv4(3232235900).into(), voter_mac: None, cast_at: 1900 };
+    gil.drop_voters.push(old_vote);
+    gil.drop_voters.push(new_vote);
+    gil.drop_votes = 2;
+
+    // 0 means that the votes never expire
+    assert_eq!(gil.expire_drop_votes(2000, 0), 0);
+    assert_eq!(gil.expire_drop_votes(2000, 1000), 1);
+    assert_eq!(gil.drop_votes, 1);
+    assert_eq!(gil.drop_voters[0].cast_at, 1900);
+}
+
+#[test]
+fn signup_again_keeps_the_drop_votes() {
+    let mut clients_map = clients::ClientsMap::new();
+    let mac = ipparser::MacAddress::new_from_str("eeee.1234.fabc").unwrap();
+    let addr: std::net::SocketAddr = "192.168.1.51:9000".parse().unwrap();
+    let voter: std::net::IpAddr = "192.168.1.50".parse().unwrap();
+    crate::replies::reply_client_signup(&mut clients_map, None, "gil_vazquez", &mac, &addr, 9000, false, 10, None, 0);
+    match clients_map.drop_vote_by_ip(&addr.ip(), &voter, 3) {
+        clients::DropVoteResult::Voted => assert!(true),
+        _ => assert!(false)
+    }
+
+    // Signing up again doesn't clear the vote, it still counts and can't be cast twice
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "gil", &mac, &addr, 9000, false, 10, None, 0);
+    assert!(reply.contains("Your data has been updated"));
+    assert_eq!(clients_map.get_by_mac(&mac).unwrap().drop_votes, 1);
+    match clients_map.drop_vote_by_ip(&addr.ip(), &voter, 3) {
+        clients::DropVoteResult::AlreadyVoted => assert!(true),
+        _ => assert!(false)
+    }
+
+    // Only the expiry ages it out
+    assert_eq!(clients_map.expire_drop_votes(0), 0);
+    let mut gil = clients_map.get_by_mac(&mac).unwrap().clone();
+    gil.drop_voters[0].cast_at = 1;
+    let mut clients_map = clients::ClientsMap::new();
+    clients_map.insert(&mac, &gil);
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "gil_vazquez", &mac, &addr, 9000, false, 10, None, 0);
+    assert!(reply.contains("Your data has been updated"));
+    assert_eq!(clients_map.get_by_mac(&mac).unwrap().drop_votes, 1);
+    assert_eq!(clients_map.expire_drop_votes(60), 1);
+    assert_eq!(clients_map.get_by_mac(&mac).unwrap().drop_votes, 0);
+}
+
+#[test]
+fn clients_map_immunity_and_reset_votes() {
+    let gil = clients::Client::new(ipparser::u32_to_ipv4(3232235827).into(), 9000, "gil_vazquez", false, 0).unwrap();
+    let mac_gil = ipparser::MacAddress::new_from_str("eeee.1234.fabc").unwrap();
+    let jorge: std::net::IpAddr = ipparser::u32_to_ipv4(3232235826).into();
+    let tania: std::net::IpAddr = ipparser::u32_to_ipv4(3232236000).into();
+    let mut clients_map = clients::ClientsMap::new();
+    clients_map.insert(&mac_gil, &gil);
+
+    assert!(clients_map.set_immunity_by_ip(&gil.get_ip_addr(), clients::Immunity::Permanent).is_some());
+    match clients_map.drop_vote_by_ip(&gil.get_ip_addr(), &jorge, 1) {
+        clients::DropVoteResult::ClientIsImmune => assert!(true),
+        _ => assert!(false)
+    }
+
+    // An expired immunity doesn't protect anymore
+    clients_map.set_immunity_by_ip(&gil.get_ip_addr(), clients::Immunity::Until(1));
+    match clients_map.drop_vote_by_ip(&gil.get_ip_addr(), &jorge, 3) {
+        clients::DropVoteResult::Voted => assert!(true),
+        _ => assert!(false)
+    }
+    clients_map.drop_vote_by_ip(&gil.get_ip_addr(), &tania, 3);
+    assert_eq!(clients_map.reset_all_drop_votes(), 1);
+    assert_eq!(clients_map.get_by_mac(&mac_gil).unwrap().drop_votes, 0);
+    assert!(clients_map.get_by_mac(&mac_gil).unwrap().drop_voters.is_empty());
+}
+
+#[test]
+fn signup_ownership_token() {
+    let mut clients_map = clients::ClientsMap::new();
+    let mac = ipparser::MacAddress::new_from_str("00:11:22:33:44:55").unwrap();
+    let owner_addr: std::net::SocketAddr = "192.168.1.10:4000".parse().unwrap();
+    let other_addr: std::net::SocketAddr = "192.168.1.20:4000".parse().unwrap();
+
+    let reply = serde_json::from_str::<serde_json::Value>(&crate::replies::reply_client_signup(&mut clients_map, None, "jorge", &mac, &owner_addr, 4000, false, 10, None, 60)).unwrap();
+    let token = String::from(reply["owner_token"].as_str().unwrap());
+    // Nobody else can take the MAC while the lease lasts
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "mallory", &mac, &other_addr, 4000, false, 10, None, 60);
+    assert!(reply.contains("WrongOwnerToken"));
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "mallory", &mac, &other_addr, 4000, false, 10, Some("guess"), 60);
+    assert!(reply.contains("WrongOwnerToken"));
+    assert_eq!(clients_map.get_by_mac(&mac).unwrap().username, "jorge");
+
+    // The owner keeps its token and renews the lease
+    let reply = serde_json::from_str::<serde_json::Value>(&crate::replies::reply_client_signup(&mut clients_map, None, "jorge_a", &mac, &owner_addr, 4001, false, 10, Some(&token), 60)).unwrap();
+    assert!(reply.get("owner_token").is_none());
+    assert!(reply["lease_until"].as_u64().is_some());
+    let client = clients_map.get_by_mac(&mac).unwrap();
+    assert_eq!(client.username, "jorge_a");
+    assert!(client.owner.as_ref().unwrap().is_owned_by(&token));
+
+    // The ownership survives the snapshots, after the lease the IP of the entry can sign up the
+    // MAC without the token, but no other IP can
+    let json = serde_json::from_str::<serde_json::Value>(&clients_map.to_json_string()).unwrap();
+    let mut restored = clients::ClientsMap::from_json_value(&json).unwrap();
+    assert_eq!(restored.get_by_mac(&mac).unwrap().owner, client.owner);
+    let mut expired = client.clone();
+    expired.owner = Some(clients::Ownership { token_digest: client.owner.unwrap().token_digest, lease_until: 1 });
+    restored.insert(&mac, &expired);
+    let reply = crate::replies::reply_client_signup(&mut restored, None, "mallory", &mac, &other_addr, 4000, false, 10, None, 60);
+    assert!(reply.contains("WrongOwnerToken"));
+    let reply = crate::replies::reply_client_signup(&mut restored, None, "jorge", &mac, &owner_addr, 4000, false, 10, None, 60);
+    assert!(reply.contains("owner_token"));
+    assert_eq!(restored.get_by_mac(&mac).unwrap().username, "jorge");
+}
+
+#[test]
+fn signup_without_lease_needs_the_token_from_another_ip() {
+    let mut clients_map = clients::ClientsMap::new();
+    let mac = ipparser::MacAddress::new_from_str("00:11:22:33:44:55").unwrap();
+    let owner_addr: std::net::SocketAddr = "192.168.1.10:4000".parse().unwrap();
+    let other_addr: std::net::SocketAddr = "192.168.1.20:4000".parse().unwrap();
+
+    crate::replies::reply_client_signup(&mut clients_map, None, "jorge", &mac, &owner_addr, 4000, false, 10, None, 0);
+    // A second IP can't take the MAC without its token
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "mallory", &mac, &other_addr, 4000, false, 10, None, 0);
+    assert!(reply.contains("WrongOwnerToken"));
+    assert_eq!(clients_map.get_by_mac(&mac).unwrap().get_ip_addr(), owner_addr.ip());
+    // The IP of the entry can sign it up again without the token, it gets a new one
+    let reply = serde_json::from_str::<serde_json::Value>(&crate::replies::reply_client_signup(&mut clients_map, None, "jorge_a", &mac, &owner_addr, 4000, false, 10, None, 0)).unwrap();
+    assert_eq!(reply["result"], "Your data has been updated");
+    let token = String::from(reply["owner_token"].as_str().unwrap());
+    // And the owner moves it to another IP with the token
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "jorge", &mac, &other_addr, 4000, false, 10, Some(&token), 0);
+    assert!(reply.contains("Your data has been updated"));
+    assert_eq!(clients_map.get_by_mac(&mac).unwrap().get_ip_addr(), other_addr.ip());
+}
+
+#[test]
+fn signup_cant_replace_a_leased_ip() {
+    let mut clients_map = clients::ClientsMap::new();
+    let mac = ipparser::MacAddress::new_from_str("00:11:22:33:44:55").unwrap();
+    let other_mac = ipparser::MacAddress::new_from_str("66:77:88:99:aa:bb").unwrap();
+    let addr: std::net::SocketAddr = "192.168.1.10:4000".parse().unwrap();
+
+    let reply = serde_json::from_str::<serde_json::Value>(&crate::replies::reply_client_signup(&mut clients_map, None, "jorge", &mac, &addr, 4000, false, 10, None, 60)).unwrap();
+    let token = String::from(reply["owner_token"].as_str().unwrap());
+    // Another MAC from the same IP would replace the leased record
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "mallory", &other_mac, &addr, 4000, false, 10, None, 60);
+    assert!(reply.contains("WrongOwnerToken"));
+    assert!(clients_map.exists_by_mac(&mac));
+    // The owner can move its record to another MAC
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "jorge", &other_mac, &addr, 4000, false, 10, Some(&token), 60);
+    assert!(reply.contains("You have been registered"));
+    assert!(!clients_map.exists_by_mac(&mac));
+
+    // Without a lease another MAC can replace the entry from its IP, like before the ownership tokens
+    let mut clients_map = clients::ClientsMap::new();
+    crate::replies::reply_client_signup(&mut clients_map, None, "jorge", &mac, &addr, 4000, false, 10, None, 0);
+    let reply = crate::replies::reply_client_signup(&mut clients_map, None, "gil", &other_mac, &addr, 4000, false, 10, None, 0);
+    assert!(reply.contains("You have been registered"));
+    assert!(!clients_map.exists_by_mac(&mac));
+}
+
+#[test]
+fn signout_and_update() {
+    let mut clients_map = clients::ClientsMap::new();
+    let mac = ipparser::MacAddress::new_from_str("00:11:22:33:44:55").unwrap();
+    let owner_addr: std::net::SocketAddr = "192.168.1.10:4000".parse().unwrap();
+    let moved_addr: std::net::SocketAddr = "192.168.1.30:4000".parse().unwrap();
+    let other_addr: std::net::SocketAddr = "192.168.1.20:4000".parse().unwrap();
+
+    let reply = serde_json::from_str::<serde_json::Value>(&crate::replies::reply_client_signup(&mut clients_map, None, "jorge", &mac, &owner_addr, 4000, false, 10, None, 60)).unwrap();
+    let token = String::from(reply["owner_token"].as_str().unwrap());
+
+    // Only the fields that were sent change, from the IP of the entry
+    let reply = crate::replies::reply_client_update(&mut clients_map, None, None, None, Some(4001), None, &owner_addr);
+    assert!(reply.contains("updated"));
+    let client = clients_map.get_by_mac(&mac).unwrap();
+    assert_eq!(client.port, 4001);
+    assert_eq!(client.username, "jorge");
+    assert!(client.owner.as_ref().unwrap().is_owned_by(&token));
+
+    // Another IP needs the owner token
+    let reply = crate::replies::reply_client_update(&mut clients_map, Some(&mac), None, Some("mallory"), None, None, &other_addr);
+    assert!(reply.contains("NotClientOwner"));
+    let reply = crate::replies::reply_client_signout(&mut clients_map, Some(&mac), Some("guess"), &other_addr);
+    assert!(reply.contains("NotClientOwner"));
+    let reply = crate::replies::reply_client_signout(&mut clients_map, None, None, &other_addr);
+    assert!(reply.contains("ClientDoesNotExist"));
+    let reply = crate::replies::reply_client_update(&mut clients_map, Some(&mac), Some(&token), Some("jorge_a"), None, Some(true), &moved_addr);
+    assert!(reply.contains("updated"));
+    let client = clients_map.get_by_mac(&mac).unwrap();
+    assert_eq!(client.username, "jorge_a");
+    assert!(client.get_only_by_mac);
+
+    let reply = crate::replies::reply_client_signout(&mut clients_map, Some(&mac), Some(&token), &moved_addr);
+    assert!(reply.contains("signed out"));
+    assert!(!clients_map.exists_by_mac(&mac));
+}
+
+#[test]
+fn parse_signout_and_update() {
+    match crate::requests::Request::from(r#"{"user":"client","password":"pw","method":"sign_out"}"#) {
+        Some(crate::requests::Request::Client(crate::requests::ClientRequest::SignOut { mac: None, owner_token: None, .. })) => assert!(true),
+        _ => assert!(false)
+    }
+    match crate::requests::Request::from(r#"{"user":"client","password":"pw","method":"update","mac":"00:11:22:33:44:55","owner_token":"t","port":4001}"#) {
+        Some(crate::requests::Request::Client(crate::requests::ClientRequest::Update { mac: Some(_), owner_token: Some(_), username: None, port: Some(4001), get_only_by_mac: None, .. })) => assert!(true),
+        _ => assert!(false)
+    }
+    // An update must change something
+    match crate::requests::Request::from(r#"{"user":"client","password":"pw","method":"update"}"#) {
+        None => assert!(true),
+        _ => assert!(false)
+    }
+    match crate::requests::Request::from(r#"{"user":"admin","password":"pw","method":"sign_out"}"#) {
+        None => assert!(true),
+        _ => assert!(false)
+    }
+}
+
+#[test]
+fn force_signup() {
+    let mut start_config = super::config::start_config();
+    start_config.force_signup = true;
+    let mut server = crate::server::Server::from_start_config(&start_config);
+    let role = crate::config::ListenerRole::Both;
+    let get = "{\"user\":\"client\",\"password\":\"clientpw\",\"method\":\"get\",\"how\":\"mac\",\"mac\":\"00:11:22:33:44:55\"}";
+    let signup = "{\"user\":\"client\",\"password\":\"clientpw\",\"method\":\"sign_up\",\"username\":\"jorge\",\"mac\":\"00:11:22:33:44:55\",\"port\":4000,\"get_only_by_mac\":false}";
+
+    // The clients that are not signed up can only sign up
+    assert!(super::config::send(&mut server, get, "192.168.1.10:4000", role).contains("ClientIsNotSignedUp"));
+    assert!(super::config::send(&mut server, signup, "192.168.1.10:4000", role).contains("You have been registered"));
+    let reply = super::config::send(&mut server, get, "192.168.1.10:4000", role);
+    assert!(reply.contains("jorge") && !reply.contains("error"));
+    assert!(super::config::send(&mut server, get, "192.168.1.20:4000", role).contains("ClientIsNotSignedUp"));
+
+    // Without it anyone that knows the password can look the clients up
+    let mut server = crate::server::Server::from_start_config(&super::config::start_config());
+    assert!(super::config::send(&mut server, signup, "192.168.1.10:4000", role).contains("You have been registered"));
+    let reply = super::config::send(&mut server, get, "192.168.1.20:4000", role);
+    assert!(reply.contains("jorge") && !reply.contains("error"));
+}
+
+#[test]
+fn signout_and_update_through_the_server() {
+    let mut server = crate::server::Server::from_start_config(&super::config::start_config());
+    let role = crate::config::ListenerRole::Client;
+    let signup = "{\"user\":\"client\",\"password\":\"clientpw\",\"method\":\"sign_up\",\"username\":\"jorge\",\"mac\":\"00:11:22:33:44:55\",\"port\":4000,\"get_only_by_mac\":false}";
+    let reply = serde_json::from_str::<serde_json::Value>(&super::config::send(&mut server, signup, "192.168.1.10:4000", role)).unwrap();
+    let token = String::from(reply["owner_token"].as_str().unwrap());
+    let update = |owner_token: &str| format!("{{\"user\":\"client\",\"password\":\"clientpw\",\"method\":\"update\",\"mac\":\"00:11:22:33:44:55\",{}\"username\":\"jorge_a\"}}", owner_token);
+    let signout = |owner_token: &str| format!("{{\"user\":\"client\",\"password\":\"clientpw\",\"method\":\"sign_out\",\"mac\":\"00:11:22:33:44:55\"{}}}", owner_token);
+
+    // Another IP is refused without the owner token, and accepted with it
+    assert!(super::config::send(&mut server, &update(""), "192.168.1.20:4000", role).contains("NotClientOwner"));
+    assert!(super::config::send(&mut server, &update("\"owner_token\":\"guess\","), "192.168.1.20:4000", role).contains("NotClientOwner"));
+    assert!(super::config::send(&mut server, &update(&format!("\"owner_token\":\"{}\",", token)), "192.168.1.20:4000", role).contains("updated"));
+    assert_eq!(server.clients.get_by_mac(&ipparser::MacAddress::new_from_str("00:11:22:33:44:55").unwrap()).unwrap().username, "jorge_a");
+    assert!(super::config::send(&mut server, &signout(""), "192.168.1.20:4000", role).contains("NotClientOwner"));
+    assert!(super::config::send(&mut server, &signout(&format!(",\"owner_token\":\"{}\"", token)), "192.168.1.20:4000", role).contains("signed out"));
+    assert_eq!(server.clients.len(), 0);
+
+    // The IP of the entry doesn't need the token
+    super::config::send(&mut server, signup, "192.168.1.10:4000", role);
+    assert!(super::config::send(&mut server, &update(""), "192.168.1.10:4000", role).contains("updated"));
+    assert!(super::config::send(&mut server, &signout(""), "192.168.1.10:4000", role).contains("signed out"));
+    assert_eq!(server.clients.len(), 0);
+}
@@ -0,0 +1,95 @@
+use crate::config;
+use crate::secrets;
+use crate::server;
+
+// The server that the tests send the requests to, the key is "adminkey" and the password "clientpw"
+pub fn start_config() -> config::StartConfig {
+    config::StartConfig {
+        listeners: Vec::new(), drop_votes: 3, password: secrets::Secret::new_from_str("clientpw").unwrap(), key: secrets::Secret::new_from_str("adminkey").unwrap(),
+        capacity: 10, list_size: 10, drop_verification: false, log_level: log::LevelFilter::Off, snapshot: None, audit_log: None, audit_key: None,
+        shutdown_timeout: 5, io_timeout: 5, notify_on_shutdown: false, remote_admin: Vec::new(), force_signup: false, vote_expiry: 0, lease: 0,
+        rotation_grace: 0, drop_quorum: None, report_queue: false, signup_approval: false, lookup_rate: None, mutation_rate: None, admin_rate: None,
+        max_auth_failures: 5, lockout: 60, auth_mode: config::AuthMode::Plaintext, session_ttl: 900, admin_accounts: Vec::new(),
+        tls_cert: None, tls_key: None, tls_admin_certs: Vec::new(), client_subnets: Vec::new(), auto_subnet: false
+    }
+}
+
+// Sends the request like it arrived through a listener with the role, from the address
+pub fn send(server: &mut server::Server, request: &str, peer_addr: &str, role: config::ListenerRole) -> String {
+    let mut reply: Vec<u8> = Vec::new();
+    server.serve(Ok(String::from(request)), &mut reply, &peer_addr.parse().unwrap(), role, false);
+    String::from_utf8(reply).unwrap()
+}
+
+
+#[test]
+fn listener_new_from_str() {
+    let listener = config::Listener::new_from_str("192.168.1.10:42000@client").unwrap();
+    assert_eq!(listener.address.to_string(), "192.168.1.10:42000");
+    assert!(listener.role == config::ListenerRole::Client);
+    assert!(listener.role.serves_clients() && !listener.role.serves_admins());
+
+    let listener = config::Listener::new_from_str("[::1]:42001@admin").unwrap();
+    assert_eq!(listener.address.to_string(), "[::1]:42001");
+    assert!(!listener.role.serves_clients() && listener.role.serves_admins());
+
+    let listener = config::Listener::new_from_str("fe80::1%2:42002").unwrap();
+    assert_eq!(listener.address.to_string(), "[fe80::1%2]:42002");
+    assert!(listener.role == config::ListenerRole::Both);
+
+    assert!(config::Listener::new_from_str("127.0.0.1:42000@nobody").is_none());
+    assert!(config::Listener::new_from_str("127.0.0.1").is_none());
+}
+
+#[test]
+fn drop_quorum_drop_votes() {
+    assert!(config::DropQuorum::new(0, 2).is_none());
+    assert!(config::DropQuorum::new(101, 2).is_none());
+    assert!(config::DropQuorum::new(10, 0).is_none());
+
+    let drop_quorum = config::DropQuorum::new(50, 2).unwrap();
+    assert_eq!(drop_quorum.drop_votes(0), 2);
+    assert_eq!(drop_quorum.drop_votes(3), 2);
+    assert_eq!(drop_quorum.drop_votes(5), 3);
+    assert_eq!(drop_quorum.drop_votes(2000), 1000);
+}
+
+#[test]
+fn subnets_of_listener() {
+    let interfaces: Vec<(std::net::IpAddr, u8)> = vec![
+        ("127.0.0.1".parse().unwrap(), 8),
+        ("192.168.1.70".parse().unwrap(), 24),
+        ("10.0.0.5".parse().unwrap(), 16),
+        ("fe80::1".parse().unwrap(), 64)
+    ];
+
+    let subnets = config::subnets_of(&"192.168.1.70".parse().unwrap(), &interfaces);
+    assert_eq!(subnets.len(), 1);
+    assert_eq!(subnets[0].to_string(), "192.168.1.0/24");
+
+    let subnets = config::subnets_of(&"0.0.0.0".parse().unwrap(), &interfaces);
+    assert_eq!(subnets.iter().map(|c| c.to_string()).collect::<Vec<String>>(), vec!["192.168.1.0/24", "10.0.0.0/16"]);
+
+    // An IPv6 socket also gets the IPv4 peers
+    assert_eq!(config::subnets_of(&"::".parse().unwrap(), &interfaces).len(), 3);
+    assert!(config::subnets_of(&"127.0.0.1".parse().unwrap(), &interfaces).is_empty());
+}
+
+#[test]
+fn listener_roles() {
+    let mut server = server::Server::from_start_config(&start_config());
+    let admin_request = "{\"user\":\"admin\",\"password\":\"adminkey\",\"method\":\"get\",\"how\":\"bans\"}";
+    let client_request = "{\"user\":\"client\",\"password\":\"clientpw\",\"method\":\"get\",\"how\":\"mac\",\"mac\":\"00:11:22:33:44:55\"}";
+
+    // A client address refuses the admins, even from the loopback
+    assert!(send(&mut server, admin_request, "127.0.0.1:4000", config::ListenerRole::Client).contains("RemoteAdminIsNotAllowed"));
+    assert!(send(&mut server, admin_request, "127.0.0.1:4000", config::ListenerRole::Admin).contains("\"bans\""));
+    assert!(send(&mut server, admin_request, "127.0.0.1:4000", config::ListenerRole::Both).contains("\"bans\""));
+    // The admin addresses only serve the --remote-admin networks besides the loopback
+    assert!(send(&mut server, admin_request, "192.168.1.10:4000", config::ListenerRole::Admin).contains("RemoteAdminIsNotAllowed"));
+
+    // An admin address refuses the clients
+    assert!(send(&mut server, client_request, "192.168.1.10:4000", config::ListenerRole::Admin).contains("ClientRequestNotAllowed"));
+    assert!(send(&mut server, client_request, "192.168.1.10:4000", config::ListenerRole::Client).contains("ClientDoesNotExist"));
+    assert!(send(&mut server, client_request, "192.168.1.10:4000", config::ListenerRole::Both).contains("ClientDoesNotExist"));
+}
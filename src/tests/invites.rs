@@ -0,0 +1,68 @@
+use crate::invites;
+use crate::requests;
+use crate::accounts;
+
+#[test]
+fn invite_list() {
+    let mut invite_list = invites::InviteList::new();
+    let (single, single_code) = invite_list.mint(1, None, "admin", 100).unwrap();
+    let (twice, twice_code) = invite_list.mint(2, Some(200), "alice", 100).unwrap();
+    assert_ne!(single_code, twice_code);
+    assert_eq!(invite_list.admits(&single_code, 150), Some(single));
+    assert_eq!(invite_list.admits("guess", 150), None);
+
+    // The uses run out
+    assert!(invite_list.redeem(single));
+    assert_eq!(invite_list.admits(&single_code, 150), None);
+    assert!(invite_list.redeem(twice));
+    assert_eq!(invite_list.admits(&twice_code, 150), Some(twice));
+    // And the expiry too
+    assert_eq!(invite_list.admits(&twice_code, 200), None);
+    assert_eq!(invite_list.get_by_code(&twice_code).unwrap().created_by, "alice");
+    assert_eq!(invite_list.get(twice).unwrap().max_uses, 2);
+
+    // The codes are never listed nor saved
+    let json = invite_list.to_json_string();
+    assert!(!json.contains(&single_code));
+    let restored = invites::InviteList::from_json_value(&serde_json::from_str::<serde_json::Value>(&json).unwrap()).unwrap();
+    assert_eq!(restored.get_by_code(&twice_code).unwrap().uses, 1);
+
+    assert!(invite_list.revoke(single).is_some());
+    assert!(invite_list.revoke(single).is_none());
+    assert!(invite_list.get_by_code(&single_code).is_none());
+    // The ids are not reused
+    let (third, _code) = invite_list.mint(1, None, "admin", 100).unwrap();
+    assert_eq!(third, twice + 1);
+}
+
+#[test]
+fn parse_invite_requests() {
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"invite\",\"action\":\"mint\",\"uses\":5,\"seconds\":3600}") {
+        Some(requests::Request::Admin(_, request @ requests::AdminRequest::MintInvite { uses: 5, seconds: Some(3600), .. })) => {
+            assert_eq!(request.required_role(), accounts::AdminRole::Operator);
+        },
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"invite\",\"action\":\"mint\"}") {
+        Some(requests::Request::Admin(_, requests::AdminRequest::MintInvite { uses: 1, seconds: None, .. })) => assert!(true),
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"invite\",\"action\":\"mint\",\"uses\":0}") {
+        None => assert!(true),
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"invite\",\"action\":\"revoke\",\"id\":3}") {
+        Some(requests::Request::Admin(_, requests::AdminRequest::RevokeInvite { id: 3, .. })) => assert!(true),
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"get\",\"how\":\"invites\"}") {
+        Some(requests::Request::Admin(_, request @ requests::AdminRequest::GetInvites { .. })) => {
+            assert_eq!(request.required_role(), accounts::AdminRole::ReadOnly);
+        },
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"client\",\"password\":\"pw\",\"method\":\"invite\",\"action\":\"mint\"}") {
+        None => assert!(true),
+        _ => assert!(false)
+    }
+}
@@ -0,0 +1,28 @@
+use crate::ipparser;
+
+#[test]
+fn cidr_contains() {
+    let lan = ipparser::Cidr::new_from_str("192.168.1.70/24").unwrap();
+    assert_eq!(lan.to_string(), "192.168.1.0/24");
+    assert!(lan.contains(&"192.168.1.1".parse().unwrap()));
+    assert!(lan.contains(&"192.168.1.255".parse().unwrap()));
+    assert!(!lan.contains(&"192.168.2.1".parse().unwrap()));
+    assert!(lan.contains(&"::ffff:192.168.1.20".parse().unwrap()));
+    assert!(!lan.contains(&"fe80::1".parse().unwrap()));
+
+    let link_local = ipparser::Cidr::new_from_str("fe80::/10").unwrap();
+    assert!(link_local.contains(&"fe80::1c2a:3bff:fe4d:5e6f".parse().unwrap()));
+    assert!(!link_local.contains(&"2001:db8::1".parse().unwrap()));
+    assert!(!link_local.contains(&"192.168.1.1".parse().unwrap()));
+
+    let host = ipparser::Cidr::new_from_str("10.0.0.7").unwrap();
+    assert!(host.contains(&"10.0.0.7".parse().unwrap()));
+    assert!(!host.contains(&"10.0.0.8".parse().unwrap()));
+
+    let everything = ipparser::Cidr::new_from_str("0.0.0.0/0").unwrap();
+    assert!(everything.contains(&"8.8.8.8".parse().unwrap()));
+
+    assert!(ipparser::Cidr::new_from_str("10.0.0.0/33").is_none());
+    assert!(ipparser::Cidr::new_from_str("10.0.0.0/8/8").is_none());
+    assert!(ipparser::Cidr::new_from_str("not-an-ip/8").is_none());
+}
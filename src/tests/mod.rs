@@ -1 +1,14 @@
 mod clients;
+mod config;
+mod ipparser;
+mod reports;
+mod ratelimit;
+mod auth;
+mod bans;
+mod secrets;
+mod tls;
+mod sessions;
+mod accounts;
+mod audit;
+mod invites;
+mod signups;
@@ -0,0 +1,31 @@
+use std::net;
+use std::time;
+use crate::config;
+use crate::ratelimit;
+
+#[test]
+fn rate_limiter_token_bucket() {
+    let limit = config::RateLimit::new_from_str("60/2").unwrap();
+    assert!(config::RateLimit::new_from_str("0").unwrap().is_none());
+    assert!(config::RateLimit::new_from_str("10/0").is_none());
+    let mut rate_limiter = ratelimit::RateLimiter::new(limit, limit, None);
+    let jorge: net::IpAddr = "192.168.1.50".parse().unwrap();
+    let gil: net::IpAddr = "192.168.1.51".parse().unwrap();
+    let now = time::Instant::now();
+
+    assert!(rate_limiter.check(&jorge, ratelimit::RequestCategory::Lookup, now).is_ok());
+    assert!(rate_limiter.check(&jorge, ratelimit::RequestCategory::Lookup, now).is_ok());
+    assert_eq!(rate_limiter.check(&jorge, ratelimit::RequestCategory::Lookup, now), Err(1));
+    // Every IP and every category has its own bucket
+    assert!(rate_limiter.check(&gil, ratelimit::RequestCategory::Lookup, now).is_ok());
+    assert!(rate_limiter.check(&jorge, ratelimit::RequestCategory::Mutation, now).is_ok());
+    // The admin requests are not limited
+    for _ in 0..10 {
+        assert!(rate_limiter.check(&jorge, ratelimit::RequestCategory::Admin, now).is_ok());
+    }
+    // 60 per minute is one token per second
+    assert!(rate_limiter.check(&jorge, ratelimit::RequestCategory::Lookup, now + time::Duration::from_secs(1)).is_ok());
+
+    assert_eq!(rate_limiter.counters(ratelimit::RequestCategory::Lookup).allowed, 4);
+    assert_eq!(rate_limiter.counters(ratelimit::RequestCategory::Lookup).limited, 1);
+}
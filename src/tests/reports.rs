@@ -0,0 +1,44 @@
+use crate::clients;
+use crate::ipparser;
+use crate::reports;
+
+#[test]
+fn reports_queue_approve_and_dismiss() {
+    let gil = clients::Client::new(ipparser::u32_to_ipv4(3232235827).into(), 9000, "gil_vazquez", false, 0).unwrap();
+    let mac_gil = ipparser::MacAddress::new_from_str("eeee.1234.fabc").unwrap();
+    let jorge = clients::DropVote { voter_ip: ipparser::u32_to_ipv4(3232235826).into(), voter_mac: None, cast_at: 0 };
+    let tania = clients::DropVote { voter_ip: ipparser::u32_to_ipv4(3232236000).into(), voter_mac: None, cast_at: 0 };
+    let mut reports_queue = reports::ReportsQueue::new();
+
+    let first_id = match reports_queue.report(&mac_gil, &gil, jorge.clone(), reports::ReportReason::Spam, "\"too many\" messages") {
+        reports::ReportResult::Reported { id } => id,
+        reports::ReportResult::AlreadyReported => panic!("the first report was ignored")
+    };
+    match reports_queue.report(&mac_gil, &gil, jorge, reports::ReportReason::Abuse, "") {
+        reports::ReportResult::AlreadyReported => assert!(true),
+        reports::ReportResult::Reported { id: _ } => assert!(false)
+    }
+    let second_id = match reports_queue.report(&mac_gil, &gil, tania, reports::ReportReason::Other, "") {
+        reports::ReportResult::Reported { id } => id,
+        reports::ReportResult::AlreadyReported => panic!("a different reporter was ignored")
+    };
+    assert_eq!(reports_queue.pending().len(), 2);
+
+    assert!(reports_queue.dismiss(second_id));
+    assert!(reports_queue.approve(first_id) == Some(mac_gil.clone()));
+    assert_eq!(reports_queue.pending().len(), 0);
+    assert_eq!(reports_queue.history(&mac_gil).len(), 2);
+    assert!(reports_queue.get(second_id).unwrap().status == reports::ReportStatus::Dismissed);
+
+    // A reviewed report can't be reviewed again
+    assert!(!reports_queue.dismiss(first_id));
+    assert!(reports_queue.approve(second_id).is_none());
+    assert!(reports_queue.get(first_id).unwrap().status == reports::ReportStatus::Approved);
+    assert!(reports_queue.get(second_id).unwrap().status == reports::ReportStatus::Dismissed);
+
+    // The history survives a snapshot
+    let json = serde_json::from_str::<serde_json::Value>(&reports_queue.to_json_string()).unwrap();
+    let restored = reports::ReportsQueue::from_json_value(&json).unwrap();
+    assert_eq!(restored.get(first_id).unwrap().text, "\"too many\" messages");
+    assert!(restored.get(99).is_none());
+}
@@ -0,0 +1,161 @@
+extern crate ring;
+
+use std::num;
+use ring::digest;
+use ring::hmac;
+use ring::pbkdf2;
+use crate::requests;
+use crate::secrets;
+
+#[test]
+fn secret_from_plaintext_and_hash() {
+    let secret = secrets::Secret::new_from_str("admin_secret").unwrap();
+    assert!(secret.verify("admin_secret"));
+    // The second time the quick digest is used
+    assert!(secret.verify("admin_secret"));
+    assert!(!secret.verify("admin_secreT"));
+    assert!(!secret.verify(""));
+
+    let hash_str = secret.to_hash_string();
+    assert!(hash_str.starts_with(secrets::HASH_PREFIX));
+    assert!(!hash_str.contains("admin_secret"));
+    let from_hash = secrets::Secret::new_from_str(&hash_str).unwrap();
+    assert!(from_hash.verify("admin_secret"));
+    assert!(!from_hash.verify(&hash_str));
+    assert_eq!(from_hash.to_hash_string(), hash_str);
+
+    // The same plaintext is salted differently every time
+    let other = secrets::Secret::new_from_str("admin_secret").unwrap();
+    assert_ne!(other.to_hash_string(), hash_str);
+}
+
+#[test]
+fn secret_is_redacted() {
+    let secret = secrets::Secret::new_from_str("secret").unwrap();
+    assert_eq!(secret.to_string(), "[redacted]");
+    assert_eq!(format!("{:?}", secret), "Secret([redacted])");
+}
+
+#[test]
+fn malformed_hashes() {
+    assert!(secrets::Secret::from_hash_str("scram-sha256$0$00$00").is_none());
+    assert!(secrets::Secret::from_hash_str("scram-sha256$1000$0011$abc").is_none());
+    assert!(secrets::Secret::from_hash_str("scram-sha256$1000$$").is_none());
+    assert!(secrets::Secret::new_from_str("scram-sha256$nope").is_none());
+    assert!(!secrets::is_valid_secret("scram-sha256$nope"));
+    assert!(secrets::is_valid_secret("secret"));
+    assert!(!secrets::is_valid_secret("a-plaintext-secret-that-is-too-long"));
+    assert!(!secrets::is_valid_secret("contraseña"));
+
+    assert_eq!(secrets::to_hex(&[0, 15, 255]), "000fff");
+    assert_eq!(secrets::from_hex("000fff"), Some(vec![0, 15, 255]));
+    assert_eq!(secrets::from_hex("0f0"), None);
+    assert_eq!(secrets::from_hex("zz"), None);
+}
+
+#[test]
+fn signed_requests() {
+    let secret = secrets::Secret::new_from_str("secret").unwrap();
+    let body = "{\"user\":\"client\",\"method\":\"get\",\"how\":\"mac\",\"mac\":\"00:11:22:33:44:55\"}";
+    let nonce = "000102030405060708090a0b0c0d0e0f";
+    let message = requests::Envelope::signed_message(nonce, body);
+
+    // What a client does with the salt and the iterations of the challenge
+    let salt = secrets::from_hex(&secret.salt_to_string()).unwrap();
+    let mut salted = [0u8; 32];
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, num::NonZeroU32::new(secret.iterations()).unwrap(), &salt, b"secret", &mut salted);
+    let client_key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &salted), b"Client Key");
+    let stored_key = digest::digest(&digest::SHA256, client_key.as_ref());
+    let prove = |key: &[u8], message: &[u8]| -> Vec<u8> {
+        let signature = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, stored_key.as_ref()), message);
+        key.iter().zip(signature.as_ref().iter()).map(|(k, s)| k ^ s).collect()
+    };
+    let proof = prove(client_key.as_ref(), &message);
+    assert!(secret.verify_hmac(&message, &proof));
+
+    let tampered = requests::Envelope::signed_message(nonce, &body.replace("55", "56"));
+    assert!(!secret.verify_hmac(&tampered, &proof));
+    assert!(!secret.verify_hmac(&message, &proof[..31]));
+    // The stored key is in the hash string, but it can't sign the requests
+    assert!(secret.to_hash_string().ends_with(&secrets::to_hex(stored_key.as_ref())));
+    assert!(!secret.verify_hmac(&message, &prove(stored_key.as_ref(), &message)));
+    assert!(!secret.verify_hmac(&message, &prove(&salted, &message)));
+
+    let envelope = format!("{{\"nonce\":\"{}\",\"proof\":\"{}\",\"request\":{}}}", nonce, secrets::to_hex(&proof), serde_json::Value::String(String::from(body)));
+    match requests::Envelope::from(&envelope) {
+        Some(requests::Envelope::Signed { nonce: n, body: b, .. }) => assert!(n == nonce && b == body),
+        _ => assert!(false)
+    }
+    match requests::Envelope::from("{\"user\":\"admin\",\"method\":\"challenge\"}") {
+        Some(requests::Envelope::Challenge { kind: crate::auth::SecretKind::AdminKey, .. }) => assert!(true),
+        _ => assert!(false)
+    }
+    assert!(requests::Envelope::from("{\"user\":\"client\",\"password\":\"secret\",\"method\":\"get\",\"how\":\"mac\",\"mac\":\"00:11:22:33:44:55\"}").is_none());
+}
+
+// What a client signs with the salt and the iterations it was given for the secret
+fn proof_of(plaintext: &str, secret: &secrets::Secret, message: &[u8]) -> Vec<u8> {
+    let salt = secrets::from_hex(&secret.salt_to_string()).unwrap();
+    let mut salted = [0u8; 32];
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, num::NonZeroU32::new(secret.iterations()).unwrap(), &salt, plaintext.as_bytes(), &mut salted);
+    let client_key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &salted), b"Client Key");
+    let stored_key = digest::digest(&digest::SHA256, client_key.as_ref());
+    let signature = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, stored_key.as_ref()), message);
+    client_key.as_ref().iter().zip(signature.as_ref().iter()).map(|(k, s)| k ^ s).collect()
+}
+
+#[test]
+fn secret_rotation_grace() {
+    let admin = crate::accounts::ActingAdmin { name: String::from("admin"), role: crate::accounts::AdminRole::Superuser, peer_addr: "127.0.0.1:4000".parse().unwrap(), session: None };
+    let mut password = secrets::Secret::new_from_str("old_password").unwrap();
+    let mut old_password = None;
+
+    // With a grace the old password is still accepted for a while
+    let reply = serde_json::from_str::<serde_json::Value>(&crate::replies::reply_admin_setpassword("new_password", 60, &mut password, &mut old_password, &admin)).unwrap();
+    let until = reply["old_accepted_until"].as_u64().unwrap();
+    assert!(password.verify("new_password"));
+    let retired = old_password.as_ref().unwrap();
+    assert_eq!(retired.until, until);
+    assert!(retired.secret.verify("old_password"));
+    assert!(retired.is_active(until - 1));
+    assert!(!retired.is_active(until));
+
+    // The new password has its own salt, the old one is checked with the salt it was hashed with
+    assert_ne!(password.salt_to_string(), retired.secret.salt_to_string());
+    assert!(secrets::verify_rotated(&password, Some(retired), until - 1, "new_password") == secrets::RotationMatch::Current);
+    assert!(secrets::verify_rotated(&password, Some(retired), until - 1, "old_password") == secrets::RotationMatch::Retired);
+    assert!(secrets::verify_rotated(&password, Some(retired), until, "old_password") == secrets::RotationMatch::Neither);
+    assert!(secrets::verify_rotated(&password, Some(retired), until - 1, "wrong_password") == secrets::RotationMatch::Neither);
+    assert!(secrets::verify_rotated(&password, Some(retired), until - 1, "a-candidate-that-is-too-long-to-be-a-secret") == secrets::RotationMatch::Neither);
+
+    // The signed requests verify with the old password while the grace lasts
+    let message = b"nonce.body";
+    let proof = proof_of("old_password", &retired.secret, message);
+    assert!(secrets::verify_hmac_rotated(&password, Some(retired), until - 1, message, &proof) == secrets::RotationMatch::Retired);
+    assert!(secrets::verify_hmac_rotated(&password, Some(retired), until, message, &proof) == secrets::RotationMatch::Neither);
+    let proof = proof_of("new_password", &password, message);
+    assert!(secrets::verify_hmac_rotated(&password, Some(retired), until - 1, message, &proof) == secrets::RotationMatch::Current);
+
+    // A new password given as a hash keeps the grace too
+    let hashed = secrets::Secret::new_from_str("hashed_password").unwrap().to_hash_string();
+    let reply = crate::replies::reply_admin_setpassword(&hashed, 60, &mut password, &mut old_password, &admin);
+    assert!(reply.contains("old_accepted_until"));
+    assert!(password.verify("hashed_password"));
+    let retired = old_password.as_ref().unwrap();
+    assert!(secrets::verify_rotated(&password, Some(retired), retired.until - 1, "new_password") == secrets::RotationMatch::Retired);
+
+    // Without a grace the old password stops working at once
+    let reply = crate::replies::reply_admin_setpassword("newer_password", 0, &mut password, &mut old_password, &admin);
+    assert!(!reply.contains("old_accepted_until"));
+    assert!(old_password.is_none());
+    assert!(secrets::RetiredSecret::new(password.clone(), 100, 0).is_none());
+
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"set\",\"what\":\"password\",\"new_password\":\"pw\",\"grace\":300}") {
+        Some(requests::Request::Admin(_, requests::AdminRequest::SetPassword { grace: Some(300), .. })) => assert!(true),
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"set\",\"what\":\"key\",\"key\":\"new_key\"}") {
+        Some(requests::Request::Admin(_, requests::AdminRequest::SetKey { grace: None, .. })) => assert!(true),
+        _ => assert!(false)
+    }
+}
@@ -0,0 +1,49 @@
+use std::net;
+use crate::sessions;
+use crate::accounts;
+
+#[test]
+fn login_validate_and_expire() {
+    let mut sessions = sessions::SessionStore::new(60);
+    let ip: net::IpAddr = "127.0.0.1".parse().unwrap();
+    let other_ip: net::IpAddr = "192.168.1.50".parse().unwrap();
+
+    let (id, token) = sessions.login(&ip, accounts::BUILTIN_ADMIN, 1000).unwrap();
+    assert_eq!(token.len(), 64);
+    assert_eq!(sessions.validate(&token, &ip, 1000), Some((id, String::from(accounts::BUILTIN_ADMIN))));
+    assert_eq!(sessions.validate(&token, &ip, 1059), Some((id, String::from(accounts::BUILTIN_ADMIN))));
+    // Only from the IP that logged in
+    assert_eq!(sessions.validate(&token, &other_ip, 1000), None);
+    assert_eq!(sessions.validate("admin_secret", &ip, 1000), None);
+    // The token is never listed
+    assert!(!sessions.to_json_string().contains(&token));
+    assert_eq!(sessions.to_json_string(), format!("[{{\"id\":{},\"ip_addr\":\"127.0.0.1\",\"account\":\"admin\",\"created_at\":1000,\"expires_at\":1060}}]", id));
+
+    assert_eq!(sessions.validate(&token, &ip, 1060), None);
+    assert!(sessions.is_empty());
+}
+
+#[test]
+fn revoke_sessions() {
+    let mut sessions = sessions::SessionStore::new(60);
+    let ip: net::IpAddr = "127.0.0.1".parse().unwrap();
+    let (first_id, first_token) = sessions.login(&ip, accounts::BUILTIN_ADMIN, 1000).unwrap();
+    let (second_id, second_token) = sessions.login(&ip, accounts::BUILTIN_ADMIN, 1000).unwrap();
+    assert_ne!(first_id, second_id);
+    assert_ne!(first_token, second_token);
+
+    assert!(sessions.revoke(first_id));
+    assert!(!sessions.revoke(first_id));
+    assert_eq!(sessions.validate(&first_token, &ip, 1000), None);
+    assert_eq!(sessions.validate(&second_token, &ip, 1000), Some((second_id, String::from(accounts::BUILTIN_ADMIN))));
+
+    let (_, alice_token) = sessions.login(&ip, "alice", 1000).unwrap();
+    assert_eq!(sessions.validate(&alice_token, &ip, 1000), Some((3, String::from("alice"))));
+    // Removing an account logs out only its sessions
+    assert_eq!(sessions.revoke_account("alice"), 1);
+    assert_eq!(sessions.validate(&alice_token, &ip, 1000), None);
+
+    sessions.login(&ip, accounts::BUILTIN_ADMIN, 1000).unwrap();
+    assert_eq!(sessions.revoke_all(), 2);
+    assert_eq!(sessions.validate(&second_token, &ip, 1000), None);
+}
@@ -0,0 +1,151 @@
+use crate::signups;
+use crate::clients;
+use crate::ipparser;
+use crate::bans;
+use crate::accounts;
+use crate::replies;
+use crate::requests;
+use crate::reports;
+
+fn operator() -> accounts::ActingAdmin {
+    accounts::ActingAdmin { name: String::from("alice"), role: accounts::AdminRole::Operator, peer_addr: "127.0.0.1:4000".parse().unwrap(), session: None }
+}
+
+#[test]
+fn signup_approval() {
+    let mut clients_map = clients::ClientsMap::new();
+    let mut signup_queue = signups::SignupQueue::new();
+    let ban_list = bans::BanList::new();
+    let mac = ipparser::MacAddress::new_from_str("00:11:22:33:44:55").unwrap();
+    let addr: std::net::SocketAddr = "192.168.1.10:4000".parse().unwrap();
+
+    // The new client waits and nobody can look it up
+    let reply = serde_json::from_str::<serde_json::Value>(&replies::reply_client_signup(&mut clients_map, Some(&mut signup_queue), "jorge", &mac, &addr, 4000, false, 10, None, 60)).unwrap();
+    let id = reply["signup"].as_u64().unwrap();
+    let token = String::from(reply["owner_token"].as_str().unwrap());
+    assert!(!clients_map.exists_by_mac(&mac));
+    assert!(replies::reply_client_signupstatus(&mac, &clients_map, &signup_queue, &addr).contains("pending"));
+    // Nobody else can replace the sign up while it waits
+    let reply = replies::reply_client_signup(&mut clients_map, Some(&mut signup_queue), "mallory", &mac, &addr, 4000, false, 10, None, 60);
+    assert!(reply.contains("WrongOwnerToken"));
+    assert_eq!(signup_queue.pending().len(), 1);
+
+    assert!(replies::reply_admin_approvesignup(id, &mut signup_queue, &mut clients_map, &ban_list, 10, 60, &operator()).contains("approved"));
+    assert!(replies::reply_admin_approvesignup(id, &mut signup_queue, &mut clients_map, &ban_list, 10, 60, &operator()).contains("SignupDoesNotExist"));
+    assert!(clients_map.get_by_mac(&mac).unwrap().owner.unwrap().is_owned_by(&token));
+    assert!(replies::reply_client_signupstatus(&mac, &clients_map, &signup_queue, &addr).contains("approved"));
+    assert!(signup_queue.is_empty());
+
+    // The approved MACs sign up again without waiting
+    let reply = replies::reply_client_signup(&mut clients_map, Some(&mut signup_queue), "jorge_a", &mac, &addr, 4001, false, 10, Some(&token), 60);
+    assert!(reply.contains("updated"));
+    assert!(signup_queue.is_empty());
+    // Even after they sign out
+    assert!(replies::reply_client_signout(&mut clients_map, Some(&mac), Some(&token), &addr).contains("result"));
+    assert!(!clients_map.exists_by_mac(&mac));
+    assert!(replies::reply_client_signupstatus(&mac, &clients_map, &signup_queue, &addr).contains("approved"));
+    let reply = replies::reply_client_signup(&mut clients_map, Some(&mut signup_queue), "jorge", &mac, &addr, 4000, false, 10, None, 60);
+    assert!(reply.contains("You have been registered"));
+    assert!(signup_queue.is_empty());
+
+    // The rejected ones are remembered until they sign up again
+    let other_mac = ipparser::MacAddress::new_from_str("00:11:22:33:44:66").unwrap();
+    let other_addr: std::net::SocketAddr = "192.168.1.20:4000".parse().unwrap();
+    let reply = serde_json::from_str::<serde_json::Value>(&replies::reply_client_signup(&mut clients_map, Some(&mut signup_queue), "other", &other_mac, &other_addr, 4000, false, 10, None, 60)).unwrap();
+    let other_id = reply["signup"].as_u64().unwrap();
+    assert!(replies::reply_admin_rejectsignup(other_id, &mut signup_queue, &operator()).contains("rejected"));
+    assert!(replies::reply_admin_rejectsignup(other_id, &mut signup_queue, &operator()).contains("SignupDoesNotExist"));
+    assert!(replies::reply_client_signupstatus(&other_mac, &clients_map, &signup_queue, &other_addr).contains("rejected"));
+    assert!(signup_queue.pending().is_empty());
+    assert!(!clients_map.exists_by_mac(&other_mac));
+
+    // The queue survives the snapshots
+    let json = serde_json::from_str::<serde_json::Value>(&signup_queue.to_json_string()).unwrap();
+    let mut restored = signups::SignupQueue::from_json_value(&json).unwrap();
+    assert!(restored.restore_approved(&serde_json::from_str::<serde_json::Value>(&signup_queue.approved_to_json_string()).unwrap()).is_some());
+    assert!(restored.is_approved(&mac));
+    assert!(!restored.is_approved(&other_mac));
+    assert_eq!(restored.get_by_mac(&other_mac).unwrap().status, signups::SignupStatus::Rejected);
+    assert_eq!(restored.get_by_mac(&other_mac).unwrap().client.username, "other");
+}
+
+// Approves the sign up of the MAC and signs it up into the directory
+fn approve(clients_map: &mut clients::ClientsMap, signup_queue: &mut signups::SignupQueue, mac: &ipparser::MacAddress, addr: &std::net::SocketAddr) {
+    let reply = serde_json::from_str::<serde_json::Value>(&replies::reply_client_signup(clients_map, Some(signup_queue), "jorge", mac, addr, 4000, false, 10, None, 0)).unwrap();
+    let id = reply["signup"].as_u64().unwrap();
+    assert!(replies::reply_admin_approvesignup(id, signup_queue, clients_map, &bans::BanList::new(), 10, 0, &operator()).contains("approved"));
+    assert!(signup_queue.is_approved(mac));
+}
+
+#[test]
+fn admin_revokes_signup_approval() {
+    let mut clients_map = clients::ClientsMap::new();
+    let mut signup_queue = signups::SignupQueue::new();
+    let mut ban_list = bans::BanList::new();
+    let mut reports_queue = reports::ReportsQueue::new();
+    let mac = ipparser::MacAddress::new_from_str("00:11:22:33:44:55").unwrap();
+    let addr: std::net::SocketAddr = "192.168.1.10:4000".parse().unwrap();
+
+    // Dropped out by an admin, the MAC waits in the queue again
+    approve(&mut clients_map, &mut signup_queue, &mac, &addr);
+    assert!(replies::reply_admin_drop(&addr.ip(), &mut clients_map, &mut signup_queue, &operator()).contains("Client was dropped out"));
+    assert!(!signup_queue.is_approved(&mac));
+    let reply = replies::reply_client_signup(&mut clients_map, Some(&mut signup_queue), "jorge", &mac, &addr, 4000, false, 10, None, 0);
+    assert!(reply.contains("signup"));
+    assert!(!clients_map.exists_by_mac(&mac));
+    assert_eq!(signup_queue.pending().len(), 1);
+
+    // An approved report revokes it too
+    let id = signup_queue.pending()[0].id;
+    assert!(replies::reply_admin_approvesignup(id, &mut signup_queue, &mut clients_map, &ban_list, 10, 0, &operator()).contains("approved"));
+    let client = clients_map.get_by_mac(&mac).unwrap().clone();
+    let reporter = clients::DropVote { voter_ip: "192.168.1.20".parse().unwrap(), voter_mac: None, cast_at: 0 };
+    let report_id = match reports_queue.report(&mac, &client, reporter, reports::ReportReason::Spam, "") {
+        reports::ReportResult::Reported { id } => id,
+        reports::ReportResult::AlreadyReported => panic!("the report was ignored")
+    };
+    assert!(replies::reply_admin_approvereport(report_id, &mut reports_queue, &mut clients_map, &mut signup_queue, &operator()).contains("dropped out"));
+    assert!(!signup_queue.is_approved(&mac));
+
+    // And a ban of the MAC, even if it wasn't signed up
+    approve(&mut clients_map, &mut signup_queue, &mac, &addr);
+    assert!(replies::reply_client_signout(&mut clients_map, Some(&mac), None, &addr).contains("result"));
+    assert!(signup_queue.is_approved(&mac));
+    assert!(replies::reply_admin_ban(bans::BanTarget::Mac(mac.clone()), None, "", &mut ban_list, &mut clients_map, &mut signup_queue, &operator()).contains("Banned"));
+    assert!(!signup_queue.is_approved(&mac));
+
+    // A ban of the network drops out the client and revokes its MAC
+    let other_mac = ipparser::MacAddress::new_from_str("00:11:22:33:44:66").unwrap();
+    approve(&mut clients_map, &mut signup_queue, &other_mac, &addr);
+    let target = bans::BanTarget::Network(ipparser::Cidr::new("192.168.1.0".parse().unwrap(), 24).unwrap());
+    assert!(replies::reply_admin_ban(target, None, "", &mut ban_list, &mut clients_map, &mut signup_queue, &operator()).contains("Banned"));
+    assert!(!clients_map.exists_by_mac(&other_mac));
+    assert!(!signup_queue.is_approved(&other_mac));
+    assert!(!signup_queue.revoke(&other_mac));
+}
+
+#[test]
+fn parse_signup_requests() {
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"signup\",\"action\":\"approve\",\"id\":2}") {
+        Some(requests::Request::Admin(_, request @ requests::AdminRequest::ApproveSignup { id: 2, .. })) => {
+            assert_eq!(request.required_role(), accounts::AdminRole::Operator);
+        },
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"signup\",\"action\":\"reject\",\"id\":2}") {
+        Some(requests::Request::Admin(_, requests::AdminRequest::RejectSignup { id: 2, .. })) => assert!(true),
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"admin\",\"password\":\"key\",\"method\":\"get\",\"how\":\"signups\"}") {
+        Some(requests::Request::Admin(_, requests::AdminRequest::GetSignups { .. })) => assert!(true),
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"client\",\"password\":\"pw\",\"method\":\"sign_up_status\",\"mac\":\"00:11:22:33:44:55\"}") {
+        Some(requests::Request::Client(requests::ClientRequest::SignUpStatus { .. })) => assert!(true),
+        _ => assert!(false)
+    }
+    match requests::Request::from("{\"user\":\"client\",\"password\":\"pw\",\"method\":\"sign_up_status\"}") {
+        None => assert!(true),
+        _ => assert!(false)
+    }
+}
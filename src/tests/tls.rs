@@ -0,0 +1,39 @@
+extern crate rustls;
+
+use rustls::pki_types::UnixTime;
+use rustls::server::danger::ClientCertVerifier;
+use crate::config;
+use crate::tls;
+
+#[test]
+fn parse_fingerprints() {
+    let with_colons = "28:7C:4D:48:F7:FD:E2:C2:C9:59:68:0D:E5:5C:52:93:E5:88:0B:A7:CC:3C:1A:A8:BE:3A:7F:AB:BA:81:09:27";
+    let without_colons = "287c4d48f7fde2c2c959680de55c5293e5880ba7cc3c1aa8be3a7fabba810927";
+    assert_eq!(tls::parse_fingerprint(with_colons), tls::parse_fingerprint(without_colons));
+    assert_eq!(tls::parse_fingerprint(with_colons).unwrap().len(), 32);
+    assert!(tls::parse_fingerprint("28:7C:4D").is_none());
+    assert!(tls::parse_fingerprint("not a fingerprint").is_none());
+}
+
+#[test]
+fn self_signed_certificate() {
+    let mut listener = config::Listener::new_from_str("192.168.1.10:42443@admin").unwrap();
+    listener.tls = true;
+    assert_eq!(listener.to_string(), "192.168.1.10:42443 (admin, tls)");
+    let listeners = vec![config::Listener::new_from_str("127.0.0.1:42000").unwrap(), listener];
+
+    let (certs, key) = tls::generate_self_signed(&listeners).unwrap();
+    let cert = certs[0].clone();
+    let fingerprint = tls::fingerprint(cert.as_ref());
+    assert_eq!(fingerprint.len(), 32 * 3 - 1);
+    let pinned = tls::parse_fingerprint(&fingerprint).unwrap();
+    assert!(tls::server_config(certs.clone(), key.clone_key(), &[]).is_ok());
+    assert!(tls::server_config(certs, key, std::slice::from_ref(&pinned)).is_ok());
+
+    // Only the pinned certificates are accepted
+    let verifier = tls::PinnedAdminCerts::new(vec![pinned]);
+    assert!(!verifier.client_auth_mandatory());
+    assert!(verifier.verify_client_cert(&cert, &[], UnixTime::now()).is_ok());
+    let (other_certs, _other_key) = tls::generate_self_signed(&[]).unwrap();
+    assert!(verifier.verify_client_cert(&other_certs[0], &[], UnixTime::now()).is_err());
+}
@@ -0,0 +1,124 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module sets up the TLS listeners, their certificate comes from the config or is generated
+// at startup, and the client certificates pinned by the admins stand in for the admin key.
+
+extern crate rustls;
+extern crate rcgen;
+extern crate ring;
+
+use std::sync::Arc;
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, UnixTime};
+use rustls::pki_types::pem::PemObject;
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{DigitallySignedStruct, DistinguishedName, SignatureScheme};
+use ring::digest;
+use crate::config;
+use crate::secrets;
+
+// The SHA-256 of the DER certificate, like `openssl x509 -fingerprint -sha256` prints it
+pub fn fingerprint(cert: &[u8]) -> String {
+    let digest = digest::digest(&digest::SHA256, cert);
+    digest.as_ref().iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(":")
+}
+
+// The fingerprints can be written with or without colons, in any case
+pub fn parse_fingerprint(fingerprint: &str) -> Option<Vec<u8>> {
+    let fingerprint = fingerprint.replace(':', "").to_lowercase();
+    let fingerprint = secrets::from_hex(&fingerprint)?;
+    if fingerprint.len() != digest::SHA256_OUTPUT_LEN {
+        return None;
+    }
+    Some(fingerprint)
+}
+
+pub fn load_certificate(cert_path: &str, key_path: &str) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), String> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<CertificateDer<'static>>, _>>())
+        .map_err(|e| format!("I couldn't read the certificates of {}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("There are no certificates in {}", cert_path));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| format!("I couldn't read the private key of {}: {}", key_path, e))?;
+    Ok((certs, key))
+}
+
+// The certificate is valid for localhost and for the addresses of the TLS listeners
+pub fn generate_self_signed(listeners: &[config::Listener]) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), String> {
+    let mut names = vec![String::from("localhost")];
+    for listener in listeners.iter().filter(|l| l.tls && !l.address.ip().is_unspecified()) {
+        let name = listener.address.ip().to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let certified = rcgen::generate_simple_self_signed(names).map_err(|e| format!("I couldn't generate a self-signed certificate: {}", e))?;
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    Ok((vec![certified.cert.der().clone()], key))
+}
+
+pub fn server_config(certs: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>, admin_certs: &[Vec<u8>]) -> Result<Arc<rustls::ServerConfig>, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("I couldn't set up TLS: {}", e))?;
+    let builder = if admin_certs.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        builder.with_client_cert_verifier(Arc::new(PinnedAdminCerts::new(admin_certs.to_vec())))
+    };
+    let config = builder.with_single_cert(certs, key).map_err(|e| format!("I couldn't use the TLS certificate: {}", e))?;
+    Ok(Arc::new(config))
+}
+
+// Only the pinned certificates get through the handshake, so a peer with a certificate is an admin
+pub fn has_admin_certificate(conn: &rustls::ServerConnection) -> bool {
+    conn.peer_certificates().map(|certs| !certs.is_empty()).unwrap_or(false)
+}
+
+// The client certificates are optional, but the ones presented must be pinned by --tls-admin-cert.
+// The handshake still proves that the client has the private key of the certificate
+#[derive(Debug)]
+pub struct PinnedAdminCerts {
+    fingerprints: Vec<Vec<u8>>,
+    algorithms: WebPkiSupportedAlgorithms
+}
+
+impl PinnedAdminCerts {
+    pub fn new(fingerprints: Vec<Vec<u8>>) -> PinnedAdminCerts {
+        PinnedAdminCerts { fingerprints, algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms }
+    }
+}
+
+impl ClientCertVerifier for PinnedAdminCerts {
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(&self, end_entity: &CertificateDer<'_>, _intermediates: &[CertificateDer<'_>], _now: UnixTime) -> Result<ClientCertVerified, rustls::Error> {
+        let presented = digest::digest(&digest::SHA256, end_entity.as_ref());
+        if self.fingerprints.iter().any(|f| f.as_slice() == presented.as_ref()) {
+            return Ok(ClientCertVerified::assertion());
+        }
+        log::warn!("A client presented the certificate {}, but it isn't pinned by --tls-admin-cert", fingerprint(end_entity.as_ref()));
+        Err(rustls::Error::InvalidCertificate(rustls::CertificateError::ApplicationVerificationFailure))
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}